@@ -21,7 +21,11 @@ type LspDecorationType =
   | "move"
   | "call"
   | "shared_mut"
-  | "outlive";
+  | "outlive"
+  | "lifetime_param"
+  | "dyn_shared_borrow"
+  | "dyn_mut_borrow"
+  | "dyn_borrow_conflict";
 
 interface LspDecoration {
   readonly type: LspDecorationType;
@@ -32,10 +36,22 @@ interface LspDecoration {
 
 interface LspCursorResponse {
   readonly is_analyzed: boolean;
-  readonly status: "analyzing" | "finished" | "error";
+  readonly status: "analyzing" | "finished" | "error" | "too_large";
   readonly decorations: readonly LspDecoration[];
 }
 
+interface LspStatusResponse {
+  readonly status: LspCursorResponse["status"];
+  readonly detail: string | null;
+}
+
+interface LspAnalysisUpdate {
+  readonly event: "started" | "crate_checked" | "files_analyzed" | "finished" | "failed";
+  readonly package?: string;
+  readonly detail?: string;
+  readonly uris?: readonly string[];
+}
+
 export let client: LanguageClient | undefined = undefined;
 
 let decoTimer: NodeJS.Timeout | null = null;
@@ -96,10 +112,13 @@ const categorizeDecoration = (
 ): "lifetime" | "immut" | "mut" | "moveCall" | "sharedMut" | "outlive" => {
   switch (deco.type) {
     case "lifetime":
+    case "lifetime_param":
       return "lifetime";
     case "imm_borrow":
+    case "dyn_shared_borrow":
       return "immut";
     case "mut_borrow":
+    case "dyn_mut_borrow":
       return "mut";
     case "call":
     case "move":
@@ -107,6 +126,7 @@ const categorizeDecoration = (
     case "shared_mut":
       return "sharedMut";
     case "outlive":
+    case "dyn_borrow_conflict":
       return "outlive";
   }
 };
@@ -199,9 +219,18 @@ class StatusBarManager {
         this.statusBar.text = "$(loading~spin) FerrousOwl";
         this.statusBar.tooltip = "Analyzing...";
         break;
+      case "too_large":
+        this.statusBar.text = "$(warning) FerrousOwl";
+        this.statusBar.tooltip = "Function too large to analyze";
+        break;
       default:
         this.statusBar.text = "$(error) FerrousOwl";
         this.statusBar.tooltip = "Analysis failed";
+        void sendStatusRequest().then((resp) => {
+          if (resp?.status === "error" && resp.detail) {
+            this.statusBar.tooltip = `Analysis failed: ${resp.detail}`;
+          }
+        });
     }
     this.statusBar.show();
   }
@@ -388,6 +417,13 @@ const sendCursorRequest = async (
   return isLspCursorResponse(resp) ? resp : null;
 };
 
+const sendStatusRequest = async (): Promise<LspStatusResponse | null> => {
+  const resp = await client?.sendRequest("ferrous-owl/status", {});
+  return typeof resp === "object" && resp !== null && "status" in resp
+    ? (resp as LspStatusResponse)
+    : null;
+};
+
 const createClientWithOptions = (
   command: string,
   clientOptions: LanguageClientOptions,
@@ -504,6 +540,46 @@ const registerCommands = (
   });
 };
 
+const registerAnalysisNotifications = (
+  context: vscode.ExtensionContext,
+  activeEditorRef: { current: vscode.TextEditor | undefined },
+  decorationManager: DecorationManager,
+  statusBarManager: StatusBarManager,
+): void => {
+  const disposable = client?.onNotification("ferrous-owl/analysis", (update: LspAnalysisUpdate) => {
+    switch (update.event) {
+      case "started":
+        statusBarManager.updateFromLspStatus("analyzing");
+        break;
+      case "finished":
+        statusBarManager.updateFromLspStatus("finished");
+        break;
+      case "failed":
+        statusBarManager.updateFromLspStatus("error");
+        break;
+      default:
+        break;
+    }
+
+    // redraw the decorations of the active editor once its results arrive
+    const editor = activeEditorRef.current;
+    if (
+      editor &&
+      getDisplayMode() === "selected" &&
+      update.uris?.includes(editor.document.uri.toString())
+    ) {
+      void sendCursorRequest(editor.selection.active, editor.document.uri).then((data) => {
+        if (data) {
+          decorationManager.update(editor, data);
+        }
+      });
+    }
+  });
+  if (disposable) {
+    context.subscriptions.push(disposable);
+  }
+};
+
 const registerEventHandlers = (
   context: vscode.ExtensionContext,
   activeEditorRef: { current: vscode.TextEditor | undefined },
@@ -597,9 +673,13 @@ export function activate(context: vscode.ExtensionContext): void {
   context.subscriptions.push({ dispose: () => decorationManager.dispose() });
   context.subscriptions.push({ dispose: () => statusBarManager.dispose() });
 
-  void initializeClient(context, clientOptions, statusBarManager).catch((e: unknown) => {
-    void vscode.window.showErrorMessage(`Failed to start FerrousOwl\n${String(e)}`);
-  });
+  void initializeClient(context, clientOptions, statusBarManager)
+    .then(() => {
+      registerAnalysisNotifications(context, activeEditorRef, decorationManager, statusBarManager);
+    })
+    .catch((e: unknown) => {
+      void vscode.window.showErrorMessage(`Failed to start FerrousOwl\n${String(e)}`);
+    });
 
   registerCommands(context, activeEditorRef, decorationManager, statusBarManager, clientOptions);
   registerEventHandlers(context, activeEditorRef, decorationManager, statusBarManager);
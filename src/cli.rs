@@ -1,10 +1,14 @@
-use std::{env, path::PathBuf, process::exit};
+use std::{env, fs, path::PathBuf, process::exit};
 
 use clap::{ArgAction, Args, Parser, Subcommand, ValueHint};
 use tokio::{fs::remove_dir_all, io};
 use tower_lsp::{LspService, Server};
 
-use crate::lsp_server::Backend;
+use crate::{
+    graph_export::{self, GraphFormat},
+    lsp_server::Backend,
+    lsp_settings,
+};
 
 #[derive(Debug, Parser)]
 #[command(author)]
@@ -28,6 +32,13 @@ pub enum Commands {
 
     /// Remove artifacts from the target directory.
     Clean,
+
+    /// Export the origin subset graph of a function.
+    OriginGraph(GraphExport),
+
+    /// Export the control-flow graph of a function annotated with ownership
+    /// facts.
+    Cfg(GraphExport),
 }
 
 #[derive(Args, Debug)]
@@ -55,6 +66,138 @@ pub struct Check {
         help = "Run the check for all features instead of the current active ones only"
     )]
     pub all_features: bool,
+
+    /// Packages to check instead of the whole workspace.
+    #[arg(short, long("package"), value_name("SPEC"))]
+    pub packages: Vec<String>,
+
+    /// Packages, or paths relative to the workspace root, to exclude.
+    #[arg(long, value_name("SPEC"))]
+    pub exclude: Vec<String>,
+
+    /// Features to activate, separated by commas.
+    #[arg(short('F'), long, value_name("FEATURES"), value_delimiter(','))]
+    pub features: Vec<String>,
+
+    /// Do not activate the `default` feature.
+    #[arg(long)]
+    pub no_default_features: bool,
+
+    /// Check only the library.
+    #[arg(long)]
+    pub lib: bool,
+
+    /// Check all binaries.
+    #[arg(long)]
+    pub bins: bool,
+
+    /// Check all tests.
+    #[arg(long)]
+    pub tests: bool,
+
+    /// Check all examples.
+    #[arg(long)]
+    pub examples: bool,
+
+    /// Check all benchmarks.
+    #[arg(long)]
+    pub benches: bool,
+
+    /// Check with the given profile.
+    #[arg(long, value_name("PROFILE-NAME"))]
+    pub profile: Option<String>,
+
+    /// Check for the target triple.
+    #[arg(long, value_name("TRIPLE"))]
+    pub target: Option<String>,
+}
+
+impl Check {
+    /// Settings given on the command line, which override those of the
+    /// project file
+    fn settings(&self) -> serde_json::Value {
+        let mut cargo = serde_json::json!({ "allTargets": self.all_targets });
+        let flags = [
+            ("allFeatures", self.all_features),
+            ("noDefaultFeatures", self.no_default_features),
+            ("lib", self.lib),
+            ("bins", self.bins),
+            ("tests", self.tests),
+            ("examples", self.examples),
+            ("benches", self.benches),
+        ];
+        for (key, _) in flags.iter().filter(|(_, set)| *set) {
+            cargo[key] = true.into();
+        }
+        if !self.packages.is_empty() {
+            cargo["packages"] = self.packages.clone().into();
+        }
+        if !self.features.is_empty() {
+            cargo["features"] = self.features.clone().into();
+        }
+        if let Some(profile) = &self.profile {
+            cargo["profile"] = profile.clone().into();
+        }
+        if let Some(target) = &self.target {
+            cargo["target"] = target.clone().into();
+        }
+        let mut settings = serde_json::json!({ "cargo": cargo });
+        if !self.exclude.is_empty() {
+            settings["exclude"] = self.exclude.clone().into();
+        }
+        settings
+    }
+}
+
+#[derive(Args, Debug)]
+pub struct GraphExport {
+    /// The path of a file or directory containing the function.
+    #[arg(value_name("path"), value_hint(ValueHint::AnyPath))]
+    pub path: Option<PathBuf>,
+
+    /// Path of the function, e.g. `module::Type::method` or `method`.
+    #[arg(short, long, value_name("function"))]
+    pub function: String,
+
+    /// Output format.
+    #[arg(long, value_enum, default_value_t)]
+    pub format: GraphFormat,
+
+    /// Write the graph to a file instead of stdout.
+    #[arg(short, long, value_name("output"), value_hint(ValueHint::FilePath))]
+    pub output: Option<PathBuf>,
+}
+
+impl GraphExport {
+    async fn execute(self, render: graph_export::Render) {
+        let path = self.path.unwrap_or_else(|| env::current_dir().unwrap());
+        let settings = lsp_settings::with_origin_graph(&serde_json::Value::Null, &self.function);
+        let Some(analyzed) = Backend::analyze_path(&path, settings).await else {
+            log::error!("Analyze failed");
+            exit(1);
+        };
+        let Some((file, func)) = graph_export::find_function_by_name(&analyzed, &self.function)
+        else {
+            log::error!("Function `{}` not found", self.function);
+            exit(1);
+        };
+        let source = fs::read_to_string(file).unwrap_or_default();
+        let graph = match render(func, &source, self.format) {
+            Ok(graph) => graph,
+            Err(e) => {
+                log::error!("Failed to render graph: {e}");
+                exit(1);
+            }
+        };
+        if let Some(output) = self.output {
+            if let Err(e) = fs::write(&output, graph) {
+                log::error!("Failed to write {}: {e}", output.display());
+                exit(1);
+            }
+        } else {
+            print!("{graph}");
+        }
+    }
 }
 
 impl Commands {
@@ -62,11 +205,10 @@ impl Commands {
     pub async fn execute(self) {
         match self {
             Self::Check(options) => {
+                let settings = options.settings();
                 let path = options.path.unwrap_or_else(|| env::current_dir().unwrap());
 
-                if Backend::check_with_options(&path, options.all_targets, options.all_features)
-                    .await
-                {
+                if Backend::check_with_options(&path, settings).await {
                     log::info!("Successfully analyzed");
                     exit(0);
                 }
@@ -79,6 +221,8 @@ impl Commands {
                     remove_dir_all(&target).await.ok();
                 }
             }
+            Self::OriginGraph(options) => options.execute(graph_export::origin_graph).await,
+            Self::Cfg(options) => options.execute(graph_export::cfg_graph).await,
         }
     }
 }
@@ -106,6 +250,11 @@ async fn start_lsp_server() {
     let (service, socket) = LspService::build(Backend::new)
         .custom_method("ferrous-owl/cursor", Backend::cursor)
         .custom_method("ferrous-owl/analyze", Backend::analyze)
+        .custom_method("ferrous-owl/status", Backend::status)
+        .custom_method(
+            "window/workDoneProgress/cancel",
+            Backend::work_done_progress_cancel,
+        )
         .finish();
 
     Server::new(stdin, stdout, socket).serve(service).await;
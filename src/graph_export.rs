@@ -0,0 +1,615 @@
+use std::{
+    collections::HashMap,
+    fmt::Write as _,
+    io::{self, Write as _},
+    process::{Command, Stdio},
+};
+
+use crate::{
+    models::{
+        Crate, DropState, FnLocal, Function, Loc, MirBasicBlock, MirDecl, MirRval, MirStatement,
+        MirTerminator, Range,
+    },
+    text_conversion,
+};
+
+/// Output format of exported graphs
+#[derive(
+    clap::ValueEnum,
+    serde::Serialize,
+    serde::Deserialize,
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    PartialEq,
+    Eq,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum GraphFormat {
+    /// Graphviz DOT
+    #[default]
+    Dot,
+    /// Mermaid flowchart
+    Mermaid,
+    /// SVG rendered by the Graphviz `dot` binary
+    Svg,
+}
+
+/// Renders a graph of a function given the source of its file
+pub type Render = fn(&Function, &str, GraphFormat) -> io::Result<String>;
+
+/// Find a function by its definition path, e.g. `module::Type::method`
+///
+/// Functions are also matched by the last segments of their path, so `method`
+/// and `Type::method` find `module::Type::method` as well.
+#[must_use]
+pub fn find_function_by_name<'a>(krate: &'a Crate, name: &str) -> Option<(&'a str, &'a Function)> {
+    krate
+        .0
+        .iter()
+        .flat_map(|(file, mir)| mir.items.iter().map(move |func| (file.as_str(), func)))
+        .filter(|(_, func)| is_named(&func.name, name))
+        .min_by_key(|(_, func)| func.name.len())
+}
+
+/// Whether the function at `path` is the one called `name`, which is its
+/// whole path or a suffix of it
+#[must_use]
+pub fn is_named(path: &str, name: &str) -> bool {
+    path == name
+        || path
+            .strip_suffix(name)
+            .is_some_and(|prefix| prefix.ends_with("::"))
+}
+
+/// Find the innermost function whose span contains `pos`
+#[must_use]
+pub fn find_function_at(functions: &[Function], pos: Loc) -> Option<&Function> {
+    functions
+        .iter()
+        .filter(|func| {
+            func.span
+                .is_some_and(|span| span.from() <= pos && pos < span.until())
+        })
+        .min_by_key(|func| func.span.map_or(u32::MAX, Range::size))
+}
+
+/// Render the origin subset graph of a function
+///
+/// Nodes are origins annotated with the locals whose types contain them.
+/// Edges point from the subset to the superset origin, i.e. in the direction
+/// loans flow, and carry the source locations where the relation holds.
+pub fn origin_graph(func: &Function, source: &str, format: GraphFormat) -> io::Result<String> {
+    let names = local_names(func);
+    let nodes: Vec<_> = func
+        .origin_graph
+        .origins
+        .iter()
+        .map(|origin| {
+            let locals: Vec<_> = origin
+                .locals
+                .iter()
+                .map(|local| local_name(&names, *local))
+                .collect();
+            let label = if locals.is_empty() {
+                format!("'?{}", origin.id)
+            } else {
+                format!("'?{}\n{}", origin.id, locals.join(", "))
+            };
+            Node {
+                id: format!("o{}", origin.id),
+                label,
+                dashed: false,
+            }
+        })
+        .collect();
+    let edges: Vec<_> = func
+        .origin_graph
+        .edges
+        .iter()
+        .map(|edge| Edge {
+            from: format!("o{}", edge.sub),
+            to: format!("o{}", edge.sup),
+            label: edge
+                .holds_at
+                .iter()
+                .map(|range| format_range(source, *range))
+                .collect::<Vec<_>>()
+                .join("\n"),
+            dashed: false,
+        })
+        .collect();
+    render(&func.name, &nodes, &edges, format)
+}
+
+/// Render the control-flow graph of a function
+///
+/// Each basic block lists its statements with their source locations and the
+/// locals moved, borrowed or dropped in it. Blocks and edges that are only
+/// taken while unwinding are dashed.
+pub fn cfg_graph(func: &Function, source: &str, format: GraphFormat) -> io::Result<String> {
+    let names = local_names(func);
+    let nodes: Vec<_> = func
+        .basic_blocks
+        .iter()
+        .enumerate()
+        .map(|(index, bb)| Node {
+            id: format!("bb{index}"),
+            label: block_label(index, bb, source, &names),
+            dashed: bb.cleanup,
+        })
+        .collect();
+    let edges: Vec<_> = func
+        .basic_blocks
+        .iter()
+        .enumerate()
+        .flat_map(|(index, bb)| {
+            bb.successors.iter().map(move |succ| {
+                let unwind = !bb.cleanup
+                    && func
+                        .basic_blocks
+                        .get(*succ as usize)
+                        .is_some_and(|succ| succ.cleanup);
+                Edge {
+                    from: format!("bb{index}"),
+                    to: format!("bb{succ}"),
+                    label: if unwind {
+                        "unwind".to_owned()
+                    } else {
+                        String::new()
+                    },
+                    dashed: unwind,
+                }
+            })
+        })
+        .collect();
+    render(&func.name, &nodes, &edges, format)
+}
+
+fn block_label(
+    index: usize,
+    bb: &MirBasicBlock,
+    source: &str,
+    names: &HashMap<u32, String>,
+) -> String {
+    let mut lines = vec![format!("bb{index}")];
+    let mut moved = Vec::new();
+    let mut borrowed = Vec::new();
+    let mut dropped = Vec::new();
+    let mut last_range = None;
+    for stmt in &bb.statements {
+        let range = stmt.range();
+        if last_range != Some(range) {
+            lines.push(format!(
+                "{} {}",
+                format_range(source, range),
+                snippet(source, range)
+            ));
+            last_range = Some(range);
+        }
+        if let MirStatement::Assign {
+            rval: Some(rval), ..
+        } = stmt
+        {
+            match rval {
+                MirRval::Move { target_local, .. } => {
+                    moved.push(local_name(names, *target_local));
+                }
+                MirRval::Borrow {
+                    target_local,
+                    mutable,
+                    ..
+                } => {
+                    let prefix = if *mutable { "&mut " } else { "&" };
+                    borrowed.push(format!("{prefix}{}", local_name(names, *target_local)));
+                }
+                MirRval::RawPtr {
+                    target_local,
+                    mutable,
+                    ..
+                } => {
+                    let prefix = if *mutable { "*mut " } else { "*const " };
+                    borrowed.push(format!("{prefix}{}", local_name(names, *target_local)));
+                }
+                // copies do not change ownership
+                MirRval::Copy { .. } => {}
+            }
+        }
+    }
+    match &bb.terminator {
+        Some(MirTerminator::Call { fn_span, .. }) => {
+            lines.push(format!(
+                "call {} {}",
+                format_range(source, *fn_span),
+                snippet(source, *fn_span)
+            ));
+        }
+        Some(MirTerminator::Drop { local, state, .. }) => match state {
+            DropState::Dropped => dropped.push(local_name(names, *local)),
+            DropState::MaybeDropped => dropped.push(format!("{}?", local_name(names, *local))),
+            DropState::MovedOut => {}
+        },
+        Some(MirTerminator::Yield { range }) => {
+            lines.push(format!("await {}", format_range(source, *range)));
+        }
+        Some(MirTerminator::Loop { range }) => {
+            lines.push(format!("loop {}", format_range(source, *range)));
+        }
+        Some(MirTerminator::Other { .. }) | None => {}
+    }
+    for (kind, locals) in [
+        ("moved", moved),
+        ("borrowed", borrowed),
+        ("dropped", dropped),
+    ] {
+        if !locals.is_empty() {
+            lines.push(format!("{kind}: {}", locals.join(", ")));
+        }
+    }
+    lines.join("\n")
+}
+
+fn local_names(func: &Function) -> HashMap<u32, String> {
+    func.decls
+        .iter()
+        .filter_map(|decl| match decl {
+            MirDecl::User { local, name, .. } => Some((local.id, name.clone())),
+            MirDecl::Other { .. } => None,
+        })
+        .collect()
+}
+
+fn local_name(names: &HashMap<u32, String>, local: FnLocal) -> String {
+    names
+        .get(&local.id)
+        .cloned()
+        .unwrap_or_else(|| format!("_{}", local.id))
+}
+
+/// Format a range as 1-based `line:col-line:col`
+fn format_range(source: &str, range: Range) -> String {
+    let (from_line, from_col) = text_conversion::index_to_line_char(source, range.from());
+    let (until_line, until_col) = text_conversion::index_to_line_char(source, range.until());
+    format!(
+        "{}:{}-{}:{}",
+        from_line + 1,
+        from_col + 1,
+        until_line + 1,
+        until_col + 1
+    )
+}
+
+/// The first line of the source text in `range`, shortened to fit a node
+fn snippet(source: &str, range: Range) -> String {
+    const MAX_CHARS: usize = 40;
+    let text: String = source
+        .replace('\r', "")
+        .chars()
+        .skip(u32::from(range.from()) as usize)
+        .take(range.size() as usize)
+        .collect();
+    let line = text.lines().next().unwrap_or_default().trim();
+    if line.chars().count() > MAX_CHARS || text.trim().contains('\n') {
+        format!("{}…", line.chars().take(MAX_CHARS).collect::<String>())
+    } else {
+        line.to_owned()
+    }
+}
+
+struct Node {
+    id: String,
+    label: String,
+    dashed: bool,
+}
+
+struct Edge {
+    from: String,
+    to: String,
+    label: String,
+    dashed: bool,
+}
+
+fn render(name: &str, nodes: &[Node], edges: &[Edge], format: GraphFormat) -> io::Result<String> {
+    match format {
+        GraphFormat::Dot => Ok(render_dot(name, nodes, edges)),
+        GraphFormat::Mermaid => Ok(render_mermaid(nodes, edges)),
+        GraphFormat::Svg => dot_to_svg(&render_dot(name, nodes, edges)),
+    }
+}
+
+fn render_dot(name: &str, nodes: &[Node], edges: &[Edge]) -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, "digraph \"{}\" {{", escape_dot(name));
+    let _ = writeln!(out, "    node [shape=box];");
+    for node in nodes {
+        let style = if node.dashed { ", style=dashed" } else { "" };
+        let _ = writeln!(
+            out,
+            "    {} [label=\"{}\"{style}];",
+            node.id,
+            escape_dot(&node.label)
+        );
+    }
+    for edge in edges {
+        let mut attrs = Vec::new();
+        if !edge.label.is_empty() {
+            attrs.push(format!("label=\"{}\"", escape_dot(&edge.label)));
+        }
+        if edge.dashed {
+            attrs.push("style=dashed".to_owned());
+        }
+        if attrs.is_empty() {
+            let _ = writeln!(out, "    {} -> {};", edge.from, edge.to);
+        } else {
+            let _ = writeln!(
+                out,
+                "    {} -> {} [{}];",
+                edge.from,
+                edge.to,
+                attrs.join(", ")
+            );
+        }
+    }
+    out.push_str("}\n");
+    out
+}
+
+fn render_mermaid(nodes: &[Node], edges: &[Edge]) -> String {
+    let mut out = String::from("flowchart TD\n");
+    for node in nodes {
+        let _ = writeln!(out, "    {}[\"{}\"]", node.id, escape_mermaid(&node.label));
+    }
+    for edge in edges {
+        let arrow = if edge.dashed { "-.->" } else { "-->" };
+        if edge.label.is_empty() {
+            let _ = writeln!(out, "    {} {arrow} {}", edge.from, edge.to);
+        } else {
+            let _ = writeln!(
+                out,
+                "    {} {arrow}|\"{}\"| {}",
+                edge.from,
+                escape_mermaid(&edge.label),
+                edge.to
+            );
+        }
+    }
+    let dashed: Vec<_> = nodes
+        .iter()
+        .filter(|node| node.dashed)
+        .map(|node| node.id.as_str())
+        .collect();
+    if !dashed.is_empty() {
+        out.push_str("    classDef dashed stroke-dasharray: 5 5\n");
+        let _ = writeln!(out, "    class {} dashed", dashed.join(","));
+    }
+    out
+}
+
+/// Render DOT source to SVG with the Graphviz `dot` binary
+fn dot_to_svg(dot: &str) -> io::Result<String> {
+    let mut child = Command::new("dot")
+        .arg("-Tsvg")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(dot.as_bytes())?;
+    }
+    let output = child.wait_with_output()?;
+    if output.status.success() {
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    } else {
+        Err(io::Error::other(
+            String::from_utf8_lossy(&output.stderr).into_owned(),
+        ))
+    }
+}
+
+fn escape_dot(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn escape_mermaid(s: &str) -> String {
+    s.replace('"', "#quot;").replace('\n', "<br/>")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{File, MirOrigin, MirOriginEdge, MirOriginGraph};
+
+    fn r(from: u32, until: u32) -> Range {
+        Range::new(Loc::from(from), Loc::from(until)).unwrap()
+    }
+
+    fn func_with_graph() -> Function {
+        Function {
+            fn_id: 1,
+            name: "demo::longest".into(),
+            span: Some(r(0, 40)),
+            decls: vec![MirDecl::User {
+                local: FnLocal::new(1, 1),
+                name: "x".into(),
+                span: r(4, 5),
+                ty: "&str".into(),
+                lives: vec![],
+                shared_borrow: vec![],
+                mutable_borrow: vec![],
+                drop: false,
+                drop_range: vec![],
+                must_live_at: vec![],
+                size: None,
+                binding: None,
+            }],
+            origin_graph: MirOriginGraph {
+                origins: vec![
+                    MirOrigin {
+                        id: 1,
+                        locals: vec![FnLocal::new(1, 1)],
+                        live_at: vec![],
+                    },
+                    MirOrigin {
+                        id: 2,
+                        locals: vec![FnLocal::new(2, 1)],
+                        live_at: vec![],
+                    },
+                ],
+                edges: vec![MirOriginEdge {
+                    sub: 1,
+                    sup: 2,
+                    holds_at: vec![r(12, 15)],
+                }],
+            },
+            ..Default::default()
+        }
+    }
+
+    const SOURCE: &str = "fn longest() {\n    x\n}\n";
+
+    #[test]
+    fn origin_graph_dot() {
+        let dot = origin_graph(&func_with_graph(), SOURCE, GraphFormat::Dot).unwrap();
+        assert!(
+            dot.starts_with("digraph \"demo::longest\" {"),
+            "unexpected header: {dot}"
+        );
+        assert!(
+            dot.contains("o1 [label=\"'?1\\nx\"];"),
+            "user local should be named: {dot}"
+        );
+        assert!(
+            dot.contains("o2 [label=\"'?2\\n_2\"];"),
+            "temporary should be numbered: {dot}"
+        );
+        assert!(
+            dot.contains("o1 -> o2 [label=\"1:13-2:1\"];"),
+            "edge should carry its location: {dot}"
+        );
+    }
+
+    #[test]
+    fn origin_graph_mermaid() {
+        let mermaid = origin_graph(&func_with_graph(), SOURCE, GraphFormat::Mermaid).unwrap();
+        assert!(
+            mermaid.starts_with("flowchart TD\n"),
+            "unexpected header: {mermaid}"
+        );
+        assert!(
+            mermaid.contains("o1[\"'?1<br/>x\"]"),
+            "user local should be named: {mermaid}"
+        );
+        assert!(
+            mermaid.contains("o1 -->|\"1:13-2:1\"| o2"),
+            "edge should carry its location: {mermaid}"
+        );
+    }
+
+    fn func_with_cfg() -> Function {
+        Function {
+            basic_blocks: vec![
+                MirBasicBlock {
+                    statements: vec![MirStatement::Assign {
+                        target_local: FnLocal::new(2, 1),
+                        range: r(19, 20),
+                        rval: Some(MirRval::Move {
+                            target_local: FnLocal::new(1, 1),
+                            range: r(19, 20),
+                        }),
+                    }],
+                    terminator: Some(MirTerminator::Drop {
+                        local: FnLocal::new(1, 1),
+                        range: r(21, 22),
+                        state: DropState::Dropped,
+                    }),
+                    successors: vec![1, 2],
+                    ..Default::default()
+                },
+                MirBasicBlock::default(),
+                MirBasicBlock {
+                    cleanup: true,
+                    ..Default::default()
+                },
+            ],
+            ..func_with_graph()
+        }
+    }
+
+    #[test]
+    fn cfg_graph_dot() {
+        let dot = cfg_graph(&func_with_cfg(), SOURCE, GraphFormat::Dot).unwrap();
+        assert!(
+            dot.contains("bb0 [label=\"bb0\\n2:5-2:6 x\\nmoved: x\\ndropped: x\"];"),
+            "block should list statements and ownership facts: {dot}"
+        );
+        assert!(
+            dot.contains("bb2 [label=\"bb2\", style=dashed];"),
+            "cleanup block should be dashed: {dot}"
+        );
+        assert!(dot.contains("bb0 -> bb1;"), "normal edge expected: {dot}");
+        assert!(
+            dot.contains("bb0 -> bb2 [label=\"unwind\", style=dashed];"),
+            "unwind edge expected: {dot}"
+        );
+    }
+
+    #[test]
+    fn cfg_graph_mermaid() {
+        let mermaid = cfg_graph(&func_with_cfg(), SOURCE, GraphFormat::Mermaid).unwrap();
+        assert!(
+            mermaid.contains("bb0 -.->|\"unwind\"| bb2"),
+            "unwind edge expected: {mermaid}"
+        );
+        assert!(
+            mermaid.contains("class bb2 dashed"),
+            "cleanup block should be dashed: {mermaid}"
+        );
+    }
+
+    #[test]
+    fn find_function_by_path_suffix() {
+        let mut files = HashMap::new();
+        files.insert(
+            "src/lib.rs".to_owned(),
+            File {
+                items: vec![func_with_graph()],
+            },
+        );
+        let krate = Crate(files);
+        assert!(find_function_by_name(&krate, "longest").is_some(), "suffix");
+        assert!(
+            find_function_by_name(&krate, "demo::longest").is_some(),
+            "full path"
+        );
+        assert!(
+            find_function_by_name(&krate, "ongest").is_none(),
+            "partial segment"
+        );
+    }
+
+    #[test]
+    fn find_innermost_function_at_position() {
+        let outer = func_with_graph();
+        let inner = Function {
+            fn_id: 2,
+            span: Some(r(10, 20)),
+            ..Default::default()
+        };
+        let functions = [outer, inner];
+        assert_eq!(
+            find_function_at(&functions, Loc::from(12u32)).map(|f| f.fn_id),
+            Some(2)
+        );
+        assert_eq!(
+            find_function_at(&functions, Loc::from(30u32)).map(|f| f.fn_id),
+            Some(1)
+        );
+        assert!(
+            find_function_at(&functions, Loc::from(50u32)).is_none(),
+            "outside"
+        );
+    }
+}
@@ -11,6 +11,7 @@ extern crate rustc_hir;
 extern crate rustc_index;
 extern crate rustc_interface;
 extern crate rustc_middle;
+extern crate rustc_mir_dataflow;
 extern crate rustc_query_system;
 extern crate rustc_session;
 extern crate rustc_span;
@@ -19,9 +20,13 @@ extern crate rustc_type_ir;
 extern crate smallvec;
 
 mod cli;
+mod graph_export;
+mod lints;
 mod lsp_decoration;
+mod lsp_inlay_hint;
 mod lsp_progress;
 mod lsp_server;
+mod lsp_settings;
 mod lsp_workspace;
 mod mir_analysis;
 mod mir_cache;
@@ -29,6 +34,8 @@ mod mir_polonius;
 mod mir_transform;
 mod models;
 mod range_ops;
+mod runtime_borrow;
+mod rust_project;
 mod rustc_wrapper;
 mod text_conversion;
 mod toolchain;
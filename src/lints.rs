@@ -0,0 +1,1379 @@
+use std::collections::{HashMap, HashSet};
+
+use serde::Deserialize;
+use tower_lsp::lsp_types;
+
+use crate::{
+    models::{
+        DropState, FnLocal, Function, Loc, MirDecl, MirRval, MirStatement, MirTerminator, Range,
+    },
+    range_ops, text_conversion,
+};
+
+/// A problem found in an analyzed function, reported as a warning
+#[derive(Clone, Debug)]
+pub struct Lint {
+    pub code: &'static str,
+    pub range: Range,
+    pub message: String,
+    /// Places that explain the problem
+    pub related: Vec<(Range, String)>,
+    /// Edit that fixes the problem
+    pub fix: Option<Fix>,
+}
+
+/// A text edit offered as quick fix of a lint
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Fix {
+    /// Replace the text of a range
+    Replace(Range, String),
+    /// Insert a line after the code ending at a position, indented like the
+    /// line the position is on
+    InsertLine(Loc, String),
+}
+
+fn lsp_position(text: &str, loc: Loc) -> lsp_types::Position {
+    let (line, character) = text_conversion::index_to_line_char(text, loc);
+    lsp_types::Position { line, character }
+}
+
+fn lsp_range(text: &str, range: Range) -> lsp_types::Range {
+    lsp_types::Range {
+        start: lsp_position(text, range.from()),
+        end: lsp_position(text, range.until()),
+    }
+}
+
+impl Lint {
+    /// Convert this lint to an LSP diagnostic of the file `uri` containing
+    /// `text`
+    #[must_use]
+    pub fn to_diagnostic(&self, text: &str, uri: &lsp_types::Url) -> lsp_types::Diagnostic {
+        let related_information = self
+            .related
+            .iter()
+            .map(|(range, message)| lsp_types::DiagnosticRelatedInformation {
+                location: lsp_types::Location {
+                    uri: uri.clone(),
+                    range: lsp_range(text, *range),
+                },
+                message: message.clone(),
+            })
+            .collect::<Vec<_>>();
+        lsp_types::Diagnostic {
+            range: lsp_range(text, self.range),
+            severity: Some(lsp_types::DiagnosticSeverity::WARNING),
+            code: Some(lsp_types::NumberOrString::String(format!(
+                "{}:{}",
+                env!("CARGO_PKG_NAME"),
+                self.code
+            ))),
+            code_description: None,
+            source: Some(env!("CARGO_PKG_NAME").to_string()),
+            message: self.message.clone(),
+            related_information: (!related_information.is_empty()).then_some(related_information),
+            tags: None,
+            data: None,
+        }
+    }
+
+    /// The quick fix of this lint as a code action, if it has one
+    #[must_use]
+    pub fn to_code_action(
+        &self,
+        text: &str,
+        uri: &lsp_types::Url,
+    ) -> Option<lsp_types::CodeAction> {
+        let (title, edit) = match self.fix.clone()? {
+            Fix::Replace(range, new_text) => (
+                format!("Replace with `{new_text}`"),
+                lsp_types::TextEdit {
+                    range: lsp_range(text, range),
+                    new_text,
+                },
+            ),
+            Fix::InsertLine(loc, line) => {
+                let at = insert_line_position(text, loc);
+                let position = lsp_position(text, at);
+                (
+                    format!("Insert `{line}`"),
+                    lsp_types::TextEdit {
+                        range: lsp_types::Range::new(position, position),
+                        new_text: format!("\n{}{line}", line_indent(text, loc)),
+                    },
+                )
+            }
+        };
+        Some(lsp_types::CodeAction {
+            title,
+            kind: Some(lsp_types::CodeActionKind::QUICKFIX),
+            diagnostics: Some(vec![self.to_diagnostic(text, uri)]),
+            edit: Some(lsp_types::WorkspaceEdit {
+                changes: Some([(uri.clone(), vec![edit])].into_iter().collect()),
+                ..Default::default()
+            }),
+            is_preferred: Some(true),
+            ..Default::default()
+        })
+    }
+}
+
+/// The characters of `text` as counted by [`Loc`], which ignores CR
+fn loc_chars(text: &str) -> Vec<char> {
+    text.chars().filter(|c| *c != '\r').collect()
+}
+
+/// The leading whitespace of the line containing `loc`
+fn line_indent(text: &str, loc: Loc) -> String {
+    let chars = loc_chars(text);
+    let at = (u32::from(loc) as usize).min(chars.len());
+    let start = chars[..at]
+        .iter()
+        .rposition(|c| *c == '\n')
+        .map_or(0, |newline| newline + 1);
+    chars[start..at]
+        .iter()
+        .take_while(|c| c.is_whitespace())
+        .collect()
+}
+
+/// Where to insert a line after the code ending at `loc`: the end of its
+/// line, unless more code follows on the same line
+fn insert_line_position(text: &str, loc: Loc) -> Loc {
+    let rest: String = loc_chars(text)
+        .into_iter()
+        .skip(u32::from(loc) as usize)
+        .take_while(|c| *c != '\n')
+        .collect();
+    let trimmed = rest.trim();
+    if trimmed.is_empty() || trimmed.starts_with("//") {
+        Loc::from(u32::from(loc) as usize + rest.chars().count())
+    } else {
+        loc
+    }
+}
+
+/// Settings of the configurable lints
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "camelCase", default)]
+pub struct LintOptions {
+    /// Moves and copies of at least this many bytes are reported
+    pub large_copy_threshold: u64,
+    /// Moves and copies inside loops of at least this many bytes are
+    /// reported
+    pub large_copy_in_loop_threshold: u64,
+    /// Only report guards held across calls of known blocking or locking
+    /// functions, instead of across any call: helpers that lock the same
+    /// mutex again are then missed
+    pub guard_blocking_calls_only: bool,
+}
+impl Default for LintOptions {
+    fn default() -> Self {
+        Self {
+            large_copy_threshold: 512,
+            large_copy_in_loop_threshold: 128,
+            guard_blocking_calls_only: false,
+        }
+    }
+}
+
+/// All lints of `func`
+#[must_use]
+pub fn lints(func: &Function, options: &LintOptions) -> Vec<Lint> {
+    let mut lints = guard_lints(func, options);
+    for lint in held_too_long_lints(func, options) {
+        // guards held across calls are already reported; offer the fix there
+        if let Some(guard) = lints
+            .iter_mut()
+            .find(|guard| guard.code == "guard-held" && guard.range.from() == lint.range.from())
+        {
+            guard.fix = lint.fix;
+            continue;
+        }
+        lints.push(lint);
+    }
+    lints.extend(needless_clone_lints(func));
+    lints.extend(large_copy_lints(func, options));
+    lints.extend(dangling_pointer_lints(func));
+    lints.sort_by_key(|lint| lint.range.from());
+    lints
+}
+
+/// Suggest moving variables that are cloned and never used afterwards
+#[must_use]
+pub fn needless_clone_lints(func: &Function) -> Vec<Lint> {
+    func.needless_clones
+        .iter()
+        .map(|clone| Lint {
+            code: "needless-clone",
+            range: clone.range,
+            message: format!(
+                "`{}` is not used after this `{}`; move it instead",
+                clone.name, clone.method
+            ),
+            related: Vec::new(),
+            fix: Some(Fix::Replace(clone.range, clone.name.clone())),
+        })
+        .collect()
+}
+
+/// Guards of `std`, `tokio`, `parking_lot` and other `lock_api` locks
+const LOCK_GUARDS: [&str; 12] = [
+    "MutexGuard",
+    "MappedMutexGuard",
+    "OwnedMutexGuard",
+    "OwnedMappedMutexGuard",
+    "RwLockReadGuard",
+    "RwLockWriteGuard",
+    "MappedRwLockReadGuard",
+    "MappedRwLockWriteGuard",
+    "RwLockMappedWriteGuard",
+    "OwnedRwLockReadGuard",
+    "OwnedRwLockWriteGuard",
+    "OwnedRwLockMappedWriteGuard",
+];
+
+/// Functions that block the thread, acquire a lock or borrow a cell, or
+/// yield to other threads, which are the only calls reported while a guard
+/// is held with [`LintOptions::guard_blocking_calls_only`]
+const BLOCKING_CALLS: [&str; 32] = [
+    "std::sync::Mutex::lock",
+    "std::sync::RwLock::read",
+    "std::sync::RwLock::write",
+    "std::cell::RefCell::borrow",
+    "std::cell::RefCell::borrow_mut",
+    "lock_api::Mutex::lock",
+    "lock_api::RwLock::read",
+    "lock_api::RwLock::write",
+    "lock_api::ReentrantMutex::lock",
+    "tokio::sync::Mutex::blocking_lock",
+    "tokio::sync::RwLock::blocking_read",
+    "tokio::sync::RwLock::blocking_write",
+    "std::sync::Condvar::wait",
+    "std::sync::Condvar::wait_while",
+    "std::sync::Condvar::wait_timeout",
+    "std::sync::Barrier::wait",
+    "std::sync::mpsc::Receiver::recv",
+    "std::sync::mpsc::Receiver::recv_timeout",
+    "std::sync::mpsc::SyncSender::send",
+    "std::thread::JoinHandle::join",
+    "std::thread::sleep",
+    "std::thread::park",
+    "std::thread::park_timeout",
+    "std::thread::yield_now",
+    "std::io::Stdin::read_line",
+    "std::process::Command::output",
+    "std::process::Command::status",
+    "std::process::Child::wait",
+    "std::net::TcpStream::connect",
+    "std::net::TcpListener::accept",
+    "tokio::runtime::Runtime::block_on",
+    "futures::executor::block_on",
+];
+
+/// What kind of guard a type is, if any
+fn guard_kind(ty: &str) -> Option<&'static str> {
+    let path = ty.split('<').next().unwrap_or(ty);
+    // references, tuples and the like only point to or contain a guard
+    if !path
+        .chars()
+        .all(|c| c.is_alphanumeric() || c == '_' || c == ':')
+    {
+        return None;
+    }
+    let name = path.rsplit("::").next().unwrap_or(path);
+    match name {
+        "Ref" | "RefMut" if path.starts_with("std::cell::") || path.starts_with("core::cell::") => {
+            Some("`RefCell` borrow")
+        }
+        _ if LOCK_GUARDS.contains(&name) => Some("lock guard"),
+        _ => None,
+    }
+}
+
+/// Something that happens while a guard is held
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum HeldAcross {
+    Call,
+    Await,
+    Loop,
+}
+impl HeldAcross {
+    const fn description(self) -> &'static str {
+        match self {
+            Self::Call => "a call",
+            Self::Await => "an `.await`",
+            Self::Loop => "a loop",
+        }
+    }
+
+    const fn site_message(self) -> &'static str {
+        match self {
+            Self::Call => "called while the guard is held",
+            Self::Await => "suspended while the guard is held",
+            Self::Loop => "loop entered while the guard is held",
+        }
+    }
+
+    const fn held_message(self) -> &'static str {
+        match self {
+            Self::Call => "called while the unused value is held",
+            Self::Await => "suspended while the unused value is held",
+            Self::Loop => "loop entered while the unused value is held",
+        }
+    }
+}
+
+/// A range in which a guard is held and what happens meanwhile
+#[derive(Clone, Debug)]
+pub struct GuardHold {
+    pub range: Range,
+    pub across: Vec<(HeldAcross, Range)>,
+}
+
+/// Locals holding the guard or a value obtained from it, such as the
+/// reference returned by `Deref`
+fn derived_locals(func: &Function, guard: FnLocal) -> HashSet<FnLocal> {
+    let mut derived = HashSet::from([guard]);
+    loop {
+        let count = derived.len();
+        for bb in &func.basic_blocks {
+            for stmt in &bb.statements {
+                if let MirStatement::Assign {
+                    target_local,
+                    rval:
+                        Some(
+                            MirRval::Move {
+                                target_local: from, ..
+                            }
+                            | MirRval::Borrow {
+                                target_local: from, ..
+                            },
+                        ),
+                    ..
+                } = stmt
+                    && derived.contains(from)
+                {
+                    derived.insert(*target_local);
+                }
+            }
+            if let Some(MirTerminator::Call {
+                destination_local,
+                args,
+                ..
+            }) = &bb.terminator
+                && args.iter().any(|arg| derived.contains(arg))
+            {
+                derived.insert(*destination_local);
+            }
+        }
+        if derived.len() == count {
+            return derived;
+        }
+    }
+}
+
+/// Follow the CFG from each point where `guard` is assigned until it is
+/// dropped or moved, collecting the calls not using the guard, the awaits and
+/// the loops on the way
+#[must_use]
+pub fn guard_holds(func: &Function, guard: FnLocal) -> Vec<GuardHold> {
+    let derived = derived_locals(func, guard);
+    let mut starts = Vec::new();
+    for (index, bb) in func.basic_blocks.iter().enumerate() {
+        for (stmt_index, stmt) in bb.statements.iter().enumerate() {
+            if let MirStatement::Assign {
+                target_local,
+                range,
+                ..
+            } = stmt
+                && *target_local == guard
+            {
+                starts.push((*range, vec![(index, stmt_index + 1)]));
+            }
+        }
+        if let Some(MirTerminator::Call {
+            destination_local,
+            fn_span,
+            ..
+        }) = &bb.terminator
+            && *destination_local == guard
+        {
+            starts.push((*fn_span, successors(func, index)));
+        }
+    }
+    starts
+        .into_iter()
+        .map(|(start, stack)| hold_from(func, guard, &derived, start, stack))
+        .collect()
+}
+
+fn successors(func: &Function, index: usize) -> Vec<(usize, usize)> {
+    func.basic_blocks[index]
+        .successors
+        .iter()
+        .map(|succ| *succ as usize)
+        .filter(|succ| func.basic_blocks.get(*succ).is_some_and(|bb| !bb.cleanup))
+        .map(|succ| (succ, 0))
+        .collect()
+}
+
+fn hold_from(
+    func: &Function,
+    guard: FnLocal,
+    derived: &HashSet<FnLocal>,
+    start: Range,
+    mut stack: Vec<(usize, usize)>,
+) -> GuardHold {
+    let mut until = start.until();
+    let mut across = Vec::new();
+    let mut visited = HashSet::new();
+    'blocks: while let Some((index, first_stmt)) = stack.pop() {
+        if first_stmt == 0 && !visited.insert(index) {
+            continue;
+        }
+        let bb = &func.basic_blocks[index];
+        for stmt in &bb.statements[first_stmt.min(bb.statements.len())..] {
+            let released = match stmt {
+                MirStatement::Assign {
+                    target_local, rval, ..
+                } => {
+                    *target_local == guard
+                        || matches!(rval, Some(MirRval::Move { target_local, .. }) if *target_local == guard)
+                }
+                MirStatement::StorageDead { target_local, .. } => *target_local == guard,
+                _ => false,
+            };
+            if released {
+                until = until.max(stmt.range().until());
+                continue 'blocks;
+            }
+        }
+        match &bb.terminator {
+            Some(MirTerminator::Drop { local, range, .. }) if *local == guard => {
+                until = until.max(range.until());
+                continue;
+            }
+            Some(MirTerminator::Call {
+                destination_local,
+                fn_span,
+                args,
+                ..
+            }) => {
+                if *destination_local == guard || args.contains(&guard) {
+                    until = until.max(fn_span.until());
+                    continue;
+                }
+                if !args.iter().any(|arg| derived.contains(arg)) {
+                    across.push((HeldAcross::Call, *fn_span));
+                }
+            }
+            Some(MirTerminator::Yield { range }) => across.push((HeldAcross::Await, *range)),
+            Some(MirTerminator::Loop { range }) => across.push((HeldAcross::Loop, *range)),
+            _ => {}
+        }
+        stack.extend(successors(func, index).into_iter().rev());
+    }
+    // the calls the `.await` desugars to all overlap the `await` keyword
+    let awaits: Vec<_> = across
+        .iter()
+        .filter(|(kind, _)| *kind == HeldAcross::Await)
+        .map(|(_, range)| *range)
+        .collect();
+    across.retain(|(kind, range)| {
+        *kind != HeldAcross::Call
+            || awaits
+                .iter()
+                .all(|await_range| range_ops::common_range(*range, *await_range).is_none())
+    });
+    across.sort_by_key(|(_, range)| range.from());
+    across.dedup();
+    GuardHold {
+        range: Range::new(start.from(), until).unwrap_or(start),
+        across,
+    }
+}
+
+/// Whether the call of `func` at `fn_span` is reported while a guard is held
+fn is_reported_call(func: &Function, fn_span: Range, options: &LintOptions) -> bool {
+    !options.guard_blocking_calls_only
+        || func.basic_blocks.iter().any(|bb| {
+            matches!(
+                &bb.terminator,
+                Some(MirTerminator::Call { fn_span: span, callee, .. })
+                    if *span == fn_span && BLOCKING_CALLS.contains(&callee.as_str())
+            )
+        })
+}
+
+/// Warn about lock guards and `RefCell` borrows that are held across calls,
+/// `.await`s or loops, which easily deadlocks or panics when the same lock or
+/// cell is used again meanwhile
+#[must_use]
+pub fn guard_lints(func: &Function, options: &LintOptions) -> Vec<Lint> {
+    let mut lints = Vec::new();
+    for decl in &func.decls {
+        let (local, ty, what, origin) = match decl {
+            MirDecl::User {
+                local,
+                ty,
+                name,
+                span,
+                ..
+            } => (*local, ty, format!("`{name}`"), Some(*span)),
+            MirDecl::Other {
+                local,
+                ty,
+                temporary,
+                ..
+            } => (
+                *local,
+                ty,
+                temporary.as_ref().map_or_else(
+                    || "temporary".to_owned(),
+                    |temporary| format!("`{}`", temporary.expr),
+                ),
+                temporary.as_ref().map(|temporary| temporary.span),
+            ),
+        };
+        let Some(kind) = guard_kind(ty) else {
+            continue;
+        };
+        for mut hold in guard_holds(func, local) {
+            hold.across.retain(|(across, range)| {
+                *across != HeldAcross::Call || is_reported_call(func, *range, options)
+            });
+            if hold.across.is_empty() {
+                continue;
+            }
+            // highlight from the binding or expression holding the guard
+            let range = origin
+                .filter(|origin| origin.from() < hold.range.from())
+                .and_then(|origin| Range::new(origin.from(), hold.range.until()))
+                .unwrap_or(hold.range);
+            let mut kinds: Vec<_> = Vec::new();
+            for (across, _) in &hold.across {
+                if !kinds.contains(across) {
+                    kinds.push(*across);
+                }
+            }
+            let kinds: Vec<_> = kinds.iter().map(|kind| kind.description()).collect();
+            lints.push(Lint {
+                code: "guard-held",
+                range,
+                message: format!(
+                    "{kind} {what} of type `{ty}` is held across {}",
+                    kinds.join(" and ")
+                ),
+                related: hold
+                    .across
+                    .iter()
+                    .map(|(across, range)| (*range, across.site_message().to_owned()))
+                    .collect(),
+                fix: None,
+            });
+        }
+    }
+    lints.sort_by_key(|lint| lint.range.from());
+    lints
+}
+
+/// Where the value of `local` is dropped at the end of its scope, if it is
+/// always dropped there and never moved out
+fn scope_drop(func: &Function, local: FnLocal) -> Option<Range> {
+    let mut drops = Vec::new();
+    for bb in func.basic_blocks.iter().filter(|bb| !bb.cleanup) {
+        if let Some(MirTerminator::Drop {
+            local: dropped,
+            range,
+            state,
+        }) = &bb.terminator
+            && *dropped == local
+        {
+            if *state != DropState::Dropped {
+                return None;
+            }
+            drops.push(*range);
+        }
+    }
+    drops.into_iter().min_by_key(|range| range.from())
+}
+
+/// Calls, `.await`s and loops of `func` entirely inside `range`
+fn sites_within(func: &Function, range: Range) -> Vec<(HeldAcross, Range)> {
+    let mut sites: Vec<_> = func
+        .basic_blocks
+        .iter()
+        .filter(|bb| !bb.cleanup)
+        .filter_map(|bb| match bb.terminator.as_ref()? {
+            MirTerminator::Call { fn_span, .. } => Some((HeldAcross::Call, *fn_span)),
+            MirTerminator::Yield { range } => Some((HeldAcross::Await, *range)),
+            MirTerminator::Loop { range } => Some((HeldAcross::Loop, *range)),
+            MirTerminator::Drop { .. } | MirTerminator::Other { .. } => None,
+        })
+        .filter(|(_, site)| range_ops::is_super_range(range, *site) || range == *site)
+        .collect();
+    sites.sort_by_key(|(_, site)| site.from());
+    sites.dedup();
+    sites
+}
+
+/// Suggest dropping values that stay alive until the end of their scope long
+/// after their last use: guards held across calls, `.await`s or loops, and
+/// other values with a destructor held across `.await`s or loops
+#[must_use]
+pub fn held_too_long_lints(func: &Function, options: &LintOptions) -> Vec<Lint> {
+    let mut lints = Vec::new();
+    for decl in &func.decls {
+        let MirDecl::User {
+            local,
+            name,
+            span,
+            ty,
+            lives,
+            shared_borrow,
+            mutable_borrow,
+            drop: true,
+            must_live_at,
+            ..
+        } = decl
+        else {
+            continue;
+        };
+        // `_guard` and the like are held on purpose
+        if name.starts_with('_') {
+            continue;
+        }
+        let Some(last_use) = lives
+            .iter()
+            .chain(must_live_at)
+            .chain(shared_borrow)
+            .chain(mutable_borrow)
+            .map(|range| range.until())
+            .max()
+        else {
+            continue;
+        };
+        let Some(dropped) = scope_drop(func, *local) else {
+            continue;
+        };
+        // the outermost statement after the declaration that contains the
+        // last use, so that the `drop` is not inserted inside a loop
+        let Some(stmt) = func
+            .statements
+            .iter()
+            .filter(|stmt| {
+                span.until() <= stmt.from()
+                    && stmt.from() < last_use
+                    && last_use <= stmt.until()
+                    && stmt.until() < dropped.from()
+            })
+            .max_by_key(|stmt| stmt.size())
+        else {
+            continue;
+        };
+        let Some(held) = Range::new(stmt.until(), dropped.from()) else {
+            continue;
+        };
+        let kind = guard_kind(ty);
+        let across: Vec<_> = sites_within(func, held)
+            .into_iter()
+            .filter(|(across, range)| {
+                *across != HeldAcross::Call
+                    || kind.is_some() && is_reported_call(func, *range, options)
+            })
+            .collect();
+        if across.is_empty() {
+            continue;
+        }
+        let mut kinds: Vec<_> = Vec::new();
+        for (across, _) in &across {
+            if !kinds.contains(&across.description()) {
+                kinds.push(across.description());
+            }
+        }
+        let mut related = vec![(*stmt, "last used here".to_owned())];
+        related.extend(
+            across
+                .iter()
+                .map(|(across, range)| (*range, across.held_message().to_owned())),
+        );
+        related.push((dropped, "dropped here".to_owned()));
+        lints.push(Lint {
+            code: "held-too-long",
+            range: *span,
+            message: format!(
+                "{} `{name}` is held across {} after its last use; drop it earlier or move it \
+                 into an inner scope",
+                kind.unwrap_or("value"),
+                kinds.join(" and ")
+            ),
+            related,
+            fix: Some(Fix::InsertLine(stmt.until(), format!("drop({name});"))),
+        });
+    }
+    lints.sort_by_key(|lint| lint.range.from());
+    lints
+}
+
+/// The blocks that can be reached again from themselves, i.e. that are part
+/// of a loop
+fn loop_blocks(func: &Function) -> HashSet<usize> {
+    (0..func.basic_blocks.len())
+        .filter(|index| !func.basic_blocks[*index].cleanup)
+        .filter(|index| {
+            let mut visited = HashSet::new();
+            let mut stack: Vec<_> = successors(func, *index)
+                .into_iter()
+                .map(|(succ, _)| succ)
+                .collect();
+            while let Some(current) = stack.pop() {
+                if current == *index {
+                    return true;
+                }
+                if visited.insert(current) {
+                    stack.extend(successors(func, current).into_iter().map(|(succ, _)| succ));
+                }
+            }
+            false
+        })
+        .collect()
+}
+
+/// Warn about moves and copies of large values, which are `memcpy`s that
+/// cannot be seen in the source
+#[must_use]
+pub fn large_copy_lints(func: &Function, options: &LintOptions) -> Vec<Lint> {
+    let values: HashMap<_, _> = func
+        .decls
+        .iter()
+        .filter_map(|decl| match decl {
+            MirDecl::User {
+                local,
+                name,
+                size: Some(size),
+                ..
+            } => Some((*local, (format!("`{name}`"), *size, true))),
+            MirDecl::Other {
+                local,
+                temporary,
+                size: Some(size),
+                ..
+            } => Some((
+                *local,
+                (
+                    temporary.as_ref().map_or_else(
+                        || "a temporary".to_owned(),
+                        |temporary| format!("`{}`", temporary.expr),
+                    ),
+                    *size,
+                    false,
+                ),
+            )),
+            MirDecl::User { .. } | MirDecl::Other { .. } => None,
+        })
+        .collect();
+    let in_loop = loop_blocks(func);
+    let mut lints = Vec::new();
+    for (index, bb) in func.basic_blocks.iter().enumerate() {
+        let (threshold, where_) = if in_loop.contains(&index) {
+            (options.large_copy_in_loop_threshold, " in a loop")
+        } else {
+            (options.large_copy_threshold, "")
+        };
+        let mut sites = Vec::new();
+        for stmt in &bb.statements {
+            if let MirStatement::Assign {
+                rval:
+                    Some(
+                        MirRval::Move {
+                            target_local,
+                            range,
+                        }
+                        | MirRval::Copy {
+                            target_local,
+                            range,
+                        },
+                    ),
+                ..
+            } = stmt
+            {
+                let kind = match stmt {
+                    MirStatement::Assign {
+                        rval: Some(MirRval::Copy { .. }),
+                        ..
+                    } => "copy",
+                    _ => "move",
+                };
+                sites.push((*target_local, *range, kind));
+            }
+        }
+        // arguments are usually moved out of temporaries already reported
+        if let Some(MirTerminator::Call { fn_span, args, .. }) = &bb.terminator {
+            sites.extend(
+                args.iter()
+                    .filter(|arg| values.get(arg).is_some_and(|(_, _, user)| *user))
+                    .map(|arg| (*arg, *fn_span, "pass")),
+            );
+        }
+        for (local, range, kind) in sites {
+            let Some((what, size, _)) = values.get(&local) else {
+                continue;
+            };
+            if *size < threshold || *size == 0 {
+                continue;
+            }
+            let message = match kind {
+                "pass" => format!("passing {what} by value copies {size} bytes{where_}"),
+                _ => format!("{kind} of {what} copies {size} bytes{where_}"),
+            };
+            lints.push(Lint {
+                code: "large-copy",
+                range,
+                message,
+                related: Vec::new(),
+                fix: None,
+            });
+        }
+    }
+    lints.sort_by_key(|lint| lint.range.from());
+    lints
+}
+
+/// The raw pointers of `func`, mapped to the local they point into and where
+/// they are created, following copies of pointers into other locals
+fn raw_pointers(func: &Function) -> HashMap<FnLocal, (FnLocal, Range)> {
+    // reads through a pointer are copies of the pointer local as well
+    let typed: HashSet<_> = func
+        .decls
+        .iter()
+        .filter_map(|decl| match decl {
+            MirDecl::User { local, ty, .. } | MirDecl::Other { local, ty, .. } => {
+                ty.starts_with('*').then_some(*local)
+            }
+        })
+        .collect();
+    let mut pointers = HashMap::new();
+    loop {
+        let count = pointers.len();
+        for stmt in func.basic_blocks.iter().flat_map(|bb| &bb.statements) {
+            let MirStatement::Assign {
+                target_local,
+                rval: Some(rval),
+                ..
+            } = stmt
+            else {
+                continue;
+            };
+            let pointee = match rval {
+                MirRval::RawPtr {
+                    target_local: pointee,
+                    range,
+                    ..
+                } => Some((*pointee, *range)),
+                MirRval::Copy {
+                    target_local: source,
+                    ..
+                }
+                | MirRval::Move {
+                    target_local: source,
+                    ..
+                } => pointers.get(source).copied(),
+                MirRval::Borrow { .. } => None,
+            };
+            if let Some(pointee) = pointee
+                && typed.contains(target_local)
+            {
+                pointers.entry(*target_local).or_insert(pointee);
+            }
+        }
+        if pointers.len() == count {
+            return pointers;
+        }
+    }
+}
+
+/// Where the value of `local` stops existing after `from`: its first drop
+/// or `StorageDead`
+fn value_end(func: &Function, local: FnLocal, from: Loc) -> Option<(Range, &'static str)> {
+    func.basic_blocks
+        .iter()
+        .filter(|bb| !bb.cleanup)
+        .flat_map(|bb| {
+            let dead = bb.statements.iter().filter_map(move |stmt| match stmt {
+                MirStatement::StorageDead {
+                    target_local,
+                    range,
+                } if *target_local == local => Some((*range, "goes out of scope")),
+                _ => None,
+            });
+            let dropped = match &bb.terminator {
+                Some(MirTerminator::Drop {
+                    local: dropped,
+                    range,
+                    state: DropState::Dropped | DropState::MaybeDropped,
+                }) if *dropped == local => Some((*range, "is dropped")),
+                _ => None,
+            };
+            dead.chain(dropped)
+        })
+        .filter(|(range, _)| from <= range.from())
+        .min_by_key(|(range, _)| range.from())
+}
+
+/// Warn about raw pointers that are still live after the value they point
+/// into is dropped or goes out of scope
+#[must_use]
+pub fn dangling_pointer_lints(func: &Function) -> Vec<Lint> {
+    let decls: HashMap<_, _> = func
+        .decls
+        .iter()
+        .map(|decl| match decl {
+            MirDecl::User {
+                local, name, lives, ..
+            } => (*local, (Some(format!("`{name}`")), lives)),
+            MirDecl::Other {
+                local,
+                temporary,
+                lives,
+                ..
+            } => (
+                *local,
+                (
+                    temporary
+                        .as_ref()
+                        .map(|temporary| format!("`{}`", temporary.expr)),
+                    lives,
+                ),
+            ),
+        })
+        .collect();
+    let mut pointers: Vec<_> = raw_pointers(func).into_iter().collect();
+    pointers.sort_by_key(|(pointer, (_, created))| (created.from(), pointer.id));
+    let mut lints: Vec<Lint> = Vec::new();
+    for (pointer, (pointee, created)) in pointers {
+        let (Some((Some(name), _)), Some((_, pointer_lives))) =
+            (decls.get(&pointee), decls.get(&pointer))
+        else {
+            continue;
+        };
+        let Some((end, what)) = value_end(func, pointee, created.until()) else {
+            continue;
+        };
+        let Some(used) = pointer_lives
+            .iter()
+            .filter(|range| end.until() < range.until())
+            .min_by_key(|range| range.from())
+        else {
+            continue;
+        };
+        if lints.iter().any(|lint| lint.range == created) {
+            continue;
+        }
+        lints.push(Lint {
+            code: "dangling-pointer",
+            range: created,
+            message: format!("raw pointer to {name} is still used after {name} {what}"),
+            related: vec![
+                (end, format!("{name} {what} here")),
+                (*used, "pointer still live here".to_owned()),
+            ],
+            fix: None,
+        });
+    }
+    lints.sort_by_key(|lint| lint.range.from());
+    lints
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{MirBasicBlock, NeedlessClone};
+
+    fn r(from: u32, until: u32) -> Range {
+        Range::new(Loc::from(from), Loc::from(until)).unwrap()
+    }
+
+    fn local(id: u32) -> FnLocal {
+        FnLocal::new(id, 0)
+    }
+
+    fn call(dest: u32, fn_span: Range, args: &[u32], successors: Vec<u32>) -> MirBasicBlock {
+        MirBasicBlock {
+            terminator: Some(MirTerminator::Call {
+                destination_local: local(dest),
+                fn_span,
+                args: args.iter().copied().map(local).collect(),
+                callee: String::new(),
+                summary: None,
+            }),
+            successors,
+            ..Default::default()
+        }
+    }
+
+    fn with_callee(mut bb: MirBasicBlock, path: &str) -> MirBasicBlock {
+        if let Some(MirTerminator::Call { callee, .. }) = &mut bb.terminator {
+            *callee = path.to_owned();
+        }
+        bb
+    }
+
+    fn guard_func(blocks: Vec<MirBasicBlock>) -> Function {
+        Function {
+            decls: vec![MirDecl::User {
+                local: local(1),
+                name: "g".into(),
+                span: r(4, 5),
+                ty: "std::sync::MutexGuard<'_, i32>".into(),
+                lives: vec![],
+                shared_borrow: vec![],
+                mutable_borrow: vec![],
+                drop: true,
+                drop_range: vec![],
+                must_live_at: vec![],
+                size: None,
+                binding: None,
+            }],
+            basic_blocks: blocks,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn guard_kind_of_types() {
+        assert_eq!(
+            guard_kind("std::sync::MutexGuard<'_, i32>"),
+            Some("lock guard")
+        );
+        assert_eq!(
+            guard_kind("tokio::sync::OwnedRwLockWriteGuard<i32>"),
+            Some("lock guard")
+        );
+        assert_eq!(
+            guard_kind("std::cell::RefMut<'_, i32>"),
+            Some("`RefCell` borrow")
+        );
+        assert_eq!(guard_kind("&std::sync::MutexGuard<'_, i32>"), None);
+        assert_eq!(guard_kind("my::Ref<i32>"), None);
+    }
+
+    #[test]
+    fn guard_held_across_unrelated_call() {
+        let func = guard_func(vec![
+            // g = m.lock()
+            call(1, r(8, 16), &[], vec![1]),
+            MirBasicBlock {
+                statements: vec![MirStatement::Assign {
+                    target_local: local(2),
+                    range: r(20, 21),
+                    rval: Some(MirRval::Borrow {
+                        target_local: local(1),
+                        range: r(20, 21),
+                        mutable: false,
+                        outlive: None,
+                    }),
+                }],
+                // g.len() uses the guard
+                ..call(3, r(20, 27), &[2], vec![2, 5])
+            },
+            // work() does not
+            call(4, r(30, 36), &[], vec![3, 5]),
+            MirBasicBlock {
+                terminator: Some(MirTerminator::Drop {
+                    local: local(1),
+                    range: r(40, 41),
+                    state: DropState::Dropped,
+                }),
+                successors: vec![4],
+                ..Default::default()
+            },
+            MirBasicBlock::default(),
+            MirBasicBlock {
+                cleanup: true,
+                ..call(5, r(50, 55), &[], vec![])
+            },
+        ]);
+        let holds = guard_holds(&func, local(1));
+        assert_eq!(holds.len(), 1);
+        assert_eq!(holds[0].range, r(8, 41));
+        assert_eq!(holds[0].across, vec![(HeldAcross::Call, r(30, 36))]);
+
+        let lints = guard_lints(&func, &LintOptions::default());
+        assert_eq!(lints.len(), 1);
+        assert_eq!(lints[0].range, r(4, 41), "highlighted from the binding");
+        assert_eq!(
+            lints[0].message,
+            "lock guard `g` of type `std::sync::MutexGuard<'_, i32>` is held across a call"
+        );
+    }
+
+    #[test]
+    fn guard_blocking_calls_only_skips_other_calls() {
+        let func = guard_func(vec![
+            call(1, r(8, 16), &[], vec![1]),
+            // *g += compute(x)
+            with_callee(call(2, r(20, 30), &[], vec![2]), "my_crate::compute"),
+            with_callee(call(3, r(32, 38), &[], vec![3]), "std::thread::sleep"),
+            MirBasicBlock {
+                terminator: Some(MirTerminator::Drop {
+                    local: local(1),
+                    range: r(40, 41),
+                    state: DropState::Dropped,
+                }),
+                ..Default::default()
+            },
+        ]);
+        let lints = guard_lints(&func, &LintOptions::default());
+        assert_eq!(lints.len(), 1);
+        assert_eq!(lints[0].related.len(), 2);
+
+        let options = LintOptions {
+            guard_blocking_calls_only: true,
+            ..LintOptions::default()
+        };
+        let lints = guard_lints(&func, &options);
+        assert_eq!(lints.len(), 1);
+        assert_eq!(
+            lints[0].related,
+            vec![(r(32, 38), "called while the guard is held".to_owned())]
+        );
+    }
+
+    #[test]
+    fn guard_released_before_loop() {
+        let func = guard_func(vec![
+            call(1, r(8, 16), &[], vec![1]),
+            // drop(g)
+            call(2, r(20, 27), &[1], vec![2]),
+            MirBasicBlock {
+                terminator: Some(MirTerminator::Loop { range: r(30, 60) }),
+                successors: vec![3],
+                ..Default::default()
+            },
+            call(3, r(40, 46), &[], vec![2]),
+        ]);
+        assert!(guard_lints(&func, &LintOptions::default()).is_empty());
+    }
+
+    #[test]
+    fn guard_held_across_loop_and_await() {
+        let func = guard_func(vec![
+            call(1, r(8, 16), &[], vec![1]),
+            MirBasicBlock {
+                terminator: Some(MirTerminator::Loop { range: r(30, 60) }),
+                successors: vec![2, 3],
+                ..Default::default()
+            },
+            MirBasicBlock {
+                terminator: Some(MirTerminator::Yield { range: r(40, 46) }),
+                successors: vec![1],
+                ..Default::default()
+            },
+            MirBasicBlock {
+                terminator: Some(MirTerminator::Drop {
+                    local: local(1),
+                    range: r(70, 71),
+                    state: DropState::Dropped,
+                }),
+                ..Default::default()
+            },
+        ]);
+        let lints = guard_lints(&func, &LintOptions::default());
+        assert_eq!(lints.len(), 1);
+        assert!(
+            lints[0]
+                .message
+                .ends_with("held across a loop and an `.await`"),
+            "unexpected message: {}",
+            lints[0].message
+        );
+        assert_eq!(lints[0].range, r(4, 71));
+        assert_eq!(lints[0].related.len(), 2);
+    }
+
+    #[test]
+    fn needless_clone_quick_fix_moves_variable() {
+        let text = "let t = s.clone();";
+        let func = Function {
+            needless_clones: vec![NeedlessClone {
+                local: local(1),
+                name: "s".into(),
+                method: "clone".into(),
+                range: r(8, 17),
+            }],
+            ..Default::default()
+        };
+        let lints = lints(&func, &LintOptions::default());
+        assert_eq!(lints.len(), 1);
+        assert_eq!(lints[0].code, "needless-clone");
+
+        let uri = lsp_types::Url::parse("file:///test.rs").unwrap();
+        let action = lints[0].to_code_action(text, &uri).unwrap();
+        assert_eq!(action.kind, Some(lsp_types::CodeActionKind::QUICKFIX));
+        let edits = &action.edit.unwrap().changes.unwrap()[&uri];
+        assert_eq!(edits.len(), 1);
+        assert_eq!(edits[0].new_text, "s");
+        assert_eq!(edits[0].range.start.character, 8);
+        assert_eq!(edits[0].range.end.character, 17);
+    }
+
+    #[test]
+    fn large_copies_use_lower_threshold_in_loops() {
+        let buf = |id: u32, name: &str, size: u64| MirDecl::User {
+            local: local(id),
+            name: name.into(),
+            span: r(0, 1),
+            ty: format!("[u8; {size}]"),
+            lives: vec![],
+            shared_borrow: vec![],
+            mutable_borrow: vec![],
+            drop: false,
+            drop_range: vec![],
+            must_live_at: vec![],
+            size: Some(size),
+            binding: None,
+        };
+        let copy = |id: u32, range: Range| MirStatement::Assign {
+            target_local: local(9),
+            range,
+            rval: Some(MirRval::Copy {
+                target_local: local(id),
+                range,
+            }),
+        };
+        // bb0: copies `big` and `small`; bb1 loops and copies `small`
+        let func = Function {
+            decls: vec![buf(1, "big", 1024), buf(2, "small", 200)],
+            basic_blocks: vec![
+                MirBasicBlock {
+                    statements: vec![copy(1, r(10, 13)), copy(2, r(20, 25))],
+                    successors: vec![1],
+                    ..Default::default()
+                },
+                MirBasicBlock {
+                    statements: vec![copy(2, r(40, 45))],
+                    terminator: Some(MirTerminator::Loop { range: r(30, 50) }),
+                    successors: vec![1],
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+        let lints = large_copy_lints(&func, &LintOptions::default());
+        let ranges: Vec<_> = lints.iter().map(|lint| lint.range).collect();
+        assert_eq!(ranges, vec![r(10, 13), r(40, 45)]);
+        assert_eq!(lints[0].message, "copy of `big` copies 1024 bytes");
+        assert_eq!(
+            lints[1].message,
+            "copy of `small` copies 200 bytes in a loop"
+        );
+
+        let options = LintOptions {
+            large_copy_threshold: 2048,
+            large_copy_in_loop_threshold: 2048,
+            ..LintOptions::default()
+        };
+        assert!(large_copy_lints(&func, &options).is_empty());
+    }
+
+    #[test]
+    fn guard_held_after_last_use_gets_drop_fix() {
+        let text =
+            "fn f() {\n    let g = m.lock().unwrap();\n    g.len(); // last\n    work();\n}\n";
+        let mut func = guard_func(vec![
+            call(2, r(44, 51), &[], vec![1]),
+            call(3, r(65, 71), &[], vec![2]),
+            MirBasicBlock {
+                terminator: Some(MirTerminator::Drop {
+                    local: local(1),
+                    range: r(73, 74),
+                    state: DropState::Dropped,
+                }),
+                ..Default::default()
+            },
+        ]);
+        func.statements = vec![r(13, 39), r(44, 52), r(65, 72)];
+        if let MirDecl::User { span, lives, .. } = &mut func.decls[0] {
+            *span = r(17, 18);
+            *lives = vec![r(39, 51)];
+        }
+        let lints = held_too_long_lints(&func, &LintOptions::default());
+        assert_eq!(lints.len(), 1);
+        assert_eq!(lints[0].range, r(17, 18));
+        assert_eq!(
+            lints[0].message,
+            "lock guard `g` is held across a call after its last use; drop it earlier or move it \
+             into an inner scope"
+        );
+        assert_eq!(lints[0].related.len(), 3);
+
+        let uri = lsp_types::Url::parse("file:///test.rs").unwrap();
+        let action = lints[0].to_code_action(text, &uri).unwrap();
+        assert_eq!(action.title, "Insert `drop(g);`");
+        let edits = &action.edit.unwrap().changes.unwrap()[&uri];
+        assert_eq!(edits[0].new_text, "\n    drop(g);");
+        assert_eq!(edits[0].range.start, lsp_types::Position::new(2, 20));
+        assert_eq!(edits[0].range.start, edits[0].range.end);
+
+        // other values are only reported when held across loops or `.await`s
+        if let MirDecl::User { ty, .. } = &mut func.decls[0] {
+            *ty = "std::string::String".into();
+        }
+        assert!(held_too_long_lints(&func, &LintOptions::default()).is_empty());
+    }
+
+    #[test]
+    fn raw_pointer_live_after_pointee_goes_out_of_scope() {
+        let var = |id: u32, name: &str, ty: &str, lives: Vec<Range>| MirDecl::User {
+            local: local(id),
+            name: name.into(),
+            span: r(0, 1),
+            ty: ty.into(),
+            lives,
+            shared_borrow: vec![],
+            mutable_borrow: vec![],
+            drop: false,
+            drop_range: vec![],
+            must_live_at: vec![],
+            size: None,
+            binding: None,
+        };
+        let mut func = Function {
+            decls: vec![
+                var(1, "x", "i32", vec![r(10, 15)]),
+                var(2, "p", "*const i32", vec![r(16, 26), r(30, 32)]),
+            ],
+            basic_blocks: vec![MirBasicBlock {
+                statements: vec![
+                    MirStatement::Assign {
+                        target_local: local(2),
+                        range: r(10, 15),
+                        rval: Some(MirRval::RawPtr {
+                            target_local: local(1),
+                            range: r(10, 15),
+                            mutable: false,
+                        }),
+                    },
+                    MirStatement::StorageDead {
+                        target_local: local(1),
+                        range: r(25, 26),
+                    },
+                ],
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let lints = dangling_pointer_lints(&func);
+        assert_eq!(lints.len(), 1);
+        assert_eq!(lints[0].range, r(10, 15));
+        assert_eq!(
+            lints[0].message,
+            "raw pointer to `x` is still used after `x` goes out of scope"
+        );
+        assert_eq!(lints[0].related[1].0, r(30, 32));
+
+        func.decls[1] = var(2, "p", "*const i32", vec![r(16, 26)]);
+        assert!(dangling_pointer_lints(&func).is_empty());
+    }
+}
@@ -1,11 +1,21 @@
-use std::{collections::HashSet, mem, path::PathBuf};
+use std::{
+    collections::{HashMap, HashSet},
+    mem,
+    path::PathBuf,
+};
 
 use tower_lsp::lsp_types;
 
 use crate::{
     lsp_progress::AnalysisStatus,
-    models::{FnLocal, Loc, MirDecl, MirRval, MirStatement, MirTerminator, Range},
-    range_ops, text_conversion,
+    mir_transform,
+    models::{
+        FnLocal, FnSummary, Function, Loc, MacroCall, MirDecl, MirRval, MirStatement,
+        MirTerminator, Range,
+    },
+    range_ops,
+    runtime_borrow::{self, RuntimeBorrowKind, RuntimeBorrowTable},
+    text_conversion,
 };
 
 impl<R> Deco<R> {
@@ -18,17 +28,26 @@ impl<R> Deco<R> {
     /// Returns the diagnostic severity for this decoration type.
     /// Each type gets a distinct severity for better visual differentiation:
     /// - Outlive -> Error (red - critical ownership issues)
-    /// - `SharedMut`, Move -> Warning (yellow/orange - ownership/aliasing)
-    /// - `MutBorrow`, Call -> Information (blue - mutable access/calls)
-    /// - `ImmBorrow`, Lifetime -> Hint (gray/dim - immutable borrow info)
+    /// - `SharedMut`, Move, `DynBorrowConflict` -> Warning (yellow/orange -
+    ///   ownership/aliasing)
+    /// - `MutBorrow`, Call, `DynMutBorrow`, `RawPtr` -> Information (blue -
+    ///   mutable access/calls)
+    /// - `ImmBorrow`, Lifetime, `LifetimeParam`, `DynSharedBorrow` -> Hint
+    ///   (gray/dim - immutable borrow info)
     pub const fn diagnostic_severity(&self) -> lsp_types::DiagnosticSeverity {
         match self {
             Self::Outlive { .. } => lsp_types::DiagnosticSeverity::ERROR,
-            Self::SharedMut { .. } | Self::Move { .. } => lsp_types::DiagnosticSeverity::WARNING,
-            Self::MutBorrow { .. } | Self::Call { .. } => {
-                lsp_types::DiagnosticSeverity::INFORMATION
+            Self::SharedMut { .. } | Self::Move { .. } | Self::DynBorrowConflict { .. } => {
+                lsp_types::DiagnosticSeverity::WARNING
             }
-            Self::ImmBorrow { .. } | Self::Lifetime { .. } => lsp_types::DiagnosticSeverity::HINT,
+            Self::MutBorrow { .. }
+            | Self::Call { .. }
+            | Self::DynMutBorrow { .. }
+            | Self::RawPtr { .. } => lsp_types::DiagnosticSeverity::INFORMATION,
+            Self::ImmBorrow { .. }
+            | Self::Lifetime { .. }
+            | Self::LifetimeParam { .. }
+            | Self::DynSharedBorrow { .. } => lsp_types::DiagnosticSeverity::HINT,
         }
     }
 
@@ -41,7 +60,31 @@ impl<R> Deco<R> {
             | Self::Move { hover_text, .. }
             | Self::Call { hover_text, .. }
             | Self::SharedMut { hover_text, .. }
-            | Self::Outlive { hover_text, .. } => hover_text,
+            | Self::Outlive { hover_text, .. }
+            | Self::LifetimeParam { hover_text, .. }
+            | Self::DynSharedBorrow { hover_text, .. }
+            | Self::DynMutBorrow { hover_text, .. }
+            | Self::DynBorrowConflict { hover_text, .. }
+            | Self::RawPtr { hover_text, .. } => hover_text,
+        }
+    }
+
+    /// The `type` of this decoration, e.g. `imm_borrow`
+    #[must_use]
+    pub const fn kind(&self) -> &'static str {
+        match self {
+            Self::Lifetime { .. } => "lifetime",
+            Self::ImmBorrow { .. } => "imm_borrow",
+            Self::MutBorrow { .. } => "mut_borrow",
+            Self::Move { .. } => "move",
+            Self::Call { .. } => "call",
+            Self::SharedMut { .. } => "shared_mut",
+            Self::Outlive { .. } => "outlive",
+            Self::LifetimeParam { .. } => "lifetime_param",
+            Self::DynSharedBorrow { .. } => "dyn_shared_borrow",
+            Self::DynMutBorrow { .. } => "dyn_mut_borrow",
+            Self::DynBorrowConflict { .. } => "dyn_borrow_conflict",
+            Self::RawPtr { .. } => "raw_ptr",
         }
     }
 
@@ -56,6 +99,11 @@ impl<R> Deco<R> {
             Self::Call { .. } => format!("{pkg}:call"),
             Self::SharedMut { .. } => format!("{pkg}:shared-mut"),
             Self::Outlive { .. } => format!("{pkg}:outlive"),
+            Self::LifetimeParam { .. } => format!("{pkg}:lifetime-param"),
+            Self::DynSharedBorrow { .. } => format!("{pkg}:dyn-shared-borrow"),
+            Self::DynMutBorrow { .. } => format!("{pkg}:dyn-mut-borrow"),
+            Self::DynBorrowConflict { .. } => format!("{pkg}:dyn-borrow-conflict"),
+            Self::RawPtr { .. } => format!("{pkg}:raw-ptr"),
         }
     }
 }
@@ -71,7 +119,12 @@ impl Deco<lsp_types::Range> {
             | Self::Move { range, .. }
             | Self::Call { range, .. }
             | Self::SharedMut { range, .. }
-            | Self::Outlive { range, .. } => *range,
+            | Self::Outlive { range, .. }
+            | Self::LifetimeParam { range, .. }
+            | Self::DynSharedBorrow { range, .. }
+            | Self::DynMutBorrow { range, .. }
+            | Self::DynBorrowConflict { range, .. }
+            | Self::RawPtr { range, .. } => *range,
         };
 
         lsp_types::Diagnostic {
@@ -140,6 +193,40 @@ pub enum Deco<R = Range> {
         hover_text: String,
         overlapped: bool,
     },
+    LifetimeParam {
+        local: FnLocal,
+        range: R,
+        hover_text: String,
+        overlapped: bool,
+    },
+    /// Shared borrow checked at runtime, e.g. `RefCell::borrow`
+    DynSharedBorrow {
+        local: FnLocal,
+        range: R,
+        hover_text: String,
+        overlapped: bool,
+    },
+    /// Mutable borrow checked at runtime, e.g. `RefCell::borrow_mut`
+    DynMutBorrow {
+        local: FnLocal,
+        range: R,
+        hover_text: String,
+        overlapped: bool,
+    },
+    /// Runtime borrow made while a conflicting one is held
+    DynBorrowConflict {
+        local: FnLocal,
+        range: R,
+        hover_text: String,
+        overlapped: bool,
+    },
+    /// Raw pointer derived from the local, e.g. `&raw mut x`
+    RawPtr {
+        local: FnLocal,
+        range: R,
+        hover_text: String,
+        overlapped: bool,
+    },
 }
 impl Deco<Range> {
     fn convert_range(s: &str, range: Range) -> lsp_types::Range {
@@ -165,7 +252,12 @@ impl Deco<Range> {
             | Self::Move { range, .. }
             | Self::Call { range, .. }
             | Self::SharedMut { range, .. }
-            | Self::Outlive { range, .. } => *range,
+            | Self::Outlive { range, .. }
+            | Self::LifetimeParam { range, .. }
+            | Self::DynSharedBorrow { range, .. }
+            | Self::DynMutBorrow { range, .. }
+            | Self::DynBorrowConflict { range, .. }
+            | Self::RawPtr { range, .. } => *range,
         }
     }
 
@@ -191,6 +283,21 @@ impl Deco<Range> {
             }
             | Self::Outlive {
                 range, overlapped, ..
+            }
+            | Self::LifetimeParam {
+                range, overlapped, ..
+            }
+            | Self::DynSharedBorrow {
+                range, overlapped, ..
+            }
+            | Self::DynMutBorrow {
+                range, overlapped, ..
+            }
+            | Self::DynBorrowConflict {
+                range, overlapped, ..
+            }
+            | Self::RawPtr {
+                range, overlapped, ..
             } => (*range, *overlapped),
         }
     }
@@ -253,87 +360,148 @@ impl Deco<Range> {
                 hover_text: hover_text.clone(),
                 overlapped,
             },
+            Self::LifetimeParam {
+                local, hover_text, ..
+            } => Self::LifetimeParam {
+                local: *local,
+                range: new_range,
+                hover_text: hover_text.clone(),
+                overlapped,
+            },
+            Self::DynSharedBorrow {
+                local, hover_text, ..
+            } => Self::DynSharedBorrow {
+                local: *local,
+                range: new_range,
+                hover_text: hover_text.clone(),
+                overlapped,
+            },
+            Self::DynMutBorrow {
+                local, hover_text, ..
+            } => Self::DynMutBorrow {
+                local: *local,
+                range: new_range,
+                hover_text: hover_text.clone(),
+                overlapped,
+            },
+            Self::DynBorrowConflict {
+                local, hover_text, ..
+            } => Self::DynBorrowConflict {
+                local: *local,
+                range: new_range,
+                hover_text: hover_text.clone(),
+                overlapped,
+            },
+            Self::RawPtr {
+                local, hover_text, ..
+            } => Self::RawPtr {
+                local: *local,
+                range: new_range,
+                hover_text: hover_text.clone(),
+                overlapped,
+            },
         }
     }
 
     #[must_use]
     pub fn to_lsp_range(&self, s: &str) -> Deco<lsp_types::Range> {
-        match self.clone() {
+        let (range, overlapped) = self.range_and_overlapped();
+        let range = Self::convert_range(s, range);
+        match self {
             Self::Lifetime {
-                local,
-                range,
-                hover_text,
-                overlapped,
+                local, hover_text, ..
             } => Deco::Lifetime {
-                local,
-                range: Self::convert_range(s, range),
-                hover_text,
+                local: *local,
+                range,
+                hover_text: hover_text.clone(),
                 overlapped,
             },
             Self::ImmBorrow {
-                local,
-                range,
-                hover_text,
-                overlapped,
+                local, hover_text, ..
             } => Deco::ImmBorrow {
-                local,
-                range: Self::convert_range(s, range),
-                hover_text,
+                local: *local,
+                range,
+                hover_text: hover_text.clone(),
                 overlapped,
             },
             Self::MutBorrow {
-                local,
-                range,
-                hover_text,
-                overlapped,
+                local, hover_text, ..
             } => Deco::MutBorrow {
-                local,
-                range: Self::convert_range(s, range),
-                hover_text,
+                local: *local,
+                range,
+                hover_text: hover_text.clone(),
                 overlapped,
             },
             Self::Move {
-                local,
-                range,
-                hover_text,
-                overlapped,
+                local, hover_text, ..
             } => Deco::Move {
-                local,
-                range: Self::convert_range(s, range),
-                hover_text,
+                local: *local,
+                range,
+                hover_text: hover_text.clone(),
                 overlapped,
             },
             Self::Call {
-                local,
-                range,
-                hover_text,
-                overlapped,
+                local, hover_text, ..
             } => Deco::Call {
-                local,
-                range: Self::convert_range(s, range),
-                hover_text,
+                local: *local,
+                range,
+                hover_text: hover_text.clone(),
                 overlapped,
             },
             Self::SharedMut {
-                local,
-                range,
-                hover_text,
-                overlapped,
+                local, hover_text, ..
             } => Deco::SharedMut {
-                local,
-                range: Self::convert_range(s, range),
-                hover_text,
+                local: *local,
+                range,
+                hover_text: hover_text.clone(),
                 overlapped,
             },
             Self::Outlive {
-                local,
+                local, hover_text, ..
+            } => Deco::Outlive {
+                local: *local,
                 range,
-                hover_text,
+                hover_text: hover_text.clone(),
                 overlapped,
-            } => Deco::Outlive {
-                local,
-                range: Self::convert_range(s, range),
-                hover_text,
+            },
+            Self::LifetimeParam {
+                local, hover_text, ..
+            } => Deco::LifetimeParam {
+                local: *local,
+                range,
+                hover_text: hover_text.clone(),
+                overlapped,
+            },
+            Self::DynSharedBorrow {
+                local, hover_text, ..
+            } => Deco::DynSharedBorrow {
+                local: *local,
+                range,
+                hover_text: hover_text.clone(),
+                overlapped,
+            },
+            Self::DynMutBorrow {
+                local, hover_text, ..
+            } => Deco::DynMutBorrow {
+                local: *local,
+                range,
+                hover_text: hover_text.clone(),
+                overlapped,
+            },
+            Self::DynBorrowConflict {
+                local, hover_text, ..
+            } => Deco::DynBorrowConflict {
+                local: *local,
+                range,
+                hover_text: hover_text.clone(),
+                overlapped,
+            },
+            Self::RawPtr {
+                local, hover_text, ..
+            } => Deco::RawPtr {
+                local: *local,
+                range,
+                hover_text: hover_text.clone(),
                 overlapped,
             },
         }
@@ -371,6 +539,7 @@ enum SelectReason {
     Move,
     Borrow,
     Call,
+    Temp,
 }
 #[derive(Clone, Debug)]
 pub struct SelectLocal {
@@ -395,13 +564,20 @@ impl SelectLocal {
         if range.from() <= self.pos && self.pos <= range.until() {
             if let Some((old_reason, _, old_range)) = self.selected {
                 match (old_reason, reason) {
+                    // a call selects its destination, prefer the temporary itself
+                    (SelectReason::Call, SelectReason::Temp) => {
+                        if range.size() <= old_range.size() {
+                            self.selected = Some((reason, local, range));
+                        }
+                    }
                     (_, SelectReason::Var) => {
                         if range.size() < old_range.size() {
                             self.selected = Some((reason, local, range));
                         }
                     }
                     (SelectReason::Var, _) => {}
-                    (_, SelectReason::Move | SelectReason::Borrow) => {
+                    (_, SelectReason::Move | SelectReason::Borrow)
+                    | (SelectReason::Temp, SelectReason::Temp) => {
                         if range.size() < old_range.size() {
                             self.selected = Some((reason, local, range));
                         }
@@ -434,8 +610,20 @@ impl range_ops::MirVisitor for SelectLocal {
             return;
         }
         self.candidate_local_decls.push(*local);
-        if let MirDecl::User { local, span, .. } = decl {
-            self.select(SelectReason::Var, *local, *span);
+        match decl {
+            MirDecl::User { local, span, .. } => {
+                self.select(SelectReason::Var, *local, *span);
+            }
+            // temporaries without destructor are short-lived and not worth selecting
+            MirDecl::Other {
+                local,
+                drop: true,
+                temporary: Some(temporary),
+                ..
+            } => {
+                self.select(SelectReason::Temp, *local, temporary.span);
+            }
+            MirDecl::Other { .. } => {}
         }
     }
     fn visit_stmt(&mut self, stmt: &MirStatement) {
@@ -447,11 +635,18 @@ impl range_ops::MirVisitor for SelectLocal {
                 }) => {
                     self.select(SelectReason::Move, *target_local, *range);
                 }
-                Some(MirRval::Borrow {
-                    target_local,
-                    range,
-                    ..
-                }) => {
+                Some(
+                    MirRval::Borrow {
+                        target_local,
+                        range,
+                        ..
+                    }
+                    | MirRval::RawPtr {
+                        target_local,
+                        range,
+                        ..
+                    },
+                ) => {
                     self.select(SelectReason::Borrow, *target_local, *range);
                 }
                 _ => {}
@@ -462,15 +657,214 @@ impl range_ops::MirVisitor for SelectLocal {
         if let MirTerminator::Call {
             destination_local,
             fn_span,
+            ..
         } = term
         {
             self.select(SelectReason::Call, *destination_local, *fn_span);
         }
     }
 }
+/// Selects the lifetime parameter under the cursor
+#[derive(Clone, Debug)]
+pub struct SelectLifetime {
+    pos: Loc,
+    selected: Option<(u32, String, Range)>,
+    /// Function id, name and spans of the parameters of the visited functions
+    params: Vec<(u32, String, Vec<Range>)>,
+}
+impl SelectLifetime {
+    #[must_use]
+    pub const fn new(pos: Loc) -> Self {
+        Self {
+            pos,
+            selected: None,
+            params: Vec::new(),
+        }
+    }
+
+    /// Returns the ids of the functions sharing the selected lifetime, like
+    /// the methods of an impl declaring it, and its name
+    #[must_use]
+    pub fn selected(&self) -> Option<(Vec<u32>, String)> {
+        let (fn_id, name, _) = self.selected.as_ref()?;
+        let (.., spans) = self
+            .params
+            .iter()
+            .find(|(id, param, _)| id == fn_id && param == name)?;
+        let fn_ids = self
+            .params
+            .iter()
+            .filter(|(_, param, other)| {
+                param == name && other.iter().any(|range| spans.contains(range))
+            })
+            .map(|(id, ..)| *id)
+            .collect();
+        Some((fn_ids, name.clone()))
+    }
+}
+impl range_ops::MirVisitor for SelectLifetime {
+    fn visit_func(&mut self, func: &Function) {
+        for param in &func.lifetimes {
+            self.params
+                .push((func.fn_id, param.name.clone(), param.spans.clone()));
+            for range in &param.spans {
+                if range.from() <= self.pos
+                    && self.pos <= range.until()
+                    && self
+                        .selected
+                        .as_ref()
+                        .is_none_or(|(_, _, old)| range.size() < old.size())
+                {
+                    self.selected = Some((func.fn_id, param.name.clone(), *range));
+                }
+            }
+        }
+    }
+}
+
+/// Names of the variables that the locals of `func` are borrowed, moved or
+/// copied from, to name the arguments of calls
+fn argument_names(func: &Function) -> HashMap<FnLocal, String> {
+    let names: HashMap<_, _> = func
+        .decls
+        .iter()
+        .filter_map(|decl| match decl {
+            MirDecl::User { local, name, .. } => Some((*local, name.clone())),
+            MirDecl::Other { .. } => None,
+        })
+        .collect();
+    let sources: HashMap<_, _> = func
+        .basic_blocks
+        .iter()
+        .flat_map(|bb| &bb.statements)
+        .filter_map(|stmt| match stmt {
+            MirStatement::Assign {
+                target_local,
+                rval:
+                    Some(
+                        MirRval::Borrow {
+                            target_local: from, ..
+                        }
+                        | MirRval::Move {
+                            target_local: from, ..
+                        }
+                        | MirRval::Copy {
+                            target_local: from, ..
+                        },
+                    ),
+                ..
+            } => Some((*target_local, *from)),
+            _ => None,
+        })
+        .collect();
+    sources
+        .keys()
+        .chain(names.keys())
+        .filter_map(|local| {
+            let mut visited = HashSet::new();
+            let mut current = *local;
+            while !names.contains_key(&current) {
+                if !visited.insert(current) {
+                    return None;
+                }
+                current = *sources.get(&current)?;
+            }
+            Some((*local, names[&current].clone()))
+        })
+        .collect()
+}
+
+/// The summaries of the analyzed `functions`, derived from their bodies,
+/// by the path their calls name them with
+#[must_use]
+pub fn callee_summaries<'a>(
+    functions: impl IntoIterator<Item = &'a Function>,
+) -> HashMap<String, FnSummary> {
+    functions
+        .into_iter()
+        .filter_map(|func| {
+            let summary = func.summary.clone()?;
+            Some((mir_transform::callee_path(&func.name), summary))
+        })
+        .collect()
+}
+
+/// The summary of a call of `callee`, taken from the body of the callee if
+/// it was analyzed and from its signature otherwise
+fn call_summary(
+    summary: Option<&FnSummary>,
+    callee: &str,
+    callees: &HashMap<String, FnSummary>,
+) -> Option<FnSummary> {
+    let summary = summary?;
+    Some(
+        callees
+            .get(callee)
+            .map_or_else(|| summary.clone(), |body| summary.with_body_of(body)),
+    )
+}
+
+/// Hover text of a call, explaining what the callee does with `args`
+fn call_hover_text(
+    summary: Option<&FnSummary>,
+    args: &[FnLocal],
+    names: &HashMap<FnLocal, String>,
+) -> String {
+    let args: Vec<_> = args
+        .iter()
+        .map(|arg| names.get(arg).map(String::as_str))
+        .collect();
+    let description = summary.map(|summary| summary.describe(&args));
+    match description {
+        Some(description) if !description.is_empty() => format!("function call: {description}"),
+        _ => "function call".to_owned(),
+    }
+}
+
+/// The innermost call of `func` at `pos` and what it does with its
+/// arguments, according to the [`callee_summaries`] `callees`
+#[must_use]
+pub fn call_hover(
+    func: &Function,
+    pos: Loc,
+    callees: &HashMap<String, FnSummary>,
+) -> Option<(Range, String)> {
+    let (fn_span, args, callee, summary) = func
+        .basic_blocks
+        .iter()
+        .filter_map(|bb| match &bb.terminator {
+            Some(MirTerminator::Call {
+                fn_span,
+                args,
+                callee,
+                summary,
+                ..
+            }) if fn_span.from() <= pos && pos <= fn_span.until() => {
+                Some((*fn_span, args, callee, summary))
+            }
+            _ => None,
+        })
+        .min_by_key(|(fn_span, ..)| fn_span.size())?;
+    let names = argument_names(func);
+    let summary = call_summary(summary.as_ref(), callee, callees);
+    Some((fn_span, call_hover_text(summary.as_ref(), args, &names)))
+}
+
 #[derive(Clone, Debug)]
 pub struct CalcDecos {
     locals: HashSet<FnLocal>,
+    lifetime: Option<(Vec<u32>, String)>,
+    runtime_borrows: RuntimeBorrowTable,
+    /// Calls of the current function decorated as runtime borrows
+    runtime_borrow_calls: Vec<Range>,
+    /// Sizes in bytes of the locals with a known layout
+    sizes: HashMap<FnLocal, u64>,
+    /// Variables the locals of the current function are taken from
+    argument_names: HashMap<FnLocal, String>,
+    /// [`callee_summaries`] of the analyzed functions
+    callees: HashMap<String, FnSummary>,
+    /// Macro calls of the current function
+    macro_calls: Vec<MacroCall>,
     decorations: Vec<Deco>,
     current_fn_id: u32,
 }
@@ -478,11 +872,177 @@ impl CalcDecos {
     pub fn new(locals: impl IntoIterator<Item = FnLocal>) -> Self {
         Self {
             locals: locals.into_iter().collect(),
+            lifetime: None,
+            runtime_borrows: RuntimeBorrowTable::default(),
+            runtime_borrow_calls: Vec::new(),
+            sizes: HashMap::new(),
+            argument_names: HashMap::new(),
+            callees: HashMap::new(),
+            macro_calls: Vec::new(),
             decorations: Vec::new(),
             current_fn_id: 0,
         }
     }
 
+    /// Describe a borrow or move at `range` made by the expansion of a macro
+    /// call rather than by an argument written in it
+    fn macro_text(&self, range: Range, verb: &str) -> Option<String> {
+        self.macro_calls
+            .iter()
+            .find(|call| call.range == range || call.formatted.contains(&range))
+            .map(|call| call.describe(range, verb))
+    }
+
+    /// Recognize calls to the APIs of `table` as runtime borrows
+    #[must_use]
+    pub fn with_runtime_borrows(mut self, table: RuntimeBorrowTable) -> Self {
+        self.runtime_borrows = table;
+        self
+    }
+
+    /// Describe calls of the analyzed functions with the [`callee_summaries`]
+    /// `callees`
+    #[must_use]
+    pub fn with_callees(mut self, callees: HashMap<String, FnSummary>) -> Self {
+        self.callees = callees;
+        self
+    }
+
+    /// Also decorate the references and points related to a lifetime
+    /// parameter shared by the functions `fn_ids`
+    #[must_use]
+    pub fn with_lifetime(mut self, fn_ids: Vec<u32>, name: String) -> Self {
+        self.lifetime = Some((fn_ids, name));
+        self
+    }
+
+    fn lifetime_decos(&mut self, func: &Function, name: &str) {
+        for param in func.lifetimes.iter().filter(|param| param.name == name) {
+            for related in &param.locals {
+                let var_name = func.decls.iter().find_map(|decl| match decl {
+                    MirDecl::User {
+                        local, name, span, ..
+                    } if *local == related.local => Some((name, *span)),
+                    _ => None,
+                });
+                if let Some((var_name, span)) = var_name {
+                    self.decorations.push(Deco::LifetimeParam {
+                        local: related.local,
+                        range: span,
+                        hover_text: format!(
+                            "`{var_name}` holds a reference bound by lifetime `{name}`"
+                        ),
+                        overlapped: false,
+                    });
+                }
+                let reason = var_name.map_or_else(
+                    || "a temporary".to_owned(),
+                    |(var_name, _)| format!("`{var_name}`"),
+                );
+                for range in &related.must_hold_at {
+                    self.decorations.push(Deco::LifetimeParam {
+                        local: related.local,
+                        range: *range,
+                        hover_text: format!("lifetime `{name}` must hold here because of {reason}"),
+                        overlapped: false,
+                    });
+                }
+            }
+        }
+    }
+
+    fn runtime_borrow_decos(&mut self, func: &Function) {
+        let borrows = runtime_borrow::runtime_borrows(func, &self.runtime_borrows);
+        let name_of = |local: FnLocal| {
+            func.decls.iter().find_map(|decl| match decl {
+                MirDecl::User {
+                    local: decl_local,
+                    name,
+                    ..
+                } if *decl_local == local => Some(format!("`{name}`")),
+                MirDecl::Other {
+                    local: decl_local,
+                    temporary: Some(temporary),
+                    ..
+                } if *decl_local == local => Some(format!("`{}`", temporary.expr)),
+                _ => None,
+            })
+        };
+        self.runtime_borrow_calls = borrows.iter().map(|borrow| borrow.call).collect();
+        for borrow in &borrows {
+            if !self.locals.contains(&borrow.cell) && !self.locals.contains(&borrow.guard) {
+                continue;
+            }
+            let cell = name_of(borrow.cell).unwrap_or_else(|| "the cell".to_owned());
+            for range in &borrow.holds {
+                let hover_text = format!(
+                    "dynamic {} borrow of {cell} through `{}`, checked at runtime",
+                    match borrow.kind {
+                        RuntimeBorrowKind::Shared => "shared",
+                        RuntimeBorrowKind::Mutable => "mutable",
+                    },
+                    borrow.api
+                );
+                self.decorations.push(match borrow.kind {
+                    RuntimeBorrowKind::Shared => Deco::DynSharedBorrow {
+                        local: borrow.cell,
+                        range: *range,
+                        hover_text,
+                        overlapped: false,
+                    },
+                    RuntimeBorrowKind::Mutable => Deco::DynMutBorrow {
+                        local: borrow.cell,
+                        range: *range,
+                        hover_text,
+                        overlapped: false,
+                    },
+                });
+            }
+        }
+        for access in runtime_borrow::cell_accesses(func) {
+            if !self.locals.contains(&access.cell) {
+                continue;
+            }
+            let cell = name_of(access.cell).unwrap_or_else(|| "the cell".to_owned());
+            self.decorations.push(Deco::Call {
+                local: access.cell,
+                range: access.call,
+                hover_text: format!(
+                    "`{}` accesses {cell} without a runtime check: `Cell` never lends out \
+                     references to its value, so the call cannot fail",
+                    access.api
+                ),
+                overlapped: false,
+            });
+        }
+        for conflict in runtime_borrow::conflicts(&borrows) {
+            if !self.locals.contains(&conflict.cell)
+                && !borrows.iter().any(|borrow| {
+                    self.locals.contains(&borrow.guard)
+                        && (borrow.call == conflict.call || borrow.call == conflict.held)
+                })
+            {
+                continue;
+            }
+            let cell = name_of(conflict.cell).unwrap_or_else(|| "the cell".to_owned());
+            self.decorations.push(Deco::DynBorrowConflict {
+                local: conflict.cell,
+                range: conflict.call,
+                hover_text: format!(
+                    "{cell} may still be {} borrowed here; `{}` {}",
+                    if conflict.kind == RuntimeBorrowKind::Mutable {
+                        "shared or mutably"
+                    } else {
+                        "mutably"
+                    },
+                    conflict.api,
+                    runtime_borrow::failure(&conflict.api, conflict.kind)
+                ),
+                overlapped: false,
+            });
+        }
+    }
+
     const fn get_deco_order(deco: &Deco) -> u8 {
         match deco {
             Deco::Lifetime { .. } => 0,
@@ -492,6 +1052,11 @@ impl CalcDecos {
             Deco::Call { .. } => 4,
             Deco::SharedMut { .. } => 5,
             Deco::Outlive { .. } => 6,
+            Deco::LifetimeParam { .. } => 7,
+            Deco::DynSharedBorrow { .. } => 8,
+            Deco::DynMutBorrow { .. } => 9,
+            Deco::DynBorrowConflict { .. } => 10,
+            Deco::RawPtr { .. } => 11,
         }
     }
 
@@ -560,8 +1125,19 @@ impl CalcDecos {
     }
 }
 impl range_ops::MirVisitor for CalcDecos {
+    fn visit_func(&mut self, func: &Function) {
+        if let Some((fn_ids, name)) = self.lifetime.clone()
+            && fn_ids.contains(&func.fn_id)
+        {
+            self.lifetime_decos(func, &name);
+        }
+        self.runtime_borrow_decos(func);
+        self.argument_names = argument_names(func);
+        self.macro_calls.clone_from(&func.macro_calls);
+    }
+
     fn visit_decl(&mut self, decl: &MirDecl) {
-        let (local, lives, shared_borrow, mutable_borrow, drop_range, must_live_at, name, drop) =
+        let (local, lives, shared_borrow, mutable_borrow, drop_range, must_live_at, var_str, drop) =
             match decl {
                 MirDecl::User {
                     local,
@@ -580,7 +1156,7 @@ impl range_ops::MirVisitor for CalcDecos {
                     mutable_borrow,
                     drop_range,
                     must_live_at,
-                    Some(name),
+                    format!("variable `{name}`"),
                     drop,
                 ),
                 MirDecl::Other {
@@ -591,6 +1167,7 @@ impl range_ops::MirVisitor for CalcDecos {
                     drop_range,
                     must_live_at,
                     drop,
+                    temporary,
                     ..
                 } => (
                     *local,
@@ -599,16 +1176,31 @@ impl range_ops::MirVisitor for CalcDecos {
                     mutable_borrow,
                     drop_range,
                     must_live_at,
-                    None,
+                    temporary.as_ref().map_or_else(
+                        || "anonymous variable".to_owned(),
+                        |temporary| format!("temporary `{}`", temporary.expr),
+                    ),
                     drop,
                 ),
             };
         self.current_fn_id = local.fn_id;
+        if let MirDecl::User {
+            size: Some(size), ..
+        }
+        | MirDecl::Other {
+            size: Some(size), ..
+        } = decl
+        {
+            self.sizes.insert(local, *size);
+        }
         if self.locals.contains(&local) {
-            let var_str = name.map_or_else(
-                || "anonymous variable".to_owned(),
-                |mir_var_name| format!("variable `{mir_var_name}`"),
-            );
+            let lifetime_text = match decl {
+                MirDecl::Other {
+                    temporary: Some(temporary),
+                    ..
+                } => format!("lifetime of {var_str}: {}", temporary.scope.explanation()),
+                _ => format!("lifetime of {var_str}"),
+            };
             // merge Drop object lives
             let drop_copy_live = if *drop {
                 range_ops::eliminated_ranges(drop_range.clone())
@@ -619,7 +1211,7 @@ impl range_ops::MirVisitor for CalcDecos {
                 self.decorations.push(Deco::Lifetime {
                     local,
                     range: *range,
-                    hover_text: format!("lifetime of {var_str}"),
+                    hover_text: lifetime_text.clone(),
                     overlapped: false,
                 });
             }
@@ -657,7 +1249,15 @@ impl range_ops::MirVisitor for CalcDecos {
                         self.decorations.push(Deco::Move {
                             local: *target_local,
                             range: *range,
-                            hover_text: "variable moved".to_string(),
+                            hover_text: {
+                                let text = self
+                                    .macro_text(*range, "moved")
+                                    .unwrap_or_else(|| "variable moved".to_string());
+                                match self.sizes.get(target_local) {
+                                    Some(size) if *size > 0 => format!("{text} ({size} bytes)"),
+                                    _ => text,
+                                }
+                            },
                             overlapped: false,
                         });
                     }
@@ -673,19 +1273,40 @@ impl range_ops::MirVisitor for CalcDecos {
                             self.decorations.push(Deco::MutBorrow {
                                 local: *target_local,
                                 range: *range,
-                                hover_text: "mutable borrow".to_string(),
+                                hover_text: self
+                                    .macro_text(*range, "mutably borrowed")
+                                    .unwrap_or_else(|| "mutable borrow".to_string()),
                                 overlapped: false,
                             });
                         } else {
                             self.decorations.push(Deco::ImmBorrow {
                                 local: *target_local,
                                 range: *range,
-                                hover_text: "immutable borrow".to_string(),
+                                hover_text: self
+                                    .macro_text(*range, "borrowed")
+                                    .unwrap_or_else(|| "immutable borrow".to_string()),
                                 overlapped: false,
                             });
                         }
                     }
                 }
+                Some(MirRval::RawPtr {
+                    target_local,
+                    range,
+                    mutable,
+                }) => {
+                    if self.locals.contains(target_local) {
+                        let kind = if *mutable { "*mut" } else { "*const" };
+                        self.decorations.push(Deco::RawPtr {
+                            local: *target_local,
+                            range: *range,
+                            hover_text: format!(
+                                "raw pointer (`{kind}`): not tracked by the borrow checker"
+                            ),
+                            overlapped: false,
+                        });
+                    }
+                }
                 _ => {}
             }
         }
@@ -695,8 +1316,12 @@ impl range_ops::MirVisitor for CalcDecos {
         if let MirTerminator::Call {
             destination_local,
             fn_span,
+            args,
+            callee,
+            summary,
         } = term
             && self.locals.contains(destination_local)
+            && !self.runtime_borrow_calls.contains(fn_span)
         {
             let mut i = 0;
             for deco in &self.decorations {
@@ -722,7 +1347,11 @@ impl range_ops::MirVisitor for CalcDecos {
             self.decorations.push(Deco::Call {
                 local: *destination_local,
                 range: *fn_span,
-                hover_text: "function call".to_string(),
+                hover_text: call_hover_text(
+                    call_summary(summary.as_ref(), callee, &self.callees).as_ref(),
+                    args,
+                    &self.argument_names,
+                ),
                 overlapped: false,
             });
         }
@@ -734,8 +1363,8 @@ mod tests {
     use super::*;
     use crate::{
         models::{
-            FnLocal, Function, Loc, MirBasicBlock, MirDecl, MirRval, MirStatement, MirTerminator,
-            Range,
+            FnLocal, Function, Loc, MirBasicBlock, MirDecl, MirLifetimeLocal, MirLifetimeParam,
+            MirRval, MirStatement, MirTemporary, MirTerminator, Range, TempScope,
         },
         range_ops::mir_visit,
     };
@@ -760,6 +1389,8 @@ mod tests {
             drop: false,
             drop_range: vec![],
             must_live_at: vec![],
+            size: None,
+            binding: None,
         }
     }
 
@@ -771,6 +1402,7 @@ mod tests {
             fn_id: 0,
             decls: vec![user_decl(1, "x", r(5, 10))],
             basic_blocks: vec![],
+            ..Default::default()
         };
         let mut sel = SelectLocal::new(Loc::from(7u32));
         mir_visit(&func, &mut sel);
@@ -783,6 +1415,7 @@ mod tests {
             fn_id: 0,
             decls: vec![user_decl(1, "x", r(5, 10))],
             basic_blocks: vec![],
+            ..Default::default()
         };
         let mut sel = SelectLocal::new(Loc::from(20u32));
         mir_visit(&func, &mut sel);
@@ -804,7 +1437,9 @@ mod tests {
                     }),
                 }],
                 terminator: None,
+                ..Default::default()
             }],
+            ..Default::default()
         };
         let mut sel = SelectLocal::new(Loc::from(16u32));
         mir_visit(&func, &mut sel);
@@ -828,7 +1463,9 @@ mod tests {
                     }),
                 }],
                 terminator: None,
+                ..Default::default()
             }],
+            ..Default::default()
         };
         let mut sel = SelectLocal::new(Loc::from(14u32));
         mir_visit(&func, &mut sel);
@@ -845,14 +1482,76 @@ mod tests {
                 terminator: Some(MirTerminator::Call {
                     destination_local: local(1),
                     fn_span: r(30, 40),
+                    args: vec![],
+                    callee: String::new(),
+                    summary: None,
                 }),
+                ..Default::default()
             }],
+            ..Default::default()
         };
         let mut sel = SelectLocal::new(Loc::from(35u32));
         mir_visit(&func, &mut sel);
         assert_eq!(sel.selected(), Some(local(1)));
     }
 
+    fn temp_decl(id: u32, expr: &str, span: Range, scope: TempScope) -> MirDecl {
+        MirDecl::Other {
+            local: local(id),
+            ty: "std::sync::MutexGuard<'_, i32>".into(),
+            lives: vec![span],
+            shared_borrow: vec![],
+            mutable_borrow: vec![],
+            drop: true,
+            drop_range: vec![span],
+            must_live_at: vec![],
+            size: None,
+            temporary: Some(MirTemporary {
+                span,
+                expr: expr.into(),
+                scope,
+            }),
+        }
+    }
+
+    #[test]
+    fn select_local_temporary_wins_over_enclosing_call() {
+        let func = Function {
+            fn_id: 0,
+            decls: vec![
+                temp_decl(1, "m.lock().unwrap()", r(10, 27), TempScope::MatchScrutinee),
+                user_decl(2, "len", r(0, 3)),
+            ],
+            basic_blocks: vec![MirBasicBlock {
+                terminator: Some(MirTerminator::Call {
+                    destination_local: local(2),
+                    fn_span: r(10, 33),
+                    args: vec![],
+                    callee: String::new(),
+                    summary: None,
+                }),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let mut sel = SelectLocal::new(Loc::from(20u32));
+        mir_visit(&func, &mut sel);
+        assert_eq!(sel.selected(), Some(local(1)));
+
+        let mut calc = CalcDecos::new([local(1)]);
+        mir_visit(&func, &mut calc);
+        let decos = calc.decorations();
+        assert!(
+            matches!(
+                &decos[..],
+                [Deco::Lifetime { hover_text, .. }]
+                    if hover_text == "lifetime of temporary `m.lock().unwrap()`: \
+                        temporary in `match` scrutinee lives until the end of the `match`"
+            ),
+            "unexpected decorations: {decos:?}"
+        );
+    }
+
     #[test]
     fn select_local_var_wins_over_move_at_same_pos() {
         // If cursor is inside both a var span and a move span,
@@ -871,7 +1570,9 @@ mod tests {
                     }),
                 }],
                 terminator: None,
+                ..Default::default()
             }],
+            ..Default::default()
         };
         let mut sel = SelectLocal::new(Loc::from(6u32));
         mir_visit(&func, &mut sel);
@@ -891,6 +1592,8 @@ mod tests {
                 drop: false,
                 drop_range: vec![],
                 must_live_at: vec![],
+                size: None,
+                temporary: None,
             }],
             basic_blocks: vec![MirBasicBlock {
                 statements: vec![MirStatement::Assign {
@@ -902,7 +1605,9 @@ mod tests {
                     }),
                 }],
                 terminator: None,
+                ..Default::default()
             }],
+            ..Default::default()
         };
         let mut sel = SelectLocal::new(Loc::from(5u32));
         mir_visit(&func, &mut sel);
@@ -910,6 +1615,81 @@ mod tests {
         assert_eq!(sel.selected(), None);
     }
 
+    // ── SelectLifetime ──────────────────────────────────────────────
+
+    fn func_with_lifetime() -> Function {
+        Function {
+            fn_id: 3,
+            decls: vec![user_decl(1, "x", r(20, 21))],
+            lifetimes: vec![MirLifetimeParam {
+                name: "'a".into(),
+                spans: vec![r(5, 7), r(25, 27)],
+                locals: vec![MirLifetimeLocal {
+                    local: local(1),
+                    must_hold_at: vec![r(40, 60)],
+                }],
+            }],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn select_lifetime_on_param_span() {
+        let func = func_with_lifetime();
+        let mut sel = SelectLifetime::new(Loc::from(26u32));
+        mir_visit(&func, &mut sel);
+        assert_eq!(sel.selected(), Some((vec![3], "'a".to_owned())));
+
+        let mut sel = SelectLifetime::new(Loc::from(30u32));
+        mir_visit(&func, &mut sel);
+        assert_eq!(sel.selected(), None);
+    }
+
+    #[test]
+    fn select_lifetime_shared_by_impl_methods() {
+        let first = func_with_lifetime();
+        // another method of the impl declaring 'a at 5..7
+        let mut second = func_with_lifetime();
+        second.fn_id = 4;
+        second.lifetimes[0].spans = vec![r(5, 7), r(80, 82)];
+        // an unrelated function with its own 'a
+        let mut other = func_with_lifetime();
+        other.fn_id = 5;
+        other.lifetimes[0].spans = vec![r(100, 102)];
+
+        for cursor in [6u32, 26, 81] {
+            let mut sel = SelectLifetime::new(Loc::from(cursor));
+            for func in [&first, &second, &other] {
+                mir_visit(func, &mut sel);
+            }
+            assert_eq!(sel.selected(), Some((vec![3, 4], "'a".to_owned())));
+        }
+    }
+
+    #[test]
+    fn calc_decos_lifetime_param() {
+        let func = func_with_lifetime();
+        let mut calc = CalcDecos::new([]).with_lifetime(vec![3], "'a".to_owned());
+        mir_visit(&func, &mut calc);
+        let decos = calc.decorations();
+        assert!(
+            decos
+                .iter()
+                .any(|d| matches!(d, Deco::LifetimeParam { range, .. } if *range == r(20, 21))),
+            "Expected the reference `x` to be decorated, got: {decos:?}"
+        );
+        assert!(
+            decos
+                .iter()
+                .any(|d| matches!(d, Deco::LifetimeParam { range, .. } if *range == r(40, 60))),
+            "Expected the must-hold points to be decorated, got: {decos:?}"
+        );
+        assert!(
+            !decos.iter().any(|d| matches!(d, Deco::Lifetime { .. })),
+            "Local lifetimes should not be decorated, got: {decos:?}"
+        );
+    }
+
     // ── CalcDecos: basic decoration generation ──────────────────────
 
     fn func_with_move() -> Function {
@@ -926,7 +1706,9 @@ mod tests {
                     }),
                 }],
                 terminator: None,
+                ..Default::default()
             }],
+            ..Default::default()
         }
     }
 
@@ -961,7 +1743,9 @@ mod tests {
                     }),
                 }],
                 terminator: None,
+                ..Default::default()
             }],
+            ..Default::default()
         };
         let mut calc = CalcDecos::new([local(1)]);
         mir_visit(&func, &mut calc);
@@ -974,6 +1758,45 @@ mod tests {
         );
     }
 
+    #[test]
+    fn calc_decos_macro_borrow_and_move() {
+        let mut func = func_with_move();
+        func.basic_blocks[0].statements.push(MirStatement::Assign {
+            target_local: local(2),
+            range: r(20, 32),
+            rval: Some(MirRval::Borrow {
+                target_local: local(1),
+                range: r(30, 31),
+                mutable: false,
+                outlive: None,
+            }),
+        });
+        func.macro_calls = vec![
+            MacroCall {
+                name: "vec".to_owned(),
+                range: r(12, 18),
+                formatted: vec![],
+            },
+            MacroCall {
+                name: "println".to_owned(),
+                range: r(20, 32),
+                formatted: vec![r(30, 31)],
+            },
+        ];
+        let mut calc = CalcDecos::new([local(1)]);
+        mir_visit(&func, &mut calc);
+        let decos = calc.decorations();
+        let hovers: Vec<_> = decos
+            .iter()
+            .filter(|d| matches!(d, Deco::Move { .. } | Deco::ImmBorrow { .. }))
+            .map(Deco::hover_text)
+            .collect();
+        assert_eq!(
+            hovers,
+            ["moved by `vec!`", "borrowed by `println!` formatting"]
+        );
+    }
+
     #[test]
     fn calc_decos_mut_borrow() {
         let func = Function {
@@ -991,7 +1814,9 @@ mod tests {
                     }),
                 }],
                 terminator: None,
+                ..Default::default()
             }],
+            ..Default::default()
         };
         let mut calc = CalcDecos::new([local(1)]);
         mir_visit(&func, &mut calc);
@@ -1014,8 +1839,13 @@ mod tests {
                 terminator: Some(MirTerminator::Call {
                     destination_local: local(1),
                     fn_span: r(30, 40),
+                    args: vec![],
+                    callee: String::new(),
+                    summary: None,
                 }),
+                ..Default::default()
             }],
+            ..Default::default()
         };
         let mut calc = CalcDecos::new([local(1)]);
         mir_visit(&func, &mut calc);
@@ -1034,6 +1864,7 @@ mod tests {
             fn_id: 0,
             decls: vec![user_decl(1, "x", r(0, 20))],
             basic_blocks: vec![],
+            ..Default::default()
         };
         let mut calc = CalcDecos::new([local(1)]);
         mir_visit(&func, &mut calc);
@@ -1075,16 +1906,25 @@ mod tests {
                     terminator: Some(MirTerminator::Call {
                         destination_local: local(1),
                         fn_span: r(15, 40),
+                        args: vec![],
+                        callee: String::new(),
+                        summary: None,
                     }),
+                    ..Default::default()
                 },
                 MirBasicBlock {
                     statements: vec![],
                     terminator: Some(MirTerminator::Call {
                         destination_local: local(1),
                         fn_span: r(10, 50),
+                        args: vec![],
+                        callee: String::new(),
+                        summary: None,
                     }),
+                    ..Default::default()
                 },
             ],
+            ..Default::default()
         };
         let mut calc = CalcDecos::new([local(1)]);
         mir_visit(&func, &mut calc);
@@ -1118,8 +1958,11 @@ mod tests {
                 drop: false,
                 drop_range: vec![],
                 must_live_at: vec![],
+                size: None,
+                binding: None,
             }],
             basic_blocks: vec![],
+            ..Default::default()
         };
         let mut calc = CalcDecos::new([local(1)]);
         mir_visit(&func, &mut calc);
@@ -1149,8 +1992,11 @@ mod tests {
                 drop: false,
                 drop_range: vec![],
                 must_live_at: vec![r(0, 30)],
+                size: None,
+                binding: None,
             }],
             basic_blocks: vec![],
+            ..Default::default()
         };
         let mut calc = CalcDecos::new([local(1)]);
         mir_visit(&func, &mut calc);
@@ -1180,7 +2026,9 @@ mod tests {
                     }),
                 }],
                 terminator: None,
+                ..Default::default()
             }],
+            ..Default::default()
         };
         let mut calc = CalcDecos::new([local(1)]);
         mir_visit(&func, &mut calc);
@@ -1230,7 +2078,9 @@ mod tests {
                     }),
                 }],
                 terminator: None,
+                ..Default::default()
             }],
+            ..Default::default()
         };
         let mut calc = CalcDecos::new([local(1)]);
         mir_visit(&func, &mut calc);
@@ -1312,6 +2162,17 @@ mod tests {
             imm.diagnostic_severity(),
             lsp_types::DiagnosticSeverity::HINT
         );
+
+        let conflict = Deco::DynBorrowConflict::<Range> {
+            local: local(1),
+            range: r(0, 1),
+            hover_text: String::new(),
+            overlapped: false,
+        };
+        assert_eq!(
+            conflict.diagnostic_severity(),
+            lsp_types::DiagnosticSeverity::WARNING
+        );
     }
 
     #[test]
@@ -1396,6 +2257,33 @@ mod tests {
         );
         assert!(
             Deco::Outlive::<Range> {
+                local: l,
+                range,
+                hover_text: ht.clone(),
+                overlapped: false
+            }
+            .should_show_as_diagnostic()
+        );
+        assert!(
+            Deco::DynSharedBorrow::<Range> {
+                local: l,
+                range,
+                hover_text: ht.clone(),
+                overlapped: false
+            }
+            .should_show_as_diagnostic()
+        );
+        assert!(
+            Deco::DynMutBorrow::<Range> {
+                local: l,
+                range,
+                hover_text: ht.clone(),
+                overlapped: false
+            }
+            .should_show_as_diagnostic()
+        );
+        assert!(
+            Deco::DynBorrowConflict::<Range> {
                 local: l,
                 range,
                 hover_text: ht,
@@ -0,0 +1,535 @@
+use std::collections::{HashMap, HashSet};
+
+use tower_lsp::lsp_types;
+
+use crate::{
+    models::{
+        BindingMode, DropState, FnLocal, Function, Loc, MirBinding, MirCapture, MirDecl,
+        MirTerminator, Range, TempScope,
+    },
+    range_ops::MirVisitor,
+    text_conversion,
+};
+
+/// An inlay hint placed before `pos`
+#[derive(Clone, Debug)]
+pub struct InlayHint {
+    pub pos: Loc,
+    pub label: String,
+    pub tooltip: String,
+    /// Whether to leave a space between the hint and the code after it
+    pub padding: bool,
+}
+
+impl InlayHint {
+    #[must_use]
+    pub fn to_lsp(&self, text: &str) -> lsp_types::InlayHint {
+        let (line, character) = text_conversion::index_to_line_char(text, self.pos);
+        lsp_types::InlayHint {
+            position: lsp_types::Position { line, character },
+            label: lsp_types::InlayHintLabel::String(self.label.clone()),
+            kind: None,
+            text_edits: None,
+            tooltip: Some(lsp_types::InlayHintTooltip::String(self.tooltip.clone())),
+            padding_left: Some(false),
+            padding_right: Some(self.padding),
+            data: None,
+        }
+    }
+}
+
+/// The drops that run at one scope end, in the order the destructors run
+#[derive(Clone, Debug)]
+pub struct ScopeDrops {
+    pub range: Range,
+    pub drops: Vec<(FnLocal, DropState)>,
+}
+
+/// Collect the drops of `func` grouped by the scope end they belong to
+///
+/// The CFG is walked from the entry block without the unwinding paths, so the
+/// drops within a group appear in the order in which they are executed.
+/// Drops of values that were moved out are skipped.
+#[must_use]
+pub fn drop_order(func: &Function) -> Vec<ScopeDrops> {
+    let mut groups: Vec<ScopeDrops> = Vec::new();
+    let mut visited = HashSet::new();
+    let mut stack = vec![0u32];
+    while let Some(index) = stack.pop() {
+        let Some(bb) = func.basic_blocks.get(index as usize) else {
+            continue;
+        };
+        if bb.cleanup || !visited.insert(index) {
+            continue;
+        }
+        if let Some(MirTerminator::Drop {
+            local,
+            range,
+            state,
+        }) = &bb.terminator
+            && *state != DropState::MovedOut
+        {
+            let group_index = groups
+                .iter()
+                .position(|g| g.range == *range)
+                .unwrap_or_else(|| {
+                    groups.push(ScopeDrops {
+                        range: *range,
+                        drops: Vec::new(),
+                    });
+                    groups.len() - 1
+                });
+            let group = &mut groups[group_index];
+            if let Some((_, existing)) = group.drops.iter_mut().find(|(l, _)| l == local) {
+                if existing != state {
+                    *existing = DropState::MaybeDropped;
+                }
+            } else {
+                group.drops.push((*local, *state));
+            }
+        }
+        // visit the first successor first
+        stack.extend(bb.successors.iter().rev());
+    }
+    groups.sort_by_key(|group| group.range.from());
+    groups
+}
+
+/// Kind of inlay hints, which can be turned off in the settings
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum InlayHintKind {
+    /// Values dropped at a scope end
+    DropOrder,
+    /// Temporaries of `if let`/`match` scrutinees held until the end
+    Scrutinee,
+    /// Closure trait and captures
+    Closure,
+    /// Implicit reference binding modes
+    Binding,
+}
+
+impl InlayHintKind {
+    pub const ALL: [Self; 4] = [
+        Self::DropOrder,
+        Self::Scrutinee,
+        Self::Closure,
+        Self::Binding,
+    ];
+
+    /// Name of the kind in the `decorations` settings
+    #[must_use]
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::DropOrder => "drop_order",
+            Self::Scrutinee => "scrutinee",
+            Self::Closure => "closure",
+            Self::Binding => "binding",
+        }
+    }
+}
+
+/// Calculates inlay hints of functions
+pub struct CalcInlayHints {
+    hints: Vec<InlayHint>,
+    kinds: HashSet<InlayHintKind>,
+}
+
+impl CalcInlayHints {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::with_kinds(InlayHintKind::ALL.into_iter().collect())
+    }
+
+    /// Calculates only the hints of `kinds`
+    #[must_use]
+    pub const fn with_kinds(kinds: HashSet<InlayHintKind>) -> Self {
+        Self {
+            hints: Vec::new(),
+            kinds,
+        }
+    }
+
+    #[must_use]
+    pub fn hints(self) -> Vec<InlayHint> {
+        self.hints
+    }
+
+    fn drop_order_hints(&mut self, func: &Function) {
+        let decls: HashMap<_, _> = func
+            .decls
+            .iter()
+            .map(|decl| match decl {
+                MirDecl::User {
+                    local, name, ty, ..
+                } => (*local, (Some(name.as_str()), ty.as_str())),
+                MirDecl::Other { local, ty, .. } => (*local, (None, ty.as_str())),
+            })
+            .collect();
+        for group in drop_order(func) {
+            let mut labels = Vec::new();
+            let mut tooltip = vec!["Destructors run in this order:".to_owned()];
+            for (index, (local, state)) in group.drops.iter().enumerate() {
+                let (name, ty) = decls.get(local).copied().unwrap_or((None, "?"));
+                let maybe = if *state == DropState::MaybeDropped {
+                    "?"
+                } else {
+                    ""
+                };
+                let label = name.map_or_else(|| format!("_{}", local.id), str::to_owned);
+                labels.push(format!("{}. {label}{maybe}", index + 1));
+                let what = name.map_or_else(
+                    || format!("temporary `_{}`", local.id),
+                    |name| format!("`{name}`"),
+                );
+                let note = if *state == DropState::MaybeDropped {
+                    " (only if not moved before)"
+                } else {
+                    ""
+                };
+                tooltip.push(format!("{}. {what}: `{ty}`{note}", index + 1));
+            }
+            self.hints.push(InlayHint {
+                pos: group.range.from(),
+                label: format!("drop {}", labels.join(", ")),
+                tooltip: tooltip.join("\n"),
+                padding: true,
+            });
+        }
+    }
+
+    /// Flag temporaries of `if let`/`match` scrutinees that run a destructor
+    /// only after the whole expression, like a `MutexGuard` held in every arm
+    fn scrutinee_hints(&mut self, func: &Function) {
+        let dropped: HashSet<_> = func
+            .basic_blocks
+            .iter()
+            .filter(|bb| !bb.cleanup)
+            .filter_map(|bb| match &bb.terminator {
+                Some(MirTerminator::Drop { local, state, .. }) if *state != DropState::MovedOut => {
+                    Some(*local)
+                }
+                _ => None,
+            })
+            .collect();
+        for decl in &func.decls {
+            let MirDecl::Other {
+                local,
+                ty,
+                drop: true,
+                temporary: Some(temporary),
+                ..
+            } = decl
+            else {
+                continue;
+            };
+            if !temporary.scope.is_scrutinee() || !dropped.contains(local) {
+                continue;
+            }
+            let owner = if temporary.scope == TempScope::MatchScrutinee {
+                "match"
+            } else {
+                "if let"
+            };
+            self.hints.push(InlayHint {
+                pos: temporary.span.until(),
+                label: format!("held until end of `{owner}`"),
+                tooltip: format!(
+                    "`{}`: `{ty}`\n{}. Evaluate it in a separate `let` statement to drop it \
+                     before the `{owner}` body runs.",
+                    temporary.expr,
+                    temporary.scope.explanation()
+                ),
+                padding: true,
+            });
+        }
+    }
+
+    /// Mark variables that a pattern binds by reference only because it
+    /// matches a reference, like `v` in `match &x { Some(v) => .. }`
+    fn binding_hints(&mut self, func: &Function) {
+        for decl in &func.decls {
+            let MirDecl::User {
+                name,
+                span,
+                ty,
+                binding:
+                    Some(MirBinding {
+                        mode,
+                        implicit: true,
+                    }),
+                ..
+            } = decl
+            else {
+                continue;
+            };
+            let (label, what, written) = match mode {
+                BindingMode::Ref => ("&", "a shared", "ref"),
+                BindingMode::RefMut => ("&mut ", "a mutable", "ref mut"),
+                BindingMode::Value => continue,
+            };
+            self.hints.push(InlayHint {
+                pos: span.from(),
+                label: label.to_owned(),
+                tooltip: format!(
+                    "`{name}` is {what} reference (`{ty}`) into the matched value: matching a \
+                     reference with a non-reference pattern switches the default binding mode to \
+                     `{written}`"
+                ),
+                padding: false,
+            });
+        }
+    }
+
+    /// Show the capture list and the closure trait after the head of a
+    /// closure, like `FnMut [&a, &mut b, move c]`
+    fn closure_hints(&mut self, func: &Function) {
+        let Some(closure) = &func.closure else {
+            return;
+        };
+        let labels: Vec<_> = closure.captures.iter().map(MirCapture::label).collect();
+        let reason = match &closure.kind_origin {
+            Some((place, _)) if closure.kind == "FnOnce" => {
+                format!(" because it moves `{place}` out")
+            }
+            Some((place, _)) => format!(" because it mutates `{place}`"),
+            None => String::new(),
+        };
+        let mut tooltip = vec![format!("Closure implements `{}`{reason}", closure.kind)];
+        tooltip.extend(
+            closure
+                .captures
+                .iter()
+                .map(|capture| format!("`{}`: {}", capture.name, capture.mode.explanation())),
+        );
+        self.hints.push(InlayHint {
+            pos: closure.head.until(),
+            label: format!("{} [{}]", closure.kind, labels.join(", ")),
+            tooltip: tooltip.join("\n"),
+            padding: true,
+        });
+    }
+}
+
+impl Default for CalcInlayHints {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MirVisitor for CalcInlayHints {
+    fn visit_func(&mut self, func: &Function) {
+        if self.kinds.contains(&InlayHintKind::DropOrder) {
+            self.drop_order_hints(func);
+        }
+        if self.kinds.contains(&InlayHintKind::Scrutinee) {
+            self.scrutinee_hints(func);
+        }
+        if self.kinds.contains(&InlayHintKind::Closure) {
+            self.closure_hints(func);
+        }
+        if self.kinds.contains(&InlayHintKind::Binding) {
+            self.binding_hints(func);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        models::{CaptureMode, MirBasicBlock, MirClosure, MirTemporary},
+        range_ops::mir_visit,
+    };
+
+    fn r(from: u32, until: u32) -> Range {
+        Range::new(Loc::from(from), Loc::from(until)).unwrap()
+    }
+
+    fn drop_block(id: u32, range: Range, state: DropState, successors: Vec<u32>) -> MirBasicBlock {
+        MirBasicBlock {
+            terminator: Some(MirTerminator::Drop {
+                local: FnLocal::new(id, 0),
+                range,
+                state,
+            }),
+            successors,
+            ..Default::default()
+        }
+    }
+
+    fn user_decl(id: u32, name: &str, ty: &str) -> MirDecl {
+        MirDecl::User {
+            local: FnLocal::new(id, 0),
+            name: name.into(),
+            span: r(0, 1),
+            ty: ty.into(),
+            lives: vec![],
+            shared_borrow: vec![],
+            mutable_borrow: vec![],
+            drop: true,
+            drop_range: vec![],
+            must_live_at: vec![],
+            size: None,
+            binding: None,
+        }
+    }
+
+    fn scope_end_func() -> Function {
+        let end = r(50, 51);
+        Function {
+            decls: vec![user_decl(1, "a", "String"), user_decl(2, "b", "File")],
+            basic_blocks: vec![
+                // b is declared last, so it is dropped first
+                drop_block(2, end, DropState::Dropped, vec![1, 4]),
+                drop_block(1, end, DropState::MaybeDropped, vec![2, 4]),
+                drop_block(3, r(20, 21), DropState::MovedOut, vec![3]),
+                MirBasicBlock::default(),
+                MirBasicBlock {
+                    cleanup: true,
+                    ..drop_block(1, end, DropState::Dropped, vec![])
+                },
+            ],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn drop_order_follows_cfg_and_skips_moved_out() {
+        let groups = drop_order(&scope_end_func());
+        assert_eq!(groups.len(), 1, "moved-out drops are skipped: {groups:?}");
+        let locals: Vec<_> = groups[0].drops.iter().map(|(l, _)| l.id).collect();
+        assert_eq!(locals, vec![2, 1], "unexpected order: {groups:?}");
+    }
+
+    #[test]
+    fn disabled_hint_kind_is_suppressed() {
+        let mut calc = CalcInlayHints::with_kinds(HashSet::from([InlayHintKind::Closure]));
+        mir_visit(&scope_end_func(), &mut calc);
+        assert!(calc.hints().is_empty(), "drop order hints are disabled");
+    }
+
+    #[test]
+    fn drop_order_hint_label() {
+        let mut calc = CalcInlayHints::new();
+        mir_visit(&scope_end_func(), &mut calc);
+        let hints = calc.hints();
+        assert_eq!(hints.len(), 1, "one hint per scope end: {hints:?}");
+        assert_eq!(hints[0].label, "drop 1. b, 2. a?");
+        assert_eq!(hints[0].pos, Loc::from(50u32));
+        assert!(
+            hints[0]
+                .tooltip
+                .contains("2. `a`: `String` (only if not moved before)"),
+            "unexpected tooltip: {}",
+            hints[0].tooltip
+        );
+    }
+
+    #[test]
+    fn scrutinee_temporary_hint() {
+        let temporary = |id: u32, span: Range, scope: TempScope| MirDecl::Other {
+            local: FnLocal::new(id, 0),
+            ty: "MutexGuard<'_, Vec<u8>>".into(),
+            lives: vec![],
+            shared_borrow: vec![],
+            mutable_borrow: vec![],
+            drop: true,
+            drop_range: vec![],
+            must_live_at: vec![],
+            size: None,
+            temporary: Some(MirTemporary {
+                span,
+                expr: "m.lock().unwrap()".into(),
+                scope,
+            }),
+        };
+        let func = Function {
+            decls: vec![
+                temporary(1, r(10, 27), TempScope::MatchScrutinee),
+                temporary(2, r(60, 77), TempScope::Statement),
+                temporary(3, r(90, 107), TempScope::LetScrutinee),
+            ],
+            basic_blocks: vec![
+                drop_block(1, r(40, 41), DropState::Dropped, vec![1]),
+                drop_block(2, r(80, 81), DropState::Dropped, vec![2]),
+                // moved into the binding, nothing is held
+                drop_block(3, r(120, 121), DropState::MovedOut, vec![]),
+            ],
+            ..Default::default()
+        };
+        let mut calc = CalcInlayHints::new();
+        calc.scrutinee_hints(&func);
+        let hints = calc.hints();
+        assert_eq!(hints.len(), 1, "only the match guard is held: {hints:?}");
+        assert_eq!(hints[0].label, "held until end of `match`");
+        assert_eq!(hints[0].pos, Loc::from(27u32));
+    }
+
+    #[test]
+    fn closure_capture_hint() {
+        let capture = |name: &str, mode| MirCapture {
+            name: name.into(),
+            mode,
+        };
+        let func = Function {
+            closure: Some(MirClosure {
+                head: r(10, 13),
+                kind: "FnOnce".into(),
+                kind_origin: Some(("c".into(), r(14, 15))),
+                captures: vec![
+                    capture("a", CaptureMode::SharedRef),
+                    capture("b", CaptureMode::MutableRef),
+                    capture("c", CaptureMode::ByValue),
+                ],
+            }),
+            ..Default::default()
+        };
+        let mut calc = CalcInlayHints::new();
+        calc.closure_hints(&func);
+        let hints = calc.hints();
+        assert_eq!(hints.len(), 1);
+        assert_eq!(hints[0].label, "FnOnce [&a, &mut b, move c]");
+        assert_eq!(hints[0].pos, Loc::from(13u32));
+        assert_eq!(
+            hints[0].tooltip,
+            "Closure implements `FnOnce` because it moves `c` out\n`a`: borrowed by the \
+             closure\n`b`: mutably borrowed by the closure\n`c`: moved or copied into the closure"
+        );
+    }
+
+    #[test]
+    fn implicit_ref_binding_hint() {
+        let bound = |id: u32, name: &str, mode, implicit| {
+            let mut decl = user_decl(id, name, "&String");
+            if let MirDecl::User { binding, span, .. } = &mut decl {
+                *binding = Some(MirBinding { mode, implicit });
+                *span = r(id * 10, id * 10 + 1);
+            }
+            decl
+        };
+        let func = Function {
+            decls: vec![
+                bound(1, "v", BindingMode::Ref, true),
+                bound(2, "w", BindingMode::RefMut, true),
+                bound(3, "r", BindingMode::Ref, false),
+                bound(4, "s", BindingMode::Value, false),
+            ],
+            ..Default::default()
+        };
+        let mut calc = CalcInlayHints::new();
+        calc.binding_hints(&func);
+        let hints: Vec<_> = calc
+            .hints()
+            .into_iter()
+            .map(|hint| (hint.pos, hint.label, hint.padding))
+            .collect();
+        assert_eq!(
+            hints,
+            vec![
+                (Loc::from(10u32), "&".to_owned(), false),
+                (Loc::from(20u32), "&mut ".to_owned(), false),
+            ],
+            "only implicit reference bindings are marked"
+        );
+    }
+}
@@ -1,15 +1,201 @@
-use serde::Serialize;
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+use tokio::time::{Duration, Instant};
 use tower_lsp::{
     Client,
-    lsp_types::{self, notification::Progress, request::WorkDoneProgressCreate},
+    lsp_types::{
+        self,
+        notification::{Notification, Progress},
+        request::WorkDoneProgressCreate,
+    },
 };
 
+use crate::{lsp_workspace::BuildError, models::Workspace};
+
 #[derive(Serialize, Clone, Copy, PartialEq, Eq, Debug)]
 #[serde(rename_all = "snake_case")]
 pub enum AnalysisStatus {
     Analyzing,
     Finished,
     Error,
+    /// The function under the cursor exceeded the analysis limits
+    TooLarge,
+    /// The borrow check of the function under the cursor timed out
+    TimedOut,
+}
+
+/// Response of `ferrous-owl/status`
+#[derive(Serialize, Clone, Debug)]
+pub struct StatusResponse {
+    pub status: AnalysisStatus,
+    /// Why the analysis failed, e.g. the first build error
+    pub detail: Option<String>,
+    pub build_errors: Vec<BuildError>,
+}
+
+/// `ferrous-owl/analysis` notification, sent when the analysis changes
+pub enum AnalysisNotification {}
+impl Notification for AnalysisNotification {
+    type Params = AnalysisUpdate;
+    const METHOD: &'static str = "ferrous-owl/analysis";
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case", tag = "event")]
+pub enum AnalysisUpdate {
+    Started,
+    CrateChecked {
+        package: String,
+    },
+    /// Results of functions in these files are available
+    FilesAnalyzed {
+        uris: Vec<lsp_types::Url>,
+    },
+    /// The analysis finished with results for these files
+    Finished {
+        uris: Vec<lsp_types::Url>,
+    },
+    /// The analysis failed, e.g. because of build errors in these files
+    Failed {
+        detail: String,
+        uris: Vec<lsp_types::Url>,
+    },
+}
+
+/// Sends the analysis notifications of a run, batching the files whose
+/// results arrive, and asks the client to redraw the inlay hints of them
+pub struct AnalysisNotifier {
+    client: Client,
+    refresh_inlay_hints: bool,
+    /// File open in the editor, whose results are notified without delay
+    active_file: Option<lsp_types::Url>,
+    pending: HashSet<lsp_types::Url>,
+    last_flush: Instant,
+}
+impl AnalysisNotifier {
+    /// Minimum time between two notifications of analyzed files
+    const BATCH: Duration = Duration::from_millis(200);
+
+    #[must_use]
+    pub fn new(client: Client, refresh_inlay_hints: bool, active_file: Option<&Path>) -> Self {
+        Self {
+            client,
+            refresh_inlay_hints,
+            active_file: active_file.and_then(|path| lsp_types::Url::from_file_path(path).ok()),
+            pending: HashSet::new(),
+            last_flush: Instant::now(),
+        }
+    }
+
+    pub async fn notify(client: &Client, update: AnalysisUpdate) {
+        client
+            .send_notification::<AnalysisNotification>(update)
+            .await;
+    }
+
+    /// Ask the client to request the inlay hints again, without waiting for
+    /// its response
+    pub fn refresh(client: &Client, refresh_inlay_hints: bool) {
+        if refresh_inlay_hints {
+            let client = client.clone();
+            tokio::spawn(async move { client.inlay_hint_refresh().await.ok() });
+        }
+    }
+
+    pub async fn crate_checked(&mut self, package: String) {
+        self.flush().await;
+        Self::notify(&self.client, AnalysisUpdate::CrateChecked { package }).await;
+    }
+
+    /// Remember the files of `ws` for the next notification
+    pub fn files_analyzed(&mut self, ws: &Workspace) {
+        let uris =
+            ws.0.values()
+                .flat_map(|krate| krate.0.keys())
+                .filter_map(|file| lsp_types::Url::from_file_path(PathBuf::from(file)).ok());
+        self.pending.extend(uris);
+    }
+
+    /// Notify the analyzed files unless the last notification is too recent
+    /// and the active file is not among them
+    pub async fn flush_if_due(&mut self) {
+        if Self::BATCH <= self.last_flush.elapsed()
+            || self
+                .active_file
+                .as_ref()
+                .is_some_and(|file| self.pending.contains(file))
+        {
+            self.flush().await;
+        }
+    }
+
+    /// Notify the files analyzed since the last notification
+    pub async fn flush(&mut self) {
+        self.last_flush = Instant::now();
+        if self.pending.is_empty() {
+            return;
+        }
+        let uris = self.pending.drain().collect();
+        Self::notify(&self.client, AnalysisUpdate::FilesAnalyzed { uris }).await;
+        Self::refresh(&self.client, self.refresh_inlay_hints);
+    }
+}
+
+/// Progress of an analysis run, from the build units cargo has checked and
+/// the functions of each crate the compiler has analyzed
+#[derive(Default, Debug)]
+pub struct AnalysisProgress {
+    units_checked: usize,
+    unit_count: usize,
+    /// Analyzed and total functions of each crate
+    functions: HashMap<String, (usize, usize)>,
+}
+impl AnalysisProgress {
+    /// Count a checked unit of the `unit_count` units of the run
+    pub const fn unit_checked(&mut self, unit_count: usize) {
+        self.units_checked += 1;
+        self.unit_count = unit_count;
+    }
+
+    pub fn crate_analyzing(&mut self, krate: String, functions: usize) {
+        self.functions.entry(krate).or_default().1 = functions;
+    }
+
+    /// Count `analyzed` more functions of `krate`, returning the progress
+    /// message of the crate
+    pub fn functions_analyzed(&mut self, krate: &str, analyzed: usize) -> Option<String> {
+        let (done, total) = self.functions.get_mut(krate)?;
+        *done += analyzed;
+        Some(format!(
+            "{krate}: {}/{total} functions",
+            (*done).min(*total)
+        ))
+    }
+
+    /// Count the functions of `ws`, returning the progress message of the
+    /// last of its crates
+    pub fn workspace_analyzed(&mut self, ws: &Workspace) -> Option<String> {
+        ws.0.iter()
+            .filter_map(|(name, krate)| {
+                let count = krate.0.values().map(|file| file.items.len()).sum();
+                self.functions_analyzed(name, count)
+            })
+            .last()
+    }
+
+    #[must_use]
+    pub fn percentage(&self) -> Option<u32> {
+        if self.unit_count == 0 {
+            return None;
+        }
+        let percentage = (self.units_checked * 100 / self.unit_count).min(100);
+        #[allow(clippy::cast_possible_truncation, reason = "percentage is 0-100")]
+        Some(percentage as u32)
+    }
 }
 
 pub struct ProgressToken {
@@ -29,7 +215,7 @@ impl ProgressToken {
         let value = lsp_types::ProgressParamsValue::WorkDone(lsp_types::WorkDoneProgress::Begin(
             lsp_types::WorkDoneProgressBegin {
                 title: "RustOwl".to_owned(),
-                cancellable: Some(false),
+                cancellable: Some(true),
                 message: message.map(|v| v.to_string()),
                 percentage: Some(0),
             },
@@ -47,11 +233,17 @@ impl ProgressToken {
         }
     }
 
+    /// Token the client cancels the progress with
+    #[must_use]
+    pub const fn token(&self) -> Option<&lsp_types::NumberOrString> {
+        self.token.as_ref()
+    }
+
     pub async fn report(&self, message: Option<impl ToString>, percentage: Option<u32>) {
         if let (Some(client), Some(token)) = (self.client.clone(), self.token.clone()) {
             let value = lsp_types::ProgressParamsValue::WorkDone(
                 lsp_types::WorkDoneProgress::Report(lsp_types::WorkDoneProgressReport {
-                    cancellable: Some(false),
+                    cancellable: Some(true),
                     message: message.map(|v| v.to_string()),
                     percentage,
                 }),
@@ -88,3 +280,60 @@ impl Drop for ProgressToken {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use tower_lsp::lsp_types;
+
+    use super::{AnalysisProgress, AnalysisUpdate};
+
+    #[test]
+    fn analysis_update_serialization() {
+        let uri = lsp_types::Url::parse("file:///ws/src/lib.rs").unwrap();
+        let update = AnalysisUpdate::FilesAnalyzed {
+            uris: vec![uri.clone()],
+        };
+        assert_eq!(
+            serde_json::to_value(&update).unwrap(),
+            serde_json::json!({ "event": "files_analyzed", "uris": ["file:///ws/src/lib.rs"] })
+        );
+        let failed = AnalysisUpdate::Failed {
+            detail: "crate app failed to compile: mismatched types".to_owned(),
+            uris: vec![uri],
+        };
+        let json = serde_json::to_string(&failed).unwrap();
+        assert_eq!(
+            serde_json::from_str::<AnalysisUpdate>(&json).unwrap(),
+            failed
+        );
+        assert_eq!(
+            serde_json::to_value(AnalysisUpdate::Started).unwrap(),
+            serde_json::json!({ "event": "started" })
+        );
+    }
+
+    #[test]
+    fn progress_of_units_and_functions() {
+        let mut progress = AnalysisProgress::default();
+        assert_eq!(progress.percentage(), None);
+
+        progress.unit_checked(4);
+        assert_eq!(progress.percentage(), Some(25));
+        for _ in 0..5 {
+            progress.unit_checked(4);
+        }
+        assert_eq!(progress.percentage(), Some(100));
+
+        // results of crates that were not announced are not counted
+        assert_eq!(progress.functions_analyzed("app", 1), None);
+        progress.crate_analyzing("app".to_owned(), 3);
+        assert_eq!(
+            progress.functions_analyzed("app", 2).as_deref(),
+            Some("app: 2/3 functions")
+        );
+        assert_eq!(
+            progress.functions_analyzed("app", 2).as_deref(),
+            Some("app: 3/3 functions")
+        );
+    }
+}
@@ -1,6 +1,6 @@
 use std::{
-    collections::BTreeMap,
-    fs,
+    collections::{BTreeMap, HashMap, HashSet},
+    fs, mem,
     path::{Path, PathBuf},
     sync::Arc,
 };
@@ -10,21 +10,30 @@ use tokio_util::sync::CancellationToken;
 use tower_lsp::{Client, LanguageServer, LspService, jsonrpc, lsp_types};
 
 use crate::{
-    lsp_decoration as decoration, lsp_progress as progress,
-    lsp_workspace::{Analyzer, AnalyzerEvent},
-    models::{Crate, Loc},
-    range_ops, text_conversion,
+    graph_export::{self, GraphFormat},
+    lints, lsp_decoration as decoration, lsp_inlay_hint as inlay_hint,
+    lsp_progress::{self as progress, AnalysisNotifier, AnalysisUpdate},
+    lsp_settings::{self, Settings},
+    lsp_workspace::{Analyzer, AnalyzerEvent, BuildError},
+    models::{AnalysisPrecision, Crate, Loc, Workspace},
+    range_ops,
+    runtime_borrow::RuntimeBorrowTable,
+    text_conversion,
 };
 
 /// Commands supported by workspace/executeCommand
 pub const CMD_SHOW_OWNERSHIP: &str = "ferrous-owl.showOwnership";
 pub const CMD_ANALYZE: &str = "ferrous-owl.analyze";
+pub const CMD_EXPORT_ORIGIN_GRAPH: &str = "ferrous-owl.exportOriginGraph";
+pub const CMD_EXPORT_CFG: &str = "ferrous-owl.exportCfg";
 
 #[derive(serde::Deserialize, Clone, Debug)]
 #[serde(rename_all = "snake_case")]
 pub struct AnalyzeRequest {}
 #[derive(serde::Serialize, Clone, Debug)]
 pub struct AnalyzeResponse {}
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct StatusRequest {}
 
 /// `FerrousOwl` LSP server backend
 pub struct Backend {
@@ -34,7 +43,20 @@ pub struct Backend {
     analyzed: Arc<RwLock<Option<Crate>>>,
     processes: Arc<RwLock<JoinSet<()>>>,
     process_tokens: Arc<RwLock<BTreeMap<usize, CancellationToken>>>,
+    /// Cancellation tokens of the analyses by their work done progress token
+    progress_tokens: Arc<RwLock<HashMap<lsp_types::NumberOrString, CancellationToken>>>,
     work_done_progress: Arc<RwLock<bool>>,
+    runtime_borrows: Arc<RwLock<RuntimeBorrowTable>>,
+    /// Settings sent by the editor, which override those of the project files
+    client_settings: Arc<RwLock<serde_json::Value>>,
+    /// Settings of the first workspace
+    settings: Arc<RwLock<Settings>>,
+    /// Whether the client redraws inlay hints when asked to
+    inlay_hint_refresh: Arc<RwLock<bool>>,
+    /// Errors of the crates that failed to compile in the last analysis
+    build_errors: Arc<RwLock<Vec<BuildError>>>,
+    /// File the user is looking at, which is analyzed first
+    active_file: Arc<RwLock<Option<PathBuf>>>,
 }
 
 impl Backend {
@@ -47,7 +69,14 @@ impl Backend {
             status: Arc::new(RwLock::new(progress::AnalysisStatus::Finished)),
             processes: Arc::new(RwLock::new(JoinSet::new())),
             process_tokens: Arc::new(RwLock::new(BTreeMap::new())),
+            progress_tokens: Arc::new(RwLock::new(HashMap::new())),
             work_done_progress: Arc::new(RwLock::new(false)),
+            runtime_borrows: Arc::new(RwLock::new(RuntimeBorrowTable::default())),
+            client_settings: Arc::new(RwLock::new(serde_json::Value::Null)),
+            settings: Arc::new(RwLock::new(Settings::default())),
+            inlay_hint_refresh: Arc::new(RwLock::new(false)),
+            build_errors: Arc::new(RwLock::new(Vec::new())),
+            active_file: Arc::new(RwLock::new(None)),
         }
     }
 
@@ -66,6 +95,25 @@ impl Backend {
         }
     }
 
+    /// Analysis status with the reason of a failure
+    pub async fn status(
+        &self,
+        _params: StatusRequest,
+    ) -> jsonrpc::Result<progress::StatusResponse> {
+        let status = *self.status.read().await;
+        let build_errors = self.build_errors.read().await.clone();
+        let detail = if status == progress::AnalysisStatus::Error {
+            Some(Self::failure_detail(&build_errors))
+        } else {
+            build_errors.first().map(BuildError::summary)
+        };
+        Ok(progress::StatusResponse {
+            status,
+            detail,
+            build_errors,
+        })
+    }
+
     pub async fn analyze(&self, _params: AnalyzeRequest) -> jsonrpc::Result<AnalyzeResponse> {
         log::info!("ferrous-owl/analyze request received");
         self.do_analyze().await;
@@ -73,11 +121,38 @@ impl Backend {
     }
     async fn do_analyze(&self) {
         self.shutdown_subprocesses().await;
-        // Use all_targets=true by default to include test code
-        self.analyze_with_options(true, false).await;
+        self.analyze_with_options().await;
     }
 
-    async fn analyze_with_options(&self, all_targets: bool, all_features: bool) {
+    /// Replace the editor settings with those in `value`; returns whether
+    /// analyses give other results with the new settings
+    async fn update_settings(&self, value: &serde_json::Value) -> bool {
+        if let Err(e) = Settings::from_value(value) {
+            log::warn!("invalid settings: {e}");
+            return false;
+        }
+        *self.client_settings.write().await = value.clone();
+        self.refresh_settings().await
+    }
+
+    /// Resolve the settings of the first workspace from the editor settings
+    /// and its project file; returns whether analyses give other results
+    async fn refresh_settings(&self) -> bool {
+        let client = self.client_settings.read().await.clone();
+        let settings = self.analyzers.read().await.first().map_or_else(
+            || Settings::from_value(&client).unwrap_or_default(),
+            |analyzer| analyzer.settings(&client),
+        );
+        let mut runtime_borrows = RuntimeBorrowTable::default();
+        runtime_borrows.extend(settings.runtime_borrows.clone());
+        *self.runtime_borrows.write().await = runtime_borrows;
+        let mut current = self.settings.write().await;
+        let changed = current.analysis_changed(&settings);
+        *current = settings;
+        changed
+    }
+
+    async fn analyze_with_options(&self) {
         log::info!("wait 100ms for rust-analyzer");
         time::sleep(time::Duration::from_millis(100)).await;
 
@@ -85,14 +160,19 @@ impl Backend {
         self.shutdown_subprocesses().await;
 
         log::info!("start analysis");
-        {
-            *self.status.write().await = progress::AnalysisStatus::Analyzing;
-        }
+        *self.status.write().await = progress::AnalysisStatus::Analyzing;
+        self.clear_build_errors().await;
+        AnalysisNotifier::notify(&self.client, AnalysisUpdate::Started).await;
         let analyzers = { self.analyzers.read().await.clone() };
+        let inlay_hint_refresh = *self.inlay_hint_refresh.read().await;
+        self.refresh_settings().await;
+        let client_settings = self.client_settings.read().await.clone();
+        let active_file = self.active_file.read().await.clone();
 
         log::info!("analyze {} packages...", analyzers.len());
         for analyzer in analyzers {
             let analyzed = self.analyzed.clone();
+            let build_errors = self.build_errors.clone();
             let client = self.client.clone();
             let work_done_progress = self.work_done_progress.clone();
             let cancellation_token = CancellationToken::new();
@@ -109,19 +189,25 @@ impl Backend {
             };
 
             let process_tokens = self.process_tokens.clone();
+            let progress_tokens = self.progress_tokens.clone();
+            let active_file = active_file.clone();
+            let settings = analyzer.settings(&client_settings);
             self.processes.write().await.spawn(async move {
-                #[allow(
-                    clippy::if_then_some_else_none,
-                    reason = "cannot use bool::then with async await"
-                )]
-                let progress_token = if *work_done_progress.read().await {
-                    Some(progress::ProgressToken::begin(client.clone(), None::<&str>).await)
-                } else {
-                    None
-                };
+                let progress_token = Self::begin_progress(
+                    &client,
+                    &work_done_progress,
+                    &progress_tokens,
+                    &cancellation_token,
+                )
+                .await;
+                let mut notifier = AnalysisNotifier::new(
+                    client.clone(),
+                    inlay_hint_refresh,
+                    active_file.as_deref(),
+                );
 
-                let mut iter = analyzer.analyze(all_targets, all_features).await;
-                let mut analyzed_package_count = 0;
+                let mut iter = analyzer.analyze(&settings, active_file.as_deref()).await;
+                let mut progress = progress::AnalysisProgress::default();
                 while let Some(event) = tokio::select! {
                     () = cancellation_token.cancelled() => None,
                     event = iter.next_event() => event,
@@ -129,53 +215,163 @@ impl Backend {
                     match event {
                         AnalyzerEvent::CrateChecked {
                             package,
-                            package_count,
+                            unit_count,
                         } => {
-                            analyzed_package_count += 1;
+                            progress.unit_checked(unit_count);
                             if let Some(token) = &progress_token {
-                                let percentage =
-                                    (analyzed_package_count * 100 / package_count).min(100);
-                                #[allow(
-                                    clippy::cast_possible_truncation,
-                                    reason = "percentage is 0-100"
-                                )]
-                                let percentage_u32 = percentage as u32;
                                 token
                                     .report(
-                                        Some(format!("{package} analyzed")),
-                                        Some(percentage_u32),
+                                        Some(format!("{package} checked")),
+                                        progress.percentage(),
                                     )
                                     .await;
                             }
+                            notifier.crate_checked(package).await;
+                        }
+                        AnalyzerEvent::CrateAnalyzing { krate, functions } => {
+                            progress.crate_analyzing(krate, functions);
+                        }
+                        AnalyzerEvent::BuildError(error) => {
+                            Self::add_build_error(&build_errors, error).await;
                         }
                         AnalyzerEvent::Analyzed(ws) => {
-                            let write = &mut *analyzed.write().await;
-                            for krate in ws.0.into_values() {
-                                if let Some(write) = write {
-                                    write.merge(krate);
-                                } else {
-                                    *write = Some(krate);
-                                }
+                            let message = progress.workspace_analyzed(&ws);
+                            if let (Some(token), Some(message)) = (&progress_token, message) {
+                                token.report(Some(message), progress.percentage()).await;
                             }
+                            notifier.files_analyzed(&ws);
+                            Self::merge_analyzed(&analyzed, ws).await;
+                            notifier.flush_if_due().await;
                         }
                     }
                 }
+                notifier.flush().await;
                 // remove cancellation token from list
                 process_tokens.write().await.remove(&cancellation_token_key);
 
                 if let Some(progress_token) = progress_token {
+                    if let Some(token) = progress_token.token() {
+                        progress_tokens.write().await.remove(token);
+                    }
                     progress_token.finish().await;
                 }
             });
         }
 
+        self.finish_analysis();
+    }
+
+    /// Begin the work done progress of an analysis if the client supports it,
+    /// registering `cancellation_token` to be cancelled with the progress
+    async fn begin_progress(
+        client: &Client,
+        work_done_progress: &RwLock<bool>,
+        progress_tokens: &RwLock<HashMap<lsp_types::NumberOrString, CancellationToken>>,
+        cancellation_token: &CancellationToken,
+    ) -> Option<progress::ProgressToken> {
+        if !*work_done_progress.read().await {
+            return None;
+        }
+        let progress_token = progress::ProgressToken::begin(client.clone(), None::<&str>).await;
+        if let Some(token) = progress_token.token() {
+            progress_tokens
+                .write()
+                .await
+                .insert(token.clone(), cancellation_token.clone());
+        }
+        Some(progress_token)
+    }
+
+    async fn merge_analyzed(analyzed: &RwLock<Option<Crate>>, ws: Workspace) {
+        let write = &mut *analyzed.write().await;
+        for krate in ws.0.into_values() {
+            if let Some(write) = write {
+                write.merge(krate);
+            } else {
+                *write = Some(krate);
+            }
+        }
+    }
+
+    async fn add_build_error(errors: &RwLock<Vec<BuildError>>, error: BuildError) {
+        // library targets are checked again as tests
+        let mut errors = errors.write().await;
+        if !errors.contains(&error) {
+            errors.push(error);
+        }
+    }
+
+    /// Why an analysis without results failed
+    fn failure_detail(errors: &[BuildError]) -> String {
+        errors.first().map_or_else(
+            || "analysis produced no results".to_owned(),
+            BuildError::summary,
+        )
+    }
+
+    /// Notification of the end of an analysis, listing the files it touched
+    fn finished_update(
+        failed: bool,
+        analyzed: Option<&Crate>,
+        errors: &[BuildError],
+    ) -> AnalysisUpdate {
+        if failed {
+            let files: HashSet<_> = errors
+                .iter()
+                .filter_map(|error| error.path.as_ref())
+                .collect();
+            AnalysisUpdate::Failed {
+                detail: Self::failure_detail(errors),
+                uris: files
+                    .into_iter()
+                    .filter_map(|path| lsp_types::Url::from_file_path(path).ok())
+                    .collect(),
+            }
+        } else {
+            AnalysisUpdate::Finished {
+                uris: analyzed
+                    .iter()
+                    .flat_map(|analyzed| analyzed.0.keys())
+                    .filter_map(|file| lsp_types::Url::from_file_path(file).ok())
+                    .collect(),
+            }
+        }
+    }
+
+    /// Clear the diagnostics of the build errors of the previous analysis
+    async fn clear_build_errors(&self) {
+        let stale = mem::take(&mut *self.build_errors.write().await);
+        let files: HashSet<_> = stale.into_iter().filter_map(|error| error.path).collect();
+        for path in files {
+            if let Ok(uri) = lsp_types::Url::from_file_path(&path) {
+                self.client.publish_diagnostics(uri, Vec::new(), None).await;
+            }
+        }
+    }
+
+    /// Diagnostics of the build errors in `path`
+    fn build_error_diagnostics(errors: &[BuildError], path: &Path) -> Vec<lsp_types::Diagnostic> {
+        errors
+            .iter()
+            .filter(|error| error.path.as_deref() == Some(path))
+            .map(BuildError::to_diagnostic)
+            .collect()
+    }
+
+    /// Update the status and publish the lints once all analyses are done
+    fn finish_analysis(&self) {
         let processes = self.processes.clone();
         let status = self.status.clone();
         let analyzed = self.analyzed.clone();
+        let build_errors = self.build_errors.clone();
+        let client = self.client.clone();
+        let settings = self.settings.clone();
+        let inlay_hint_refresh = self.inlay_hint_refresh.clone();
         tokio::spawn(async move {
+            let inlay_hint_refresh = *inlay_hint_refresh.read().await;
             while { processes.write().await.join_next().await }.is_some() {}
             let mut status = status.write().await;
-            let analyzed = analyzed.write().await;
+            let analyzed = analyzed.write().await.downgrade();
             if *status != progress::AnalysisStatus::Error {
                 if analyzed.as_ref().map_or(0, |v| v.0.len()) == 0 {
                     *status = progress::AnalysisStatus::Error;
@@ -183,15 +379,113 @@ impl Backend {
                     *status = progress::AnalysisStatus::Finished;
                 }
             }
+            let failed = *status == progress::AnalysisStatus::Error;
+            drop(status);
+            let settings = settings.read().await.clone();
+            let errors = build_errors.read().await.clone();
+            Self::publish_lints(&client, analyzed.as_ref(), &errors, &settings).await;
+            let update = Self::finished_update(failed, analyzed.as_ref(), &errors);
+            drop(analyzed);
+            AnalysisNotifier::notify(&client, update).await;
+            AnalysisNotifier::refresh(&client, inlay_hint_refresh);
         });
     }
 
+    /// Lint diagnostics of the analyzed functions in `path` containing `text`,
+    /// with the severities of `settings`
+    fn lint_diagnostics(
+        analyzed: &Crate,
+        path: &Path,
+        text: &str,
+        settings: &Settings,
+    ) -> Vec<lsp_types::Diagnostic> {
+        let Ok(uri) = lsp_types::Url::from_file_path(path) else {
+            return Vec::new();
+        };
+        analyzed
+            .0
+            .iter()
+            .filter(|(filename, _)| path == PathBuf::from(filename))
+            .flat_map(|(_, file)| &file.items)
+            .flat_map(|func| lints::lints(func, &settings.lints))
+            .filter_map(|lint| {
+                let severity = settings.lint_severity(lint.code)?;
+                Some(lsp_types::Diagnostic {
+                    severity: Some(severity),
+                    ..lint.to_diagnostic(text, &uri)
+                })
+            })
+            .collect()
+    }
+
+    /// Quick fixes of the published lints of `path` overlapping `range`
+    fn lint_fixes(
+        analyzed: &Crate,
+        path: &Path,
+        text: &str,
+        range: lsp_types::Range,
+        settings: &Settings,
+    ) -> Vec<lsp_types::CodeActionOrCommand> {
+        let Ok(uri) = lsp_types::Url::from_file_path(path) else {
+            return Vec::new();
+        };
+        let from = Loc::from(text_conversion::line_char_to_index(
+            text,
+            range.start.line,
+            range.start.character,
+        ));
+        let until = Loc::from(text_conversion::line_char_to_index(
+            text,
+            range.end.line,
+            range.end.character,
+        ));
+        analyzed
+            .0
+            .iter()
+            .filter(|(filename, _)| path == PathBuf::from(filename))
+            .flat_map(|(_, file)| &file.items)
+            .flat_map(|func| lints::lints(func, &settings.lints))
+            .filter(|lint| settings.lint_severity(lint.code).is_some())
+            .filter(|lint| lint.range.from() <= until && from <= lint.range.until())
+            .filter_map(|lint| lint.to_code_action(text, &uri))
+            .map(lsp_types::CodeActionOrCommand::CodeAction)
+            .collect()
+    }
+
+    /// Publish the lints of every analyzed file and the build errors
+    async fn publish_lints(
+        client: &Client,
+        analyzed: Option<&Crate>,
+        errors: &[BuildError],
+        settings: &Settings,
+    ) {
+        let files: HashSet<_> = analyzed
+            .iter()
+            .flat_map(|analyzed| analyzed.0.keys().map(PathBuf::from))
+            .chain(errors.iter().filter_map(|error| error.path.clone()))
+            .collect();
+        for path in files {
+            let (Ok(text), Ok(uri)) = (
+                fs::read_to_string(&path),
+                lsp_types::Url::from_file_path(&path),
+            ) else {
+                continue;
+            };
+            let mut diagnostics = Self::build_error_diagnostics(errors, &path);
+            if let Some(analyzed) = analyzed {
+                diagnostics.extend(Self::lint_diagnostics(analyzed, &path, &text, settings));
+            }
+            client.publish_diagnostics(uri, diagnostics, None).await;
+        }
+    }
+
     async fn decos(
         &self,
         filepath: &Path,
         position: Loc,
     ) -> Result<Vec<decoration::Deco>, progress::AnalysisStatus> {
         let mut selected = decoration::SelectLocal::new(position);
+        let mut selected_lifetime = decoration::SelectLifetime::new(position);
         let mut error = progress::AnalysisStatus::Error;
         if let Some(analyzed) = &*self.analyzed.read().await {
             log::debug!(
@@ -208,6 +502,18 @@ impl Backend {
                     }
                     for item in &file.items {
                         range_ops::mir_visit(item, &mut selected);
+                        range_ops::mir_visit(item, &mut selected_lifetime);
+                        let status = match item.precision {
+                            AnalysisPrecision::TooLarge => progress::AnalysisStatus::TooLarge,
+                            AnalysisPrecision::TimedOut => progress::AnalysisStatus::TimedOut,
+                            _ => continue,
+                        };
+                        if item
+                            .span
+                            .is_some_and(|span| span.from() <= position && position < span.until())
+                        {
+                            error = status;
+                        }
                     }
                 }
             }
@@ -220,7 +526,16 @@ impl Backend {
             }
 
             log::debug!("Selected local: {:?}", selected.selected());
-            let mut calc = decoration::CalcDecos::new(selected.selected().iter().copied());
+            let mut calc = if let Some((fn_ids, name)) = selected_lifetime.selected() {
+                log::debug!("Selected lifetime: {name}");
+                decoration::CalcDecos::new([]).with_lifetime(fn_ids, name)
+            } else {
+                decoration::CalcDecos::new(selected.selected().iter().copied())
+            }
+            .with_runtime_borrows(self.runtime_borrows.read().await.clone())
+            .with_callees(decoration::callee_summaries(
+                analyzed.0.values().flat_map(|file| &file.items),
+            ));
             for (filename, file) in &analyzed.0 {
                 if filepath == PathBuf::from(filename) {
                     for item in &file.items {
@@ -251,6 +566,7 @@ impl Backend {
         if let Some(path) = params.path()
             && let Ok(text) = fs::read_to_string(&path)
         {
+            *self.active_file.write().await = Some(path.clone());
             let position = params.position();
             let pos = Loc::from(text_conversion::line_char_to_index(
                 &text,
@@ -268,7 +584,12 @@ impl Backend {
                     },
                 ),
             };
-            let items = decos.into_iter().map(|v| v.to_lsp_range(&text)).collect();
+            let settings = self.settings.read().await;
+            let items = decos
+                .into_iter()
+                .filter(|deco| settings.decoration_enabled(deco))
+                .map(|v| v.to_lsp_range(&text))
+                .collect();
             return Ok(decoration::Decorations {
                 is_analyzed,
                 status,
@@ -297,13 +618,19 @@ impl Backend {
                 position.character,
             ));
 
-            let diagnostics = match self.decos(path, pos).await {
+            let settings = self.settings.read().await.clone();
+            let mut diagnostics = match self.decos(path, pos).await {
                 Ok(decos) => {
                     log::debug!("Got {} decorations", decos.len());
                     decos
                         .into_iter()
-                        .filter(decoration::Deco::should_show_as_diagnostic)
-                        .map(|d| d.to_lsp_range(&text).to_diagnostic())
+                        .filter_map(|d| {
+                            let severity = settings.diagnostic_severity(&d)?;
+                            Some(lsp_types::Diagnostic {
+                                severity: Some(severity),
+                                ..d.to_lsp_range(&text).to_diagnostic()
+                            })
+                        })
                         .collect()
                 }
                 Err(e) => {
@@ -311,6 +638,13 @@ impl Backend {
                     Vec::new()
                 }
             };
+            if let Some(analyzed) = &*self.analyzed.read().await {
+                diagnostics.extend(Self::lint_diagnostics(analyzed, path, &text, &settings));
+            }
+            diagnostics.extend(Self::build_error_diagnostics(
+                &self.build_errors.read().await,
+                path,
+            ));
 
             log::debug!("Publishing {} diagnostics", diagnostics.len());
             let uri = lsp_types::Url::from_file_path(path).unwrap();
@@ -348,10 +682,157 @@ impl Backend {
                 self.do_analyze().await;
                 Ok(Some(serde_json::json!({ "status": "analyzing" })))
             }
+            CMD_EXPORT_ORIGIN_GRAPH => {
+                self.export_graph(&params.arguments, graph_export::origin_graph, true)
+                    .await
+            }
+            CMD_EXPORT_CFG => {
+                self.export_graph(&params.arguments, graph_export::cfg_graph, false)
+                    .await
+            }
             _ => Err(jsonrpc::Error::method_not_found()),
         }
     }
 
+    /// Render a graph of the function at the position given by `args`:
+    /// [`uri_string`, line, character, format?]; the function is analyzed
+    /// again to compute its origin subset graph if `with_origin_graph` is set
+    async fn export_graph(
+        &self,
+        args: &[serde_json::Value],
+        render: graph_export::Render,
+        with_origin_graph: bool,
+    ) -> jsonrpc::Result<Option<serde_json::Value>> {
+        let Some((path, position)) = Self::parse_position_args(args) else {
+            return Err(jsonrpc::Error::invalid_params(
+                "Expected arguments: [document_uri, line, character, format?]",
+            ));
+        };
+        let format: GraphFormat = args
+            .get(3)
+            .and_then(|v| serde_json::from_value(v.clone()).ok())
+            .unwrap_or_default();
+        let Ok(text) = fs::read_to_string(&path) else {
+            return Ok(None);
+        };
+        let pos = Loc::from(text_conversion::line_char_to_index(
+            &text,
+            position.line,
+            position.character,
+        ));
+        let Some(mut func) = self
+            .analyzed
+            .read()
+            .await
+            .as_ref()
+            .and_then(|analyzed| {
+                analyzed
+                    .0
+                    .iter()
+                    .find(|(filename, _)| path == PathBuf::from(filename))
+            })
+            .and_then(|(_, file)| graph_export::find_function_at(&file.items, pos))
+            .cloned()
+        else {
+            return Ok(None);
+        };
+        if with_origin_graph {
+            let client = self.client_settings.read().await.clone();
+            let settings = lsp_settings::with_origin_graph(&client, &func.name);
+            if let Some(analyzed) = Self::analyze_for_export(&path, &settings).await
+                && let Some((_, with_graph)) =
+                    graph_export::find_function_by_name(&analyzed, &func.name)
+            {
+                func = with_graph.clone();
+            }
+        }
+        let graph = Some(render(&func, &text, format));
+        match graph {
+            Some(Ok(graph)) => Ok(Some(
+                serde_json::json!({ "format": format, "graph": graph }),
+            )),
+            Some(Err(e)) => Err(jsonrpc::Error {
+                code: jsonrpc::ErrorCode::InternalError,
+                message: format!("Failed to render graph: {e}").into(),
+                data: None,
+            }),
+            None => Ok(None),
+        }
+    }
+
+    /// Inlay hints of the analyzed functions in `path` within `range`
+    async fn inlay_hints(
+        &self,
+        path: &Path,
+        range: lsp_types::Range,
+    ) -> Option<Vec<lsp_types::InlayHint>> {
+        let text = fs::read_to_string(path).ok()?;
+        let kinds = self.settings.read().await.inlay_hint_kinds();
+        let hints = self
+            .analyzed
+            .read()
+            .await
+            .as_ref()?
+            .0
+            .iter()
+            .find(|(filename, _)| path == PathBuf::from(filename))
+            .map(|(_, file)| {
+                let mut calc = inlay_hint::CalcInlayHints::with_kinds(kinds);
+                for item in &file.items {
+                    range_ops::mir_visit(item, &mut calc);
+                }
+                calc.hints()
+            })?;
+        Some(
+            hints
+                .iter()
+                .map(|hint| hint.to_lsp(&text))
+                .filter(|hint| range.start <= hint.position && hint.position <= range.end)
+                .collect(),
+        )
+    }
+
+    /// Hover of the call at `position`, explaining what the callee does with
+    /// the arguments
+    async fn call_hover(
+        &self,
+        path: &Path,
+        position: lsp_types::Position,
+    ) -> Option<lsp_types::Hover> {
+        let text = fs::read_to_string(path).ok()?;
+        let pos = Loc::from(text_conversion::line_char_to_index(
+            &text,
+            position.line,
+            position.character,
+        ));
+        let analyzed = self.analyzed.read().await;
+        let krate = analyzed.as_ref()?;
+        let callees = decoration::callee_summaries(krate.0.values().flat_map(|file| &file.items));
+        let hover = krate
+            .0
+            .iter()
+            .filter(|(filename, _)| path == PathBuf::from(filename))
+            .flat_map(|(_, file)| &file.items)
+            .filter_map(|func| decoration::call_hover(func, pos, &callees))
+            .min_by_key(|(range, _)| range.size());
+        drop(analyzed);
+        let (range, hover_text) = hover?;
+        let position_of = |loc| {
+            let (line, character) = text_conversion::index_to_line_char(&text, loc);
+            lsp_types::Position { line, character }
+        };
+        Some(lsp_types::Hover {
+            contents: lsp_types::HoverContents::Markup(lsp_types::MarkupContent {
+                kind: lsp_types::MarkupKind::Markdown,
+                value: hover_text,
+            }),
+            range: Some(lsp_types::Range {
+                start: position_of(range.from()),
+                end: position_of(range.until()),
+            }),
+        })
+    }
+
     /// Parse position arguments from command: [`uri_string`, line, character]
     fn parse_position_args(args: &[serde_json::Value]) -> Option<(PathBuf, lsp_types::Position)> {
         if args.is_empty() {
@@ -370,28 +851,52 @@ impl Backend {
         Some((path, lsp_types::Position { line, character }))
     }
 
-    pub async fn check_with_options(
+    pub async fn check_with_options(path: impl AsRef<Path>, settings: serde_json::Value) -> bool {
+        Self::analyze_path(path, settings)
+            .await
+            .is_some_and(|v| !v.0.is_empty())
+    }
+
+    /// Analyze `path` without a client and return the analysis results;
+    /// `settings` override those of the project file like editor settings
+    pub async fn analyze_path(
         path: impl AsRef<Path>,
-        all_targets: bool,
-        all_features: bool,
-    ) -> bool {
+        settings: serde_json::Value,
+    ) -> Option<Crate> {
         let path = path.as_ref();
         let (service, _) = LspService::build(Self::new).finish();
         let backend = service.inner();
+        *backend.client_settings.write().await = settings;
 
         if backend.add_analyze_target(path).await {
-            backend
-                .analyze_with_options(all_targets, all_features)
-                .await;
+            backend.analyze_with_options().await;
             while backend.processes.write().await.join_next().await.is_some() {}
-            backend
-                .analyzed
-                .read()
-                .await
-                .as_ref()
-                .is_some_and(|v| !v.0.is_empty())
+            backend.analyzed.write().await.take()
         } else {
-            false
+            None
+        }
+    }
+
+    /// Analyze the package owning `path` for a graph export, next to the
+    /// analysis of the editor, whose results and run are left alone
+    async fn analyze_for_export(path: &Path, settings: &serde_json::Value) -> Option<Crate> {
+        let analyzer = Analyzer::new(path).await.ok()?;
+        let settings = analyzer.settings(settings);
+        let mut iter = analyzer.analyze_for_export(&settings, path).await;
+        let results = RwLock::new(None);
+        while let Some(event) = iter.next_event().await {
+            if let AnalyzerEvent::Analyzed(ws) = event {
+                Self::merge_analyzed(&results, ws).await;
+            }
+        }
+        results.into_inner()
+    }
+
+    /// Cancel the analysis whose progress was cancelled in the editor
+    pub async fn work_done_progress_cancel(&self, params: lsp_types::WorkDoneProgressCancelParams) {
+        log::info!("analysis cancelled by the client");
+        if let Some(token) = self.progress_tokens.write().await.remove(&params.token) {
+            token.cancel();
         }
     }
 
@@ -424,7 +929,22 @@ impl LanguageServer for Backend {
         for path in workspaces {
             self.add_analyze_target(&path).await;
         }
-        self.do_analyze().await;
+        if let Some(options) = &params.initialization_options {
+            self.update_settings(options).await;
+        }
+        if params
+            .capabilities
+            .workspace
+            .as_ref()
+            .and_then(|v| v.inlay_hint.as_ref())
+            .and_then(|v| v.refresh_support)
+            .unwrap_or(false)
+        {
+            *self.inlay_hint_refresh.write().await = true;
+        }
+        if self.settings.read().await.auto_analyze.on_open() {
+            self.do_analyze().await;
+        }
 
         let sync_options = lsp_types::TextDocumentSyncOptions {
             open_close: Some(true),
@@ -441,7 +961,12 @@ impl LanguageServer for Backend {
         };
         // Advertise executeCommand capability with supported commands
         let execute_command_provider = lsp_types::ExecuteCommandOptions {
-            commands: vec![CMD_SHOW_OWNERSHIP.to_string(), CMD_ANALYZE.to_string()],
+            commands: vec![
+                CMD_SHOW_OWNERSHIP.to_string(),
+                CMD_ANALYZE.to_string(),
+                CMD_EXPORT_ORIGIN_GRAPH.to_string(),
+                CMD_EXPORT_CFG.to_string(),
+            ],
             work_done_progress_options: lsp_types::WorkDoneProgressOptions::default(),
         };
         // Advertise code action support
@@ -451,6 +976,8 @@ impl LanguageServer for Backend {
             workspace: Some(workspace_cap),
             execute_command_provider: Some(execute_command_provider),
             code_action_provider: Some(code_action_provider),
+            inlay_hint_provider: Some(lsp_types::OneOf::Left(true)),
+            hover_provider: Some(lsp_types::HoverProviderCapability::Simple(true)),
             ..Default::default()
         };
         let init_res = lsp_types::InitializeResult {
@@ -487,19 +1014,37 @@ impl LanguageServer for Backend {
         for added in params.event.added {
             if let Ok(path) = added.uri.to_file_path()
                 && self.add_analyze_target(&path).await
+                && self.settings.read().await.auto_analyze.on_open()
             {
                 self.do_analyze().await;
             }
         }
     }
 
+    async fn did_change_configuration(&self, params: lsp_types::DidChangeConfigurationParams) {
+        // clients that pull the configuration send no settings
+        if params.settings.is_null() {
+            return;
+        }
+        if self.update_settings(&params.settings).await
+            && self.settings.read().await.auto_analyze.on_open()
+        {
+            log::info!("analysis settings changed, re-analyzing");
+            self.do_analyze().await;
+        }
+    }
+
     async fn did_open(&self, params: lsp_types::DidOpenTextDocumentParams) {
         if let Ok(path) = params.text_document.uri.to_file_path()
             && path.is_file()
             && params.text_document.language_id == "rust"
-            && self.add_analyze_target(&path).await
         {
-            self.do_analyze().await;
+            *self.active_file.write().await = Some(path.clone());
+            if self.add_analyze_target(&path).await
+                && self.settings.read().await.auto_analyze.on_open()
+            {
+                self.do_analyze().await;
+            }
         }
     }
 
@@ -511,8 +1056,10 @@ impl LanguageServer for Backend {
     async fn did_save(&self, params: lsp_types::DidSaveTextDocumentParams) {
         if let Ok(path) = params.text_document.uri.to_file_path()
             && path.extension().is_some_and(|ext| ext == "rs")
+            && self.settings.read().await.auto_analyze.on_save()
         {
             log::info!("Rust file saved, re-analyzing: {}", path.display());
+            *self.active_file.write().await = Some(path);
             self.do_analyze().await;
         }
     }
@@ -560,10 +1107,44 @@ impl LanguageServer for Backend {
             ..Default::default()
         };
 
-        Ok(Some(vec![
-            lsp_types::CodeActionOrCommand::CodeAction(show_action),
-            lsp_types::CodeActionOrCommand::CodeAction(analyze_action),
-        ]))
+        let mut actions = Vec::new();
+        if let Ok(path) = uri.to_file_path()
+            && let Ok(text) = fs::read_to_string(&path)
+            && let Some(analyzed) = &*self.analyzed.read().await
+        {
+            let settings = self.settings.read().await.clone();
+            actions.extend(Self::lint_fixes(
+                analyzed,
+                &path,
+                &text,
+                params.range,
+                &settings,
+            ));
+        }
+        actions.push(lsp_types::CodeActionOrCommand::CodeAction(show_action));
+        actions.push(lsp_types::CodeActionOrCommand::CodeAction(analyze_action));
+        Ok(Some(actions))
+    }
+
+    async fn inlay_hint(
+        &self,
+        params: lsp_types::InlayHintParams,
+    ) -> jsonrpc::Result<Option<Vec<lsp_types::InlayHint>>> {
+        let Ok(path) = params.text_document.uri.to_file_path() else {
+            return Ok(None);
+        };
+        Ok(self.inlay_hints(&path, params.range).await)
+    }
+
+    async fn hover(
+        &self,
+        params: lsp_types::HoverParams,
+    ) -> jsonrpc::Result<Option<lsp_types::Hover>> {
+        let position = params.text_document_position_params;
+        let Ok(path) = position.text_document.uri.to_file_path() else {
+            return Ok(None);
+        };
+        Ok(self.call_hover(&path, position.position).await)
     }
 
     async fn execute_command(
@@ -0,0 +1,562 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+};
+
+use serde::Deserialize;
+use tower_lsp::lsp_types;
+
+use crate::{
+    lints::LintOptions, lsp_decoration::Deco, lsp_inlay_hint::InlayHintKind,
+    mir_polonius::PoloniusOptions, runtime_borrow::RuntimeBorrowApi,
+};
+
+/// Section of the client configuration holding the settings
+pub const SECTION: &str = "ferrous-owl";
+
+/// Settings file of a workspace, checked into its repository
+pub const PROJECT_FILE: &str = ".ferrous-owl.toml";
+
+/// Settings of the language server, read from the initialization options and
+/// `workspace/didChangeConfiguration`
+#[derive(Deserialize, Clone, PartialEq, Eq, Debug, Default)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Settings {
+    pub cargo: CargoSettings,
+    /// Settings of each decoration kind, by its `type`, of each inlay hint
+    /// kind and of each lint, by its code
+    pub decorations: HashMap<String, DecorationSettings>,
+    pub auto_analyze: AutoAnalyze,
+    /// Whether analysis results are cached in the target directory
+    pub cache: CacheSettings,
+    /// Worker threads analyzing functions in each compiler process
+    pub threads: Option<usize>,
+    pub polonius: PoloniusOptions,
+    pub runtime_borrows: Vec<RuntimeBorrowApi>,
+    /// Packages, or paths relative to the workspace root, that are not
+    /// analyzed
+    pub exclude: Vec<String>,
+    /// Environment variables of cargo and the compilers it runs
+    pub env: BTreeMap<String, String>,
+    /// Function whose origin subset graph is computed, for graph exports
+    pub origin_graph: Option<String>,
+    #[serde(flatten)]
+    pub lints: LintOptions,
+}
+
+impl Settings {
+    /// Read the settings from `value`, which is either the settings or the
+    /// whole client configuration containing the settings section; settings
+    /// set to `null` take their default value
+    pub fn from_value(value: &serde_json::Value) -> Result<Self, serde_json::Error> {
+        let value = value.get(SECTION).unwrap_or(value);
+        if value.is_null() {
+            return Ok(Self::default());
+        }
+        let mut settings = serde_json::json!({});
+        merge(&mut settings, value);
+        Self::deserialize(settings)
+    }
+
+    /// Settings of the workspace at `root`: the editor settings `client`
+    /// override those of the project file of the workspace
+    #[must_use]
+    pub fn of_workspace(root: &Path, client: &serde_json::Value) -> Self {
+        let client = client.get(SECTION).unwrap_or(client);
+        let Some(mut value) = project_settings(root) else {
+            return Self::from_value(client).unwrap_or_default();
+        };
+        merge(&mut value, client);
+        Self::from_value(&value).unwrap_or_else(|e| {
+            log::warn!("invalid settings of {}: {e}", root.display());
+            Self::from_value(client).unwrap_or_default()
+        })
+    }
+
+    /// Excluded paths of the workspace at `root`, i.e. the excluded entries
+    /// that are not one of `packages`
+    #[must_use]
+    pub fn excluded_paths(&self, root: &Path, packages: &[&str]) -> Vec<PathBuf> {
+        self.exclude
+            .iter()
+            .filter(|entry| !packages.contains(&entry.as_str()))
+            .map(|entry| root.join(entry))
+            .collect()
+    }
+
+    /// Whether analyses with `other` give other results
+    #[must_use]
+    pub fn analysis_changed(&self, other: &Self) -> bool {
+        self.cargo != other.cargo
+            || self.cache != other.cache
+            || self.threads != other.threads
+            || self.polonius != other.polonius
+            || self.exclude != other.exclude
+            || self.env != other.env
+    }
+
+    fn decoration<R>(&self, deco: &Deco<R>) -> DecorationSettings {
+        self.decorations
+            .get(deco.kind())
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Whether `deco` is shown at all
+    #[must_use]
+    pub fn decoration_enabled<R>(&self, deco: &Deco<R>) -> bool {
+        self.decoration(deco).enabled
+    }
+
+    /// Inlay hint kinds that are shown
+    #[must_use]
+    pub fn inlay_hint_kinds(&self) -> HashSet<InlayHintKind> {
+        InlayHintKind::ALL
+            .into_iter()
+            .filter(|kind| {
+                self.decorations
+                    .get(kind.name())
+                    .is_none_or(|settings| settings.enabled)
+            })
+            .collect()
+    }
+
+    /// Severity of `deco` as a diagnostic, or `None` if it is not published
+    #[must_use]
+    pub fn diagnostic_severity<R>(&self, deco: &Deco<R>) -> Option<lsp_types::DiagnosticSeverity> {
+        self.decoration(deco).diagnostic_severity(|| {
+            deco.should_show_as_diagnostic()
+                .then(|| deco.diagnostic_severity())
+        })
+    }
+
+    /// Severity of the lint `code` as a diagnostic, a warning unless set
+    /// under `decorations` like that of a decoration kind, or `None` if it
+    /// is not published
+    #[must_use]
+    pub fn lint_severity(&self, code: &str) -> Option<lsp_types::DiagnosticSeverity> {
+        self.decorations
+            .get(code)
+            .copied()
+            .unwrap_or_default()
+            .diagnostic_severity(|| Some(lsp_types::DiagnosticSeverity::WARNING))
+    }
+}
+
+/// The editor settings `client` with the origin subset graph of `function`
+#[must_use]
+pub fn with_origin_graph(client: &serde_json::Value, function: &str) -> serde_json::Value {
+    let mut client = client.clone();
+    let settings = if client.get(SECTION).is_some() {
+        &mut client[SECTION]
+    } else {
+        &mut client
+    };
+    if !settings.is_object() {
+        *settings = serde_json::json!({});
+    }
+    settings["originGraph"] = function.into();
+    client
+}
+
+/// Read the project file of the workspace at `root`, if it has one
+fn project_settings(root: &Path) -> Option<serde_json::Value> {
+    let path = root.join(PROJECT_FILE);
+    let text = fs::read_to_string(&path).ok()?;
+    match toml::from_str(&text) {
+        Ok(value) => Some(value),
+        Err(e) => {
+            log::warn!("invalid {}: {e}", path.display());
+            None
+        }
+    }
+}
+
+/// Override the settings in `base` with those set in `over`; settings set to
+/// `null` are removed, so they take their default value
+fn merge(base: &mut serde_json::Value, over: &serde_json::Value) {
+    match (base, over) {
+        (serde_json::Value::Object(base), serde_json::Value::Object(over)) => {
+            for (key, value) in over {
+                if value.is_null() {
+                    base.remove(key);
+                } else {
+                    merge(base.entry(key).or_insert(serde_json::Value::Null), value);
+                }
+            }
+        }
+        (_, serde_json::Value::Null) => {}
+        // nested settings set to `null` are removed as well
+        (base, serde_json::Value::Object(_)) => {
+            *base = serde_json::json!({});
+            merge(base, over);
+        }
+        (base, over) => *base = over.clone(),
+    }
+}
+
+/// Targets and features that cargo checks
+#[derive(Deserialize, Clone, PartialEq, Eq, Debug)]
+#[serde(rename_all = "camelCase", default)]
+pub struct CargoSettings {
+    /// Packages to check instead of the whole workspace
+    pub packages: Vec<String>,
+    /// Check all targets, unless some kinds of targets are selected
+    pub all_targets: bool,
+    pub lib: bool,
+    pub bins: bool,
+    pub tests: bool,
+    pub examples: bool,
+    pub benches: bool,
+    pub all_features: bool,
+    pub no_default_features: bool,
+    pub features: Vec<String>,
+    pub profile: Option<String>,
+    /// Target triple to check for instead of the host
+    pub target: Option<String>,
+    /// Number of parallel cargo jobs
+    pub jobs: Option<u32>,
+}
+impl Default for CargoSettings {
+    fn default() -> Self {
+        Self {
+            packages: Vec::new(),
+            // include test code
+            all_targets: true,
+            lib: false,
+            bins: false,
+            tests: false,
+            examples: false,
+            benches: false,
+            all_features: false,
+            no_default_features: false,
+            features: Vec::new(),
+            profile: None,
+            target: None,
+            jobs: None,
+        }
+    }
+}
+impl CargoSettings {
+    /// Arguments of `cargo check` selecting the targets, features and
+    /// profile, but not the packages
+    #[must_use]
+    pub fn args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        let kinds = [
+            (self.lib, "--lib"),
+            (self.bins, "--bins"),
+            (self.tests, "--tests"),
+            (self.examples, "--examples"),
+            (self.benches, "--benches"),
+        ];
+        if kinds.iter().any(|(selected, _)| *selected) {
+            args.extend(
+                kinds
+                    .iter()
+                    .filter(|(selected, _)| *selected)
+                    .map(|(_, arg)| (*arg).to_owned()),
+            );
+        } else if self.all_targets {
+            args.push("--all-targets".to_owned());
+        }
+        if self.all_features {
+            args.push("--all-features".to_owned());
+        }
+        if self.no_default_features {
+            args.push("--no-default-features".to_owned());
+        }
+        if !self.features.is_empty() {
+            args.push("--features".to_owned());
+            args.push(self.features.join(","));
+        }
+        if let Some(profile) = &self.profile {
+            args.push("--profile".to_owned());
+            args.push(profile.clone());
+        }
+        if let Some(target) = &self.target {
+            args.push("--target".to_owned());
+            args.push(target.clone());
+        }
+        if let Some(jobs) = self.jobs {
+            args.push("--jobs".to_owned());
+            args.push(jobs.to_string());
+        }
+        args
+    }
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "camelCase", default)]
+pub struct DecorationSettings {
+    /// Whether decorations of the kind are shown, both in the editor and as
+    /// diagnostics
+    pub enabled: bool,
+    /// Severity of the diagnostics of the kind, instead of the default one
+    pub severity: Option<Severity>,
+}
+impl Default for DecorationSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            severity: None,
+        }
+    }
+}
+impl DecorationSettings {
+    /// Severity of the diagnostics of the kind, `default` if none is set
+    fn diagnostic_severity(
+        self,
+        default: impl FnOnce() -> Option<lsp_types::DiagnosticSeverity>,
+    ) -> Option<lsp_types::DiagnosticSeverity> {
+        if !self.enabled {
+            return None;
+        }
+        match self.severity {
+            Some(Severity::Error) => Some(lsp_types::DiagnosticSeverity::ERROR),
+            Some(Severity::Warning) => Some(lsp_types::DiagnosticSeverity::WARNING),
+            Some(Severity::Information) => Some(lsp_types::DiagnosticSeverity::INFORMATION),
+            Some(Severity::Hint) => Some(lsp_types::DiagnosticSeverity::HINT),
+            Some(Severity::Off) => None,
+            None => default(),
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub enum Severity {
+    Error,
+    Warning,
+    Information,
+    Hint,
+    /// Not published as a diagnostic
+    Off,
+}
+
+/// When the server starts an analysis on its own
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum AutoAnalyze {
+    /// On startup, when a file of a new workspace is opened and when a file is
+    /// saved
+    #[default]
+    OnSave,
+    /// On startup and when a file of a new workspace is opened
+    OnOpen,
+    /// Only when requested
+    Never,
+}
+
+impl AutoAnalyze {
+    #[must_use]
+    pub const fn on_open(self) -> bool {
+        !matches!(self, Self::Never)
+    }
+
+    #[must_use]
+    pub const fn on_save(self) -> bool {
+        matches!(self, Self::OnSave)
+    }
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "camelCase", default)]
+pub struct CacheSettings {
+    pub enabled: bool,
+}
+impl Default for CacheSettings {
+    fn default() -> Self {
+        Self { enabled: true }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{FnLocal, Loc, Range};
+
+    fn deco(kind: &str) -> Deco<Range> {
+        let local = FnLocal::new(1, 0);
+        let range = Range::new(Loc::from(0u32), Loc::from(1u32)).unwrap();
+        let hover_text = String::new();
+        match kind {
+            "lifetime" => Deco::Lifetime {
+                local,
+                range,
+                hover_text,
+                overlapped: false,
+            },
+            _ => Deco::Move {
+                local,
+                range,
+                hover_text,
+                overlapped: false,
+            },
+        }
+    }
+
+    #[test]
+    fn settings_of_initialization_options() {
+        let settings = Settings::from_value(&serde_json::json!({
+            "cargo": { "allTargets": false, "features": ["a", "b"], "jobs": 2 },
+            "decorations": {
+                "move": { "severity": "error" },
+                "lifetime": { "severity": "hint" },
+                "call": { "enabled": false },
+                "guard-held": { "severity": "error" },
+                "large-copy": { "severity": "off" }
+            },
+            "autoAnalyze": "never",
+            "cache": { "enabled": false },
+            "threads": 3,
+            "polonius": { "maxFacts": 10 },
+            "largeCopyThreshold": 64
+        }))
+        .unwrap();
+        assert_eq!(settings.cargo.args(), ["--features", "a,b", "--jobs", "2"]);
+        assert_eq!(settings.auto_analyze, AutoAnalyze::Never);
+        assert!(!settings.cache.enabled);
+        assert_eq!(settings.threads, Some(3));
+        assert_eq!(settings.polonius.max_facts, 10);
+        assert_eq!(settings.lints.large_copy_threshold, 64);
+        assert_eq!(settings.lints.large_copy_in_loop_threshold, 128);
+
+        assert_eq!(
+            settings.diagnostic_severity(&deco("move")),
+            Some(lsp_types::DiagnosticSeverity::ERROR)
+        );
+        assert_eq!(
+            settings.diagnostic_severity(&deco("lifetime")),
+            Some(lsp_types::DiagnosticSeverity::HINT)
+        );
+        assert_eq!(
+            settings.lint_severity("guard-held"),
+            Some(lsp_types::DiagnosticSeverity::ERROR)
+        );
+        assert_eq!(settings.lint_severity("large-copy"), None);
+        assert_eq!(
+            settings.lint_severity("needless-clone"),
+            Some(lsp_types::DiagnosticSeverity::WARNING)
+        );
+    }
+
+    #[test]
+    fn cargo_args_of_target_kinds_profile_and_target() {
+        let cargo: CargoSettings = serde_json::from_value(serde_json::json!({
+            "packages": ["app"],
+            "lib": true,
+            "tests": true,
+            "noDefaultFeatures": true,
+            "profile": "ci",
+            "target": "wasm32-unknown-unknown"
+        }))
+        .unwrap();
+        assert_eq!(
+            cargo.args(),
+            [
+                "--lib",
+                "--tests",
+                "--no-default-features",
+                "--profile",
+                "ci",
+                "--target",
+                "wasm32-unknown-unknown"
+            ]
+        );
+    }
+
+    #[test]
+    fn editor_settings_override_project_file() {
+        let root = tempfile::tempdir().unwrap();
+        fs::write(
+            root.path().join(PROJECT_FILE),
+            r#"
+exclude = ["generated", "vendored-crate"]
+largeCopyThreshold = 1024
+
+[cargo]
+features = ["serde"]
+noDefaultFeatures = true
+
+[env]
+PROTOC = "/usr/bin/protoc"
+
+[polonius]
+maxFacts = 1000
+"#,
+        )
+        .unwrap();
+
+        let client = serde_json::json!({
+            SECTION: { "cargo": { "features": ["tokio"] }, "polonius": { "timeoutMs": 5 } }
+        });
+        let settings = Settings::of_workspace(root.path(), &client);
+        assert_eq!(settings.cargo.features, ["tokio"]);
+        assert!(settings.cargo.no_default_features);
+        assert_eq!(settings.polonius.max_facts, 1000);
+        assert_eq!(settings.polonius.timeout_ms, 5);
+        assert_eq!(settings.lints.large_copy_threshold, 1024);
+        assert_eq!(settings.env["PROTOC"], "/usr/bin/protoc");
+        assert_eq!(
+            settings.excluded_paths(root.path(), &["vendored-crate"]),
+            [root.path().join("generated")]
+        );
+
+        let other = tempfile::tempdir().unwrap();
+        let settings = Settings::of_workspace(other.path(), &client);
+        assert!(!settings.cargo.no_default_features);
+        assert!(settings.exclude.is_empty());
+
+        // `null` unsets a setting of the project file back to its default
+        let client = serde_json::json!({
+            "largeCopyThreshold": null,
+            "cargo": { "noDefaultFeatures": null },
+            "polonius": { "maxFacts": null, "timeoutMs": 5 }
+        });
+        let settings = Settings::of_workspace(root.path(), &client);
+        let defaults = Settings::default();
+        assert_eq!(
+            settings.lints.large_copy_threshold,
+            defaults.lints.large_copy_threshold
+        );
+        assert!(!settings.cargo.no_default_features);
+        assert_eq!(settings.cargo.features, ["serde"]);
+        assert_eq!(settings.polonius.max_facts, defaults.polonius.max_facts);
+        assert_eq!(settings.polonius.timeout_ms, 5);
+        let settings = Settings::of_workspace(other.path(), &client);
+        assert_eq!(settings.polonius.timeout_ms, 5);
+        assert!(Settings::from_value(&client).is_ok());
+    }
+
+    #[test]
+    fn default_settings_and_configuration_section() {
+        let settings = Settings::from_value(&serde_json::json!({
+            SECTION: { "decorations": { "move": { "enabled": false } } }
+        }))
+        .unwrap();
+        assert!(!settings.decoration_enabled(&deco("move")));
+        assert_eq!(settings.diagnostic_severity(&deco("move")), None);
+
+        let settings = Settings::from_value(&serde_json::json!({
+            "decorations": { "drop_order": { "enabled": false } }
+        }))
+        .unwrap();
+        let kinds = settings.inlay_hint_kinds();
+        assert!(!kinds.contains(&InlayHintKind::DropOrder));
+        assert!(kinds.contains(&InlayHintKind::Closure));
+
+        let settings = Settings::from_value(&serde_json::Value::Null).unwrap();
+        assert_eq!(settings, Settings::default());
+        assert_eq!(settings.cargo.args(), ["--all-targets"]);
+        assert_eq!(settings.auto_analyze, AutoAnalyze::OnSave);
+        assert!(settings.decoration_enabled(&deco("lifetime")));
+        assert_eq!(settings.diagnostic_severity(&deco("lifetime")), None);
+        assert_eq!(
+            settings.diagnostic_severity(&deco("move")),
+            Some(lsp_types::DiagnosticSeverity::WARNING)
+        );
+        assert!(!settings.analysis_changed(&Settings::default()));
+    }
+}
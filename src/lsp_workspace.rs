@@ -1,22 +1,301 @@
 use std::{
+    collections::HashMap,
+    fs,
     path::{Path, PathBuf},
     process::Stdio,
     sync::Arc,
 };
 
+use cargo_metadata::diagnostic::{Diagnostic, DiagnosticLevel};
+use serde::Serialize;
 use tokio::{
     io::{AsyncBufReadExt, BufReader},
-    process::{self, Command},
+    process::Command,
     sync::{Notify, mpsc},
     task,
 };
-
-use crate::{models::Workspace, rustc_wrapper as compiler, toolchain};
+use tokio_util::sync::CancellationToken;
+use tower_lsp::lsp_types;
+
+use crate::{
+    lsp_settings::Settings,
+    mir_polonius::PoloniusOptions,
+    models::{CrateFunctions, Workspace},
+    rust_project::{self, RustProject},
+    rustc_wrapper as compiler, text_conversion, toolchain,
+};
 
 fn set_cache_path(cmd: &mut Command, target_dir: impl AsRef<Path>) {
     cmd.env(toolchain::CACHE_DIR_ENV, target_dir.as_ref().join("cache"));
 }
 
+/// Start a new analysis run of `target_dir`, which makes the compilers of any
+/// previous run stop analyzing; returns the run file and the id of the run
+fn start_run(target_dir: &Path) -> Option<(PathBuf, String)> {
+    let path = target_dir.join("run");
+    let run_id = uuid::Uuid::new_v4().to_string();
+    if let Err(e) = fs::create_dir_all(target_dir).and_then(|()| fs::write(&path, &run_id)) {
+        log::warn!("failed to write run file {}: {e}", path.display());
+        return None;
+    }
+    Some((path, run_id))
+}
+
+/// Environment of the compilers of an analysis run, which belong to `run` and
+/// write their cache into `target_dir`
+fn set_analysis_env(
+    command: &mut Command,
+    settings: &Settings,
+    target_dir: &Path,
+    run: Option<&(PathBuf, String)>,
+    active_file: Option<&Path>,
+    excluded_paths: &[PathBuf],
+) {
+    if settings.cache.enabled {
+        set_cache_path(command, target_dir);
+    }
+    if let Some(threads) = settings.threads {
+        command.env(toolchain::THREADS_ENV, threads.to_string());
+    }
+    if let Some((path, run_id)) = run {
+        command
+            .env(toolchain::RUN_FILE_ENV, path)
+            .env(toolchain::RUN_ID_ENV, run_id);
+    }
+    if let Some(file) = active_file {
+        command.env(toolchain::ACTIVE_FILE_ENV, file);
+    }
+    if !excluded_paths.is_empty()
+        && let Ok(paths) = serde_json::to_string(excluded_paths)
+    {
+        command.env(toolchain::EXCLUDE_ENV, paths);
+    }
+    if let Ok(options) = serde_json::to_string(&settings.polonius) {
+        command.env(toolchain::POLONIUS_OPTIONS_ENV, options);
+    }
+    if let Some(function) = &settings.origin_graph {
+        command.env(toolchain::ORIGIN_GRAPH_ENV, function);
+    }
+}
+
+/// Workspace package with the innermost manifest directory containing `file`
+fn owning_package<'a>(
+    metadata: &'a cargo_metadata::Metadata,
+    file: &Path,
+) -> Option<&'a cargo_metadata::Package> {
+    metadata
+        .workspace_packages()
+        .into_iter()
+        .filter(|package| {
+            package
+                .manifest_path
+                .parent()
+                .is_some_and(|dir| file.starts_with(dir))
+        })
+        .max_by_key(|package| package.manifest_path.as_str().len())
+}
+
+/// Packages that `cargo check` selects: the selected packages or the
+/// workspace without the excluded `packages`. The package owning the active
+/// file is not checked on its own first: cargo unifies the features of the
+/// dependencies of the selected packages only, so it would check it and its
+/// dependencies with other features than the whole workspace, and again
+fn package_selection(packages: &[&str], settings: &Settings) -> Vec<String> {
+    let is_excluded = |package: &str| settings.exclude.iter().any(|entry| entry == package);
+    let mut selection = Vec::new();
+    if settings.cargo.packages.is_empty() {
+        selection.push("--workspace".to_owned());
+        for package in packages.iter().filter(|package| is_excluded(package)) {
+            selection.extend(["--exclude".to_owned(), (*package).to_owned()]);
+        }
+    } else {
+        for package in settings
+            .cargo
+            .packages
+            .iter()
+            .filter(|package| !is_excluded(package))
+        {
+            selection.extend(["--package".to_owned(), package.clone()]);
+        }
+    }
+    selection
+}
+
+/// Run the cargo `command` and forward its events
+async fn run_check(
+    mut command: Command,
+    sender: mpsc::Sender<AnalyzerEvent>,
+    unit_count: usize,
+    workspace_root: PathBuf,
+) {
+    let mut child = match command.spawn() {
+        Ok(child) => child,
+        Err(e) => {
+            log::error!("failed to run cargo: {e}");
+            return;
+        }
+    };
+    let mut stdout = BufReader::new(child.stdout.take().unwrap()).lines();
+    while let Ok(Some(line)) = stdout.next_line().await {
+        if let Ok(CargoCheckMessage::CompilerArtifact { target }) = serde_json::from_str(&line) {
+            let checked = target.name;
+            log::debug!("crate {checked} checked");
+
+            let event = AnalyzerEvent::CrateChecked {
+                package: checked,
+                unit_count,
+            };
+            let _ = sender.send(event).await;
+        }
+        if let Ok(CargoCheckMessage::CompilerMessage { target, message }) =
+            serde_json::from_str(&line)
+            && matches!(message.level, DiagnosticLevel::Error | DiagnosticLevel::Ice)
+        {
+            let error = BuildError::new(target.name, &message, &workspace_root);
+            log::info!("{}", error.summary());
+            let _ = sender.send(AnalyzerEvent::BuildError(error)).await;
+        }
+        forward_analysis(&line, &sender).await;
+    }
+    log::debug!("stdout closed");
+    child.wait().await.ok();
+}
+
+/// Forward the analysis results a compiler prints as `line`
+async fn forward_analysis(line: &str, sender: &mpsc::Sender<AnalyzerEvent>) {
+    if let Ok(CrateFunctions { name, functions }) = serde_json::from_str(line) {
+        let event = AnalyzerEvent::CrateAnalyzing {
+            krate: name,
+            functions,
+        };
+        let _ = sender.send(event).await;
+    }
+    if let Ok(ws) = serde_json::from_str::<Workspace>(line) {
+        let event = AnalyzerEvent::Analyzed(ws);
+        let _ = sender.send(event).await;
+    }
+}
+
+/// Compiler of a crate of a `rust-project.json`
+struct CrateCompiler {
+    index: usize,
+    name: String,
+    /// Indices of the crates it depends on
+    deps: Vec<usize>,
+    command: Command,
+}
+
+/// Run the compilers of the crates of a `rust-project.json` one after another
+/// and forward their events; crates depending on a crate that failed to
+/// compile, or on one of the crates `unavailable` for the given reason, are
+/// skipped
+async fn run_compilers(
+    compilers: Vec<CrateCompiler>,
+    unavailable: HashMap<usize, String>,
+    sender: mpsc::Sender<AnalyzerEvent>,
+    project_root: PathBuf,
+) {
+    let unit_count = compilers.len();
+    // why the crates depending on each crate that failed to compile, was
+    // skipped or is unavailable are skipped
+    let mut failed = unavailable;
+    for CrateCompiler {
+        index,
+        name,
+        deps,
+        command,
+    } in compilers
+    {
+        let compiled = if let Some(reason) = deps.iter().find_map(|dep| failed.get(dep)) {
+            let error = BuildError::without_location(name.clone(), reason.clone());
+            log::info!("{}", error.summary());
+            let _ = sender.send(AnalyzerEvent::BuildError(error)).await;
+            false
+        } else {
+            run_compiler(&name, command, &sender, &project_root).await
+        };
+        if !compiled {
+            failed.insert(index, format!("dependency {name} failed to compile"));
+        }
+
+        log::debug!("crate {name} checked");
+        let event = AnalyzerEvent::CrateChecked {
+            package: name,
+            unit_count,
+        };
+        let _ = sender.send(event).await;
+    }
+}
+
+/// Run the compiler of crate `krate` and forward its events; returns whether
+/// the crate compiled
+async fn run_compiler(
+    krate: &str,
+    mut command: Command,
+    sender: &mpsc::Sender<AnalyzerEvent>,
+    project_root: &Path,
+) -> bool {
+    let send_error = async |error: BuildError| {
+        log::info!("{}", error.summary());
+        let _ = sender.send(AnalyzerEvent::BuildError(error)).await;
+    };
+    let mut child = match command.spawn() {
+        Ok(child) => child,
+        Err(e) => {
+            send_error(BuildError::without_location(
+                krate.to_owned(),
+                format!("failed to run the compiler: {e}"),
+            ))
+            .await;
+            return false;
+        }
+    };
+    let mut stdout = BufReader::new(child.stdout.take().unwrap()).lines();
+    let mut stderr = BufReader::new(child.stderr.take().unwrap()).lines();
+    let results = async {
+        while let Ok(Some(line)) = stdout.next_line().await {
+            forward_analysis(&line, sender).await;
+        }
+    };
+    // the compiler prints its diagnostics as JSON, between log lines
+    let errors = async {
+        let mut reported = false;
+        while let Ok(Some(line)) = stderr.next_line().await {
+            match serde_json::from_str::<Diagnostic>(&line) {
+                Ok(diagnostic)
+                    if matches!(
+                        diagnostic.level,
+                        DiagnosticLevel::Error | DiagnosticLevel::Ice
+                    ) =>
+                {
+                    reported = true;
+                    send_error(BuildError::new(krate.to_owned(), &diagnostic, project_root)).await;
+                }
+                // log lines of the compiler, formatted already
+                Err(_) if log::log_enabled!(log::Level::Info) => eprintln!("{line}"),
+                _ => {}
+            }
+        }
+        reported
+    };
+    let ((), reported) = tokio::join!(results, errors);
+
+    match child.wait().await {
+        Ok(status) if status.success() => true,
+        status => {
+            if !reported {
+                let status = status.map_or_else(|e| e.to_string(), |status| status.to_string());
+                send_error(BuildError::without_location(
+                    krate.to_owned(),
+                    format!("the compiler failed with {status}"),
+                ))
+                .await;
+            }
+            false
+        }
+    }
+}
+
 #[derive(serde::Deserialize, Clone, Debug)]
 pub struct CargoCheckMessageTarget {
     name: String,
@@ -24,22 +303,147 @@ pub struct CargoCheckMessageTarget {
 #[derive(serde::Deserialize, Clone, Debug)]
 #[serde(tag = "reason", rename_all = "kebab-case")]
 pub enum CargoCheckMessage {
-    CompilerArtifact { target: CargoCheckMessageTarget },
+    CompilerArtifact {
+        target: CargoCheckMessageTarget,
+    },
+    CompilerMessage {
+        target: CargoCheckMessageTarget,
+        message: Diagnostic,
+    },
     BuildFinished {},
 }
 
+/// Error the compiler reported while checking a crate
+#[derive(Serialize, Clone, PartialEq, Eq, Debug)]
+pub struct BuildError {
+    /// Target that failed to compile
+    pub target: String,
+    pub message: String,
+    pub code: Option<String>,
+    /// File of the primary span of the error, if it has one
+    pub path: Option<PathBuf>,
+    pub range: Option<lsp_types::Range>,
+}
+impl BuildError {
+    fn new(target: String, diagnostic: &Diagnostic, workspace_root: &Path) -> Self {
+        let span = diagnostic.spans.iter().find(|span| span.is_primary);
+        // rustc counts lines and columns in characters from 1, and gives the
+        // text of the lines to count columns in UTF-16 code units with
+        let position = |line: usize, column: usize, text: Option<&str>| {
+            let column = column.saturating_sub(1);
+            lsp_types::Position {
+                line: u32::try_from(line.saturating_sub(1)).unwrap_or(u32::MAX),
+                character: text.map_or_else(
+                    || u32::try_from(column).unwrap_or(u32::MAX),
+                    |text| text_conversion::utf16_column(text, column),
+                ),
+            }
+        };
+        Self {
+            target,
+            message: diagnostic.message.clone(),
+            code: diagnostic.code.as_ref().map(|code| code.code.clone()),
+            path: span.map(|span| workspace_root.join(&span.file_name)),
+            range: span.map(|span| lsp_types::Range {
+                start: position(
+                    span.line_start,
+                    span.column_start,
+                    span.text.first().map(|line| line.text.as_str()),
+                ),
+                end: position(
+                    span.line_end,
+                    span.column_end,
+                    span.text.last().map(|line| line.text.as_str()),
+                ),
+            }),
+        }
+    }
+
+    /// Error of `target` that is not reported by the compiler
+    const fn without_location(target: String, message: String) -> Self {
+        Self {
+            target,
+            message,
+            code: None,
+            path: None,
+            range: None,
+        }
+    }
+
+    /// One line description for the analysis status
+    #[must_use]
+    pub fn summary(&self) -> String {
+        format!("crate {} failed to compile: {}", self.target, self.message)
+    }
+
+    /// The error as a diagnostic of its file
+    #[must_use]
+    pub fn to_diagnostic(&self) -> lsp_types::Diagnostic {
+        lsp_types::Diagnostic {
+            range: self.range.unwrap_or_default(),
+            severity: Some(lsp_types::DiagnosticSeverity::ERROR),
+            code: self.code.clone().map(lsp_types::NumberOrString::String),
+            code_description: None,
+            source: Some(env!("CARGO_PKG_NAME").to_string()),
+            message: self.summary(),
+            related_information: None,
+            tags: None,
+            data: None,
+        }
+    }
+}
+
+/// Output of `cargo check --unit-graph`
+#[derive(serde::Deserialize, Clone, Debug)]
+struct UnitGraph {
+    units: Vec<UnitGraphUnit>,
+}
+#[derive(serde::Deserialize, Clone, Debug)]
+struct UnitGraphUnit {
+    mode: String,
+}
+
 pub enum AnalyzerEvent {
     CrateChecked {
         package: String,
-        package_count: usize,
+        /// Number of build units of the whole check
+        unit_count: usize,
+    },
+    /// The compiler started analyzing the functions of a crate
+    CrateAnalyzing {
+        krate: String,
+        functions: usize,
     },
+    /// A crate failed to compile, so its functions are not analyzed
+    BuildError(BuildError),
     Analyzed(Workspace),
 }
 
+/// What an analysis run is for
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum RunScope {
+    /// The analysis of the workspace shown in the editor
+    Workspace,
+    /// The analysis of the package owning a file for a graph export, in a
+    /// target directory and with a run file of its own, so that the run of
+    /// the editor goes on
+    Export,
+}
+impl RunScope {
+    fn target_dir(self, owl_dir: PathBuf) -> PathBuf {
+        match self {
+            Self::Workspace => owl_dir,
+            Self::Export => owl_dir.join("export"),
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct Analyzer {
     path: PathBuf,
     metadata: Option<cargo_metadata::Metadata>,
+    /// Crates of a workspace that is not built with cargo
+    rust_project: Option<RustProject>,
 }
 
 impl Analyzer {
@@ -71,15 +475,32 @@ impl Analyzer {
             None
         };
 
+        let dir = if path.is_file() {
+            path.parent().unwrap()
+        } else {
+            &path
+        };
         if let Some(metadata) = metadata {
             Ok(Self {
                 path: metadata.workspace_root.as_std_path().to_path_buf(),
                 metadata: Some(metadata),
+                rust_project: None,
+            })
+        } else if let Some(project) = RustProject::discover(dir) {
+            log::info!(
+                "analyze the crates of {}",
+                project.root.join(rust_project::FILE_NAME).display()
+            );
+            Ok(Self {
+                path: project.root.clone(),
+                metadata: None,
+                rust_project: Some(project),
             })
         } else if path.is_file() && path.extension().is_some_and(|v| v == "rs") {
             Ok(Self {
                 path,
                 metadata: None,
+                rust_project: None,
             })
         } else {
             log::warn!("Invalid analysis target: {}", path.display());
@@ -91,27 +512,79 @@ impl Analyzer {
         &self.path
     }
 
-    pub async fn analyze(&self, all_targets: bool, all_features: bool) -> AnalyzeEventIter {
+    /// Settings of the analyses of this target, with the editor settings
+    /// `client`
+    #[must_use]
+    pub fn settings(&self, client: &serde_json::Value) -> Settings {
+        if self.metadata.is_some() || self.rust_project.is_some() {
+            Settings::of_workspace(&self.path, client)
+        } else {
+            Settings::from_value(client).unwrap_or_default()
+        }
+    }
+
+    pub async fn analyze(
+        &self,
+        settings: &Settings,
+        active_file: Option<&Path>,
+    ) -> AnalyzeEventIter {
         if let Some(metadata) = &self.metadata {
-            self.analyze_package(metadata, all_targets, all_features)
+            self.analyze_package(metadata, settings, active_file, RunScope::Workspace)
                 .await
+        } else if let Some(project) = &self.rust_project {
+            Self::analyze_rust_project(project, settings, active_file, RunScope::Workspace)
         } else {
-            self.analyze_single_file(&self.path).await
+            self.analyze_single_file(&self.path, settings.polonius, settings.origin_graph.clone())
+                .await
+        }
+    }
+
+    /// Analyze only the package or crate owning `file`, for graph exports,
+    /// without disturbing the analysis of the editor
+    pub async fn analyze_for_export(&self, settings: &Settings, file: &Path) -> AnalyzeEventIter {
+        if let Some(metadata) = &self.metadata {
+            self.analyze_package(metadata, settings, Some(file), RunScope::Export)
+                .await
+        } else if let Some(project) = &self.rust_project {
+            Self::analyze_rust_project(project, settings, Some(file), RunScope::Export)
+        } else {
+            self.analyze_single_file(&self.path, settings.polonius, settings.origin_graph.clone())
+                .await
         }
     }
 
     async fn analyze_package(
         &self,
         metadata: &cargo_metadata::Metadata,
-        all_targets: bool,
-        all_features: bool,
+        settings: &Settings,
+        active_file: Option<&Path>,
+        scope: RunScope,
     ) -> AnalyzeEventIter {
-        let target_dir = metadata.target_directory.as_std_path().join("owl");
+        let target_dir = scope.target_dir(metadata.target_directory.as_std_path().join("owl"));
+
+        let packages: Vec<&str> = metadata
+            .workspace_packages()
+            .into_iter()
+            .map(|package| package.name.as_str())
+            .collect();
+        let owning = active_file
+            .and_then(|file| owning_package(metadata, file))
+            .map(|package| package.name.to_string());
+        let selection = match (scope, &owning) {
+            (RunScope::Export, Some(package)) => vec!["--package".to_owned(), package.clone()],
+            _ => package_selection(&packages, settings),
+        };
 
-        log::info!("clear cargo cache for workspace");
+        // cargo only runs the compilers of packages that are not fresh
+        let clean_args = match (scope, &owning) {
+            (RunScope::Export, Some(package)) => vec!["--package", package.as_str()],
+            _ => vec!["--workspace"],
+        };
+        log::info!("clear cargo cache: cargo clean {}", clean_args.join(" "));
         let mut clean_cmd = toolchain::setup_cargo_command();
         clean_cmd
-            .args(["clean", "--workspace"])
+            .arg("clean")
+            .args(&clean_args)
             .env("CARGO_TARGET_DIR", &target_dir)
             .current_dir(&self.path)
             .stdout(Stdio::null())
@@ -120,26 +593,38 @@ impl Analyzer {
             child.wait().await.ok();
         }
 
-        let mut command = toolchain::setup_cargo_command();
+        let excluded_paths = settings.excluded_paths(&self.path, &packages);
 
-        let mut args = vec!["check", "--workspace"];
-        if all_targets {
-            args.push("--all-targets");
-        }
-        if all_features {
-            args.push("--all-features");
-        }
-        args.extend_from_slice(&["--keep-going", "--message-format=json"]);
+        let mut check_args = vec!["check".to_owned()];
+        check_args.extend(selection);
+        check_args.extend(settings.cargo.args());
+        check_args.push("--keep-going".to_owned());
+        let unit_count = self
+            .count_units(check_args.clone(), settings, &target_dir)
+            .await
+            .unwrap_or(metadata.packages.len());
+
+        let run = start_run(&target_dir);
+        let mut command = toolchain::setup_cargo_command();
 
         command
-            .args(args)
+            .args(check_args)
+            .arg("--message-format=json")
+            .envs(&settings.env)
             .env("CARGO_TARGET_DIR", &target_dir)
             .env_remove("RUSTC_WRAPPER")
             .current_dir(&self.path)
             .stdout(Stdio::piped())
             .kill_on_drop(true);
-
-        set_cache_path(&mut command, target_dir);
+        // the wrapper analyzes the functions of the active file first
+        set_analysis_env(
+            &mut command,
+            settings,
+            &target_dir,
+            run.as_ref(),
+            active_file,
+            &excluded_paths,
+        );
 
         if log::max_level()
             .to_level()
@@ -148,51 +633,166 @@ impl Analyzer {
             command.stderr(Stdio::null());
         }
 
-        let package_count = metadata.packages.len();
-
         let workspace_name = metadata
             .root_package()
             .map_or_else(|| self.path.display().to_string(), |p| p.name.to_string());
         log::info!("start analyzing package {workspace_name}");
-        let mut child = command.spawn().unwrap();
-        let mut stdout = BufReader::new(child.stdout.take().unwrap()).lines();
 
         let (sender, receiver) = mpsc::channel(1024);
         let notify = Arc::new(Notify::new());
         let notify_c = notify.clone();
-        let _handle = tokio::spawn(async move {
-            // prevent command from dropped
-            while let Ok(Some(line)) = stdout.next_line().await {
-                if let Ok(CargoCheckMessage::CompilerArtifact { target }) =
-                    serde_json::from_str(&line)
-                {
-                    let checked = target.name;
-                    log::debug!("crate {checked} checked");
-
-                    let event = AnalyzerEvent::CrateChecked {
-                        package: checked,
-                        package_count,
-                    };
-                    let _ = sender.send(event).await;
-                }
-                if let Ok(ws) = serde_json::from_str::<Workspace>(&line) {
-                    let event = AnalyzerEvent::Analyzed(ws);
-                    let _ = sender.send(event).await;
+        let workspace_root = self.path.clone();
+        let checks = tokio::spawn(async move {
+            run_check(command, sender, unit_count, workspace_root).await;
+            notify_c.notify_one();
+        });
+
+        AnalyzeEventIter {
+            receiver,
+            notify,
+            cancel: run.map(|(path, run_id)| CancelHandle::RunFile { path, run_id }),
+            checks: Some(checks.abort_handle()),
+        }
+    }
+
+    /// Compile the crates of `project` in dependency order, analyzing its
+    /// workspace members except the excluded ones
+    fn analyze_rust_project(
+        project: &RustProject,
+        settings: &Settings,
+        active_file: Option<&Path>,
+        scope: RunScope,
+    ) -> AnalyzeEventIter {
+        let target_dir = scope.target_dir(project.target_dir(&settings.env).join("owl"));
+        // exports only need the crate owning the file and its dependencies
+        let needed = match scope {
+            RunScope::Export => active_file
+                .and_then(|file| project.owning_crate(file))
+                .map(|index| project.with_dependencies(index)),
+            RunScope::Workspace => None,
+        };
+        let out_dir = target_dir.join("rust-project");
+        if let Err(e) = fs::create_dir_all(&out_dir) {
+            log::warn!("failed to create {}: {e}", out_dir.display());
+        }
+        let sysroot = toolchain::get_sysroot();
+        let names: Vec<String> = (0..project.crates.len())
+            .map(|index| project.crate_name(index))
+            .collect();
+        let packages: Vec<&str> = names.iter().map(String::as_str).collect();
+        let excluded_paths = settings.excluded_paths(&project.root, &packages);
+
+        // procedural macros outside the workspace are loaded, not compiled
+        let unavailable = (0..project.crates.len())
+            .filter(|&index| project.lacks_dylib(index))
+            .map(|index| {
+                let reason = format!(
+                    "procedural macro dependency {} has no compiled library: its \
+                     `proc_macro_dylib_path` is missing",
+                    names[index]
+                );
+                (index, reason)
+            })
+            .collect();
+
+        let run = start_run(&target_dir);
+        let compilers: Vec<_> = project
+            .build_order()
+            .into_iter()
+            .filter(|&index| project.is_compiled(index))
+            .filter(|index| needed.as_ref().is_none_or(|needed| needed.contains(index)))
+            .map(|index| {
+                let krate = &project.crates[index];
+                let name = &names[index];
+                let analyze = krate.is_workspace_member
+                    && !settings.exclude.iter().any(|entry| entry == name);
+                let mut command = toolchain::setup_rustc_command(analyze);
+                command
+                    .args(project.compiler_args(index, &sysroot, &out_dir))
+                    .envs(&settings.env)
+                    .envs(&krate.env)
+                    .current_dir(&project.root)
+                    .stdout(Stdio::piped())
+                    .stderr(Stdio::piped())
+                    .kill_on_drop(true);
+                set_analysis_env(
+                    &mut command,
+                    settings,
+                    &target_dir,
+                    run.as_ref(),
+                    active_file,
+                    &excluded_paths,
+                );
+                CrateCompiler {
+                    index,
+                    name: name.clone(),
+                    deps: krate.deps.iter().map(|dep| dep.krate).collect(),
+                    command,
                 }
-            }
-            log::debug!("stdout closed");
+            })
+            .collect();
+
+        log::info!("start analyzing {}", project.root.display());
+
+        let (sender, receiver) = mpsc::channel(1024);
+        let notify = Arc::new(Notify::new());
+        let notify_c = notify.clone();
+        let project_root = project.root.clone();
+        let checks = tokio::spawn(async move {
+            run_compilers(compilers, unavailable, sender, project_root).await;
             notify_c.notify_one();
         });
 
         AnalyzeEventIter {
             receiver,
             notify,
-            _child: Some(child),
+            cancel: run.map(|(path, run_id)| CancelHandle::RunFile { path, run_id }),
+            checks: Some(checks.abort_handle()),
         }
     }
 
+    /// Number of build units that cargo compiles when run with `args`, except
+    /// for build script runs, which produce no artifact
+    async fn count_units(
+        &self,
+        args: Vec<String>,
+        settings: &Settings,
+        target_dir: &Path,
+    ) -> Option<usize> {
+        let mut command = toolchain::setup_cargo_command();
+        command
+            .args(args)
+            .args(["-Zunstable-options", "--unit-graph"])
+            .envs(&settings.env)
+            .env("CARGO_TARGET_DIR", target_dir)
+            .env_remove("RUSTC_WRAPPER")
+            .current_dir(&self.path)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null());
+        let output = command.output().await.ok()?;
+        let graph: UnitGraph = match serde_json::from_slice(&output.stdout) {
+            Ok(graph) => graph,
+            Err(e) => {
+                log::warn!("failed to read the unit graph: {e}");
+                return None;
+            }
+        };
+        Some(
+            graph
+                .units
+                .iter()
+                .filter(|unit| unit.mode != "run-custom-build")
+                .count(),
+        )
+    }
+
     #[allow(clippy::unused_async, reason = "required by async closure signature")]
-    async fn analyze_single_file(&self, path: &Path) -> AnalyzeEventIter {
+    async fn analyze_single_file(
+        &self,
+        path: &Path,
+        polonius: PoloniusOptions,
+        origin_graph: Option<String>,
+    ) -> AnalyzeEventIter {
         let sysroot = toolchain::get_sysroot();
         let path = path.to_path_buf();
 
@@ -202,13 +802,21 @@ impl Analyzer {
 
         log::info!("start analyzing {}", path.display());
 
+        let cancel = CancellationToken::new();
+        let cancel_c = cancel.clone();
         let _handle = tokio::spawn(async move {
-            let handle = compiler::spawn_analysis(&path, &sysroot);
+            let handle =
+                compiler::spawn_analysis_with_origin_graph(&path, &sysroot, polonius, origin_graph);
 
             let compiler::AnalysisHandle {
                 mut results,
                 thread,
+                cancel: compiler_cancel,
             } = handle;
+            let _cancel = tokio::spawn(async move {
+                cancel_c.cancelled().await;
+                compiler_cancel.cancel();
+            });
             while let Some(ws) = results.recv().await {
                 let event = AnalyzerEvent::Analyzed(ws);
                 if sender.send(event).await.is_err() {
@@ -230,21 +838,136 @@ impl Analyzer {
         AnalyzeEventIter {
             receiver,
             notify,
-            _child: None,
+            cancel: Some(CancelHandle::Token(cancel)),
+            checks: None,
+        }
+    }
+}
+
+/// How to stop the analyses of a run that is no longer wanted
+enum CancelHandle {
+    /// Compiler processes watching the run file of the target directory
+    RunFile { path: PathBuf, run_id: String },
+    /// Compiler thread of this process
+    Token(CancellationToken),
+}
+impl CancelHandle {
+    fn cancel(&self) {
+        match self {
+            Self::RunFile { path, run_id } => {
+                // a newer run has already taken over the run file
+                if fs::read_to_string(path).is_ok_and(|v| v == *run_id) {
+                    let _ = fs::write(path, "");
+                }
+            }
+            Self::Token(token) => token.cancel(),
         }
     }
 }
 
+/// Events of an analysis run; dropping it cancels the analyses that have not
+/// finished yet
 pub struct AnalyzeEventIter {
     receiver: mpsc::Receiver<AnalyzerEvent>,
     notify: Arc<Notify>,
-    _child: Option<process::Child>,
+    cancel: Option<CancelHandle>,
+    /// Task running the cargo command, whose process is killed with it
+    checks: Option<task::AbortHandle>,
+}
+impl Drop for AnalyzeEventIter {
+    fn drop(&mut self) {
+        if let Some(cancel) = &self.cancel {
+            cancel.cancel();
+        }
+        if let Some(checks) = &self.checks {
+            checks.abort();
+        }
+    }
 }
 impl AnalyzeEventIter {
     pub async fn next_event(&mut self) -> Option<AnalyzerEvent> {
+        // events sent right before the run finished are still delivered
         tokio::select! {
+            biased;
             v = self.receiver.recv() => v,
             () = self.notify.notified() => None,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use tower_lsp::lsp_types;
+
+    use super::{BuildError, CargoCheckMessage, package_selection};
+    use crate::lsp_settings::Settings;
+
+    #[test]
+    fn compiler_error_becomes_build_error() {
+        let line = r#"{"reason":"compiler-message","package_id":"path+file:///ws/app#0.1.0","manifest_path":"/ws/app/Cargo.toml","target":{"kind":["lib"],"crate_types":["lib"],"name":"app","src_path":"/ws/app/src/lib.rs","edition":"2021","doc":true,"doctest":true,"test":true},"message":{"rendered":"error[E0308]: mismatched types","$message_type":"diagnostic","children":[],"level":"error","message":"mismatched types","code":{"code":"E0308","explanation":null},"spans":[{"byte_start":36,"byte_end":40,"column_start":37,"column_end":41,"expansion":null,"file_name":"app/src/lib.rs","is_primary":false,"label":"arguments to this method are incorrect","line_end":1,"line_start":1,"suggested_replacement":null,"suggestion_applicability":null,"text":[]},{"byte_start":41,"byte_end":44,"column_start":42,"column_end":45,"expansion":null,"file_name":"app/src/lib.rs","is_primary":true,"label":"expected `u8`, found `&str`","line_end":1,"line_start":1,"suggested_replacement":null,"suggestion_applicability":null,"text":[]}]}}"#;
+        let Ok(CargoCheckMessage::CompilerMessage { target, message }) = serde_json::from_str(line)
+        else {
+            panic!("compiler message should be parsed");
+        };
+        let error = BuildError::new(target.name, &message, Path::new("/ws"));
+        assert_eq!(error.path, Some(PathBuf::from("/ws/app/src/lib.rs")));
+        assert_eq!(
+            error.range,
+            Some(lsp_types::Range {
+                start: lsp_types::Position {
+                    line: 0,
+                    character: 41
+                },
+                end: lsp_types::Position {
+                    line: 0,
+                    character: 44
+                },
+            })
+        );
+        let diagnostic = error.to_diagnostic();
+        assert_eq!(
+            diagnostic.message,
+            "crate app failed to compile: mismatched types"
+        );
+        assert_eq!(
+            diagnostic.code,
+            Some(lsp_types::NumberOrString::String("E0308".to_owned()))
+        );
+        assert_eq!(diagnostic.source.as_deref(), Some("ferrous-owl"));
+    }
+
+    #[test]
+    fn build_error_columns_count_utf16_code_units() {
+        // `x` follows a crab, one character but two UTF-16 code units
+        let line = r#"{"reason":"compiler-message","package_id":"path+file:///ws/app#0.1.0","manifest_path":"/ws/app/Cargo.toml","target":{"kind":["lib"],"crate_types":["lib"],"name":"app","src_path":"/ws/app/src/lib.rs","edition":"2021","doc":true,"doctest":true,"test":true},"message":{"rendered":"error[E0425]: cannot find value `x` in this scope","$message_type":"diagnostic","children":[],"level":"error","message":"cannot find value `x` in this scope","code":{"code":"E0425","explanation":null},"spans":[{"byte_start":24,"byte_end":25,"column_start":22,"column_end":23,"expansion":null,"file_name":"app/src/lib.rs","is_primary":true,"label":"not found in this scope","line_end":1,"line_start":1,"suggested_replacement":null,"suggestion_applicability":null,"text":[{"text":"const C: &str = \"🦀\"; x","highlight_start":22,"highlight_end":23}]}]}}"#;
+        let Ok(CargoCheckMessage::CompilerMessage { target, message }) = serde_json::from_str(line)
+        else {
+            panic!("compiler message should be parsed");
+        };
+        let error = BuildError::new(target.name, &message, Path::new("/ws"));
+        let range = error.range.unwrap();
+        assert_eq!((range.start.character, range.end.character), (22, 23));
+    }
+
+    #[test]
+    fn packages_checked_in_one_selection() {
+        let packages = ["app", "core", "generated"];
+        let settings =
+            Settings::from_value(&serde_json::json!({ "exclude": ["generated"] })).unwrap();
+        assert_eq!(
+            package_selection(&packages, &settings),
+            ["--workspace", "--exclude", "generated"]
+        );
+        let settings = Settings::from_value(&serde_json::json!({
+            "cargo": { "packages": ["app", "generated"] },
+            "exclude": ["generated"]
+        }))
+        .unwrap();
+        assert_eq!(
+            package_selection(&packages, &settings),
+            ["--package", "app"]
+        );
+    }
+}
@@ -1,18 +1,28 @@
-use std::{collections::HashMap, env::current_dir, fs::read_to_string, future::Future, pin::Pin};
+use std::{
+    collections::HashMap, env::current_dir, fs::read_to_string, future::Future, pin::Pin, sync::Arc,
+};
 
 use rustc_borrowck::consumers::{
-    ConsumerOptions, PoloniusInput, PoloniusOutput, get_body_with_borrowck_facts,
+    ConsumerOptions, PoloniusInput, PoloniusLocationTable, PoloniusOutput, PoloniusRegionVid,
+    get_body_with_borrowck_facts,
 };
 use rustc_hir::def_id::{LOCAL_CRATE, LocalDefId};
 use rustc_middle::{
-    mir::{BasicBlock, Local},
+    mir::{BasicBlock, Body, Local},
     ty::TyCtxt,
 };
 use rustc_span::Span;
 
 use crate::{
-    mir_cache, mir_polonius, mir_transform,
-    models::{FnLocal, Function, Loc, MirBasicBlock, MirDecl, Range},
+    graph_export, mir_cache, mir_polonius,
+    mir_polonius::PoloniusOptions,
+    mir_transform,
+    mir_transform::RegionParam,
+    models::{
+        AnalysisPrecision, FnLocal, FnSummary, Function, Loc, MacroCall, MirBasicBlock, MirBinding,
+        MirClosure, MirDecl, MirLifetimeLocal, MirLifetimeParam, MirOrigin, MirOriginEdge,
+        MirOriginGraph, MirTemporary, NeedlessClone, Range,
+    },
 };
 
 pub type MirAnalyzeFuture = Pin<Box<dyn Future<Output = MirAnalyzer> + Send + Sync>>;
@@ -26,7 +36,7 @@ pub struct AnalyzeResult {
 }
 
 pub enum MirAnalyzerInitResult {
-    Cached(AnalyzeResult),
+    Cached(Box<AnalyzeResult>),
     Analyzer(MirAnalyzeFuture),
 }
 
@@ -43,8 +53,10 @@ pub fn sort_locs(v: &mut [(BasicBlock, usize)]) {
 pub struct MirAnalyzer {
     file_name: String,
     local_decls: HashMap<Local, String>,
+    local_sizes: HashMap<Local, u64>,
     user_vars: HashMap<Local, (Range, String)>,
-    input: PoloniusInput,
+    bindings: HashMap<Local, MirBinding>,
+    input: Arc<PoloniusInput>,
     basic_blocks: Vec<MirBasicBlock>,
     fn_id: LocalDefId,
     file_hash: String,
@@ -54,18 +66,28 @@ pub struct MirAnalyzer {
     shared_live: HashMap<Local, Vec<Range>>,
     mutable_live: HashMap<Local, Vec<Range>>,
     drop_range: HashMap<Local, Vec<Range>>,
+    region_params: Vec<RegionParam>,
+    region_param_live: HashMap<PoloniusRegionVid, HashMap<Local, Vec<Range>>>,
+    /// Only computed for the function whose graph is exported
+    origin_graph: Option<mir_polonius::OriginGraph>,
+    temporaries: HashMap<Local, MirTemporary>,
+    needless_clones: Vec<NeedlessClone>,
+    statements: Vec<Range>,
+    summary: Option<FnSummary>,
+    closure: Option<MirClosure>,
+    macro_calls: Vec<MacroCall>,
+    precision: AnalysisPrecision,
+    name: String,
+    span: Option<Range>,
 }
 
 impl MirAnalyzer {
-    pub fn init(tcx: TyCtxt<'_>, fn_id: LocalDefId) -> MirAnalyzerInitResult {
-        let mut facts =
-            get_body_with_borrowck_facts(tcx, fn_id, ConsumerOptions::PoloniusInputFacts);
-        let input = *facts.input_facts.take().unwrap();
-        let location_table = facts.location_table.take().unwrap();
-
+    /// Resolves the file containing `span`, returning its display name,
+    /// contents and start offset in the source map.
+    fn read_source(tcx: TyCtxt<'_>, span: Span) -> (String, String, u32) {
         let source_map = tcx.sess.source_map();
 
-        let file_name = source_map.span_to_filename(facts.body.span);
+        let file_name = source_map.span_to_filename(span);
         let source_file = source_map.get_source_file(&file_name).unwrap();
         let offset = source_file.start_pos.0;
         let file_name = source_map.path_mapping().to_embeddable_absolute_path(
@@ -74,81 +96,135 @@ impl MirAnalyzer {
         );
         let path = file_name.to_path(rustc_span::FileNameDisplayPreference::Local);
         let source = read_to_string(path).unwrap();
-        let file_name = path.to_string_lossy().to_string();
-        log::debug!("facts of {fn_id:?} prepared; start analyze of {fn_id:?}");
+        (path.to_string_lossy().to_string(), source, offset)
+    }
+
+    fn cached(
+        tcx: TyCtxt<'_>,
+        file_name: &str,
+        file_hash: &str,
+        mir_hash: &str,
+    ) -> Option<AnalyzeResult> {
+        let mut cache = mir_cache::CACHE.lock().unwrap();
+        if cache.is_none() {
+            *cache = mir_cache::get_cache(&tcx.crate_name(LOCAL_CRATE).to_string());
+        }
+        let analyzed = cache.as_mut()?.get_cache(file_hash, mir_hash)?;
+        drop(cache);
+        Some(AnalyzeResult {
+            file_name: file_name.to_owned(),
+            file_hash: file_hash.to_owned(),
+            mir_hash: mir_hash.to_owned(),
+            analyzed,
+        })
+    }
 
-        let local_decls = facts
-            .body
-            .local_decls
+    fn collect_local_decls(body: &Body<'_>) -> HashMap<Local, String> {
+        body.local_decls
             .iter_enumerated()
             .map(|(local, decl)| (local, decl.ty.to_string()))
-            .collect();
+            .collect()
+    }
+
+    /// Prepare the analysis of `fn_id`, which computes the origin subset graph
+    /// if the function is called `origin_graph`
+    pub fn init(
+        tcx: TyCtxt<'_>,
+        fn_id: LocalDefId,
+        polonius: PoloniusOptions,
+        origin_graph: Option<&str>,
+    ) -> MirAnalyzerInitResult {
+        let mut facts =
+            get_body_with_borrowck_facts(tcx, fn_id, ConsumerOptions::PoloniusInputFacts);
+        let input = Arc::new(*facts.input_facts.take().unwrap());
+        let location_table = facts.location_table.take().unwrap();
+
+        let (file_name, source, offset) = Self::read_source(tcx, facts.body.span);
+        log::debug!("facts of {fn_id:?} prepared; start analyze of {fn_id:?}");
+
+        let local_decls = Self::collect_local_decls(&facts.body);
+        let local_sizes = mir_transform::collect_local_sizes(tcx, &facts.body);
 
         let mir_hash = mir_cache::Hasher::get_hash(
             tcx,
             mir_transform::erase_region_variables(tcx, facts.body.clone()),
         );
         let file_hash = mir_cache::Hasher::get_hash(tcx, &source);
-        let mut cache = mir_cache::CACHE.lock().unwrap();
-
-        if cache.is_none() {
-            *cache = mir_cache::get_cache(&tcx.crate_name(LOCAL_CRATE).to_string());
-        }
-        if let Some(cache) = cache.as_mut()
-            && let Some(analyzed) = cache.get_cache(&file_hash, &mir_hash)
+        let name = tcx.def_path_str(fn_id);
+        // cached results have no origin graph
+        let with_origin_graph =
+            origin_graph.is_some_and(|graph| graph_export::is_named(&name, graph));
+        if !with_origin_graph
+            && let Some(cached) = Self::cached(tcx, &file_name, &file_hash, &mir_hash)
         {
             log::debug!("MIR cache hit: {fn_id:?}");
-            return MirAnalyzerInitResult::Cached(AnalyzeResult {
-                file_name,
-                file_hash,
-                mir_hash,
-                analyzed,
-            });
+            return MirAnalyzerInitResult::Cached(Box::new(cached));
         }
-        drop(cache);
 
         let user_vars = mir_transform::collect_user_vars(&source, offset, &facts.body);
+        let bindings = mir_transform::collect_bindings(tcx, fn_id, &facts.body);
+        let temporaries =
+            mir_transform::collect_temporaries(tcx, fn_id, &source, offset, &facts.body);
 
-        let basic_blocks = mir_transform::collect_basic_blocks(
-            fn_id,
-            &source,
-            offset,
-            &facts.body.basic_blocks,
-            tcx.sess.source_map(),
-        );
+        let basic_blocks =
+            mir_transform::collect_basic_blocks(tcx, fn_id, &source, offset, &facts.body);
 
         let borrow_data = mir_transform::BorrowMap::new(&facts.borrow_set);
+        let clone_calls =
+            mir_transform::collect_clone_calls(tcx, &source, offset, &facts.body, &user_vars);
+
+        let statements = mir_transform::collect_statements(tcx, fn_id, &source, offset);
+        let summary = mir_transform::fn_summary(tcx, fn_id.to_def_id(), Some(&facts.body));
+        let closure = mir_transform::collect_closure(tcx, fn_id, &source, offset);
+        let macro_calls = mir_transform::collect_macro_calls(&source, offset, &facts.body);
+        let span = range_from_span(&source, facts.body.span, offset);
+
+        let universal_count = input.universal_region.len();
+        let region_params =
+            mir_transform::collect_region_params(tcx, fn_id, &source, offset, universal_count);
 
         let analyzer = Box::pin(async move {
             log::debug!("start re-computing borrow check with dump: true");
-            let output_datafrog =
-                PoloniusOutput::compute(&input, polonius_engine::Algorithm::DatafrogOpt, true);
+            let (output, precision) = mir_polonius::compute(input.clone(), polonius).await;
             log::debug!("borrow check finished");
 
             let accurate_live =
-                mir_polonius::get_accurate_live(&output_datafrog, &location_table, &basic_blocks);
+                mir_polonius::get_accurate_live(&output, &location_table, &basic_blocks);
+
+            let must_live =
+                mir_polonius::get_must_live(&output, &location_table, &borrow_data, &basic_blocks);
 
-            let must_live = mir_polonius::get_must_live(
-                &output_datafrog,
+            let (shared_live, mutable_live) = mir_polonius::get_borrow_live(
+                &output,
                 &location_table,
                 &borrow_data,
                 &basic_blocks,
             );
 
-            let (shared_live, mutable_live) = mir_polonius::get_borrow_live(
-                &output_datafrog,
+            let drop_range = mir_polonius::drop_range(&output, &location_table, &basic_blocks);
+
+            let regions: Vec<_> = region_params.iter().map(|param| param.region).collect();
+            let region_param_live = mir_polonius::get_region_param_live(
+                &output,
+                &input,
                 &location_table,
-                &borrow_data,
                 &basic_blocks,
+                &regions,
             );
 
-            let drop_range =
-                mir_polonius::drop_range(&output_datafrog, &location_table, &basic_blocks);
+            let origin_graph = with_origin_graph.then(|| {
+                mir_polonius::get_origin_graph(&output, &input, &location_table, &basic_blocks)
+            });
+
+            let needless_clones =
+                Self::needless_clones(clone_calls, &output, &location_table, &borrow_data, fn_id);
 
             Self {
                 file_name,
                 local_decls,
+                local_sizes,
                 user_vars,
+                bindings,
                 input,
                 basic_blocks,
                 fn_id,
@@ -159,11 +235,51 @@ impl MirAnalyzer {
                 shared_live,
                 mutable_live,
                 drop_range,
+                region_params,
+                region_param_live,
+                origin_graph,
+                temporaries,
+                needless_clones,
+                statements,
+                summary,
+                closure,
+                macro_calls,
+                precision,
+                name,
+                span,
             }
         });
         MirAnalyzerInitResult::Analyzer(analyzer)
     }
 
+    /// The clones after which the cloned variable is dead
+    fn needless_clones(
+        clone_calls: Vec<mir_transform::CloneCall>,
+        datafrog: &PoloniusOutput,
+        location_table: &PoloniusLocationTable,
+        borrow_data: &mir_transform::BorrowMap,
+        fn_id: LocalDefId,
+    ) -> Vec<NeedlessClone> {
+        clone_calls
+            .into_iter()
+            .filter(|call| {
+                mir_polonius::is_unused_from(
+                    datafrog,
+                    location_table,
+                    borrow_data,
+                    call.source,
+                    call.after,
+                )
+            })
+            .map(|call| NeedlessClone {
+                local: FnLocal::new(call.source.as_u32(), fn_id.local_def_index.as_u32()),
+                name: call.name,
+                method: call.method,
+                range: call.range,
+            })
+            .collect()
+    }
+
     fn collect_decls(&self) -> Vec<MirDecl> {
         let user_vars = &self.user_vars;
         let lives = &self.accurate_live;
@@ -181,6 +297,7 @@ impl MirAnalyzer {
                 let drop = self.is_drop(*local);
                 let drop_range = drop_range.get(local).cloned().unwrap_or(Vec::new());
                 let fn_local = FnLocal::new(local.as_u32(), self.fn_id.local_def_index.as_u32());
+                let size = self.local_sizes.get(local).copied();
                 if let Some((span, name)) = user_vars.get(local).cloned() {
                     MirDecl::User {
                         local: fn_local,
@@ -193,6 +310,8 @@ impl MirAnalyzer {
                         must_live_at,
                         drop,
                         drop_range,
+                        size,
+                        binding: self.bindings.get(local).copied(),
                     }
                 } else {
                     MirDecl::Other {
@@ -204,12 +323,73 @@ impl MirAnalyzer {
                         drop,
                         drop_range,
                         must_live_at,
+                        temporary: self.temporaries.get(local).cloned(),
+                        size,
                     }
                 }
             })
             .collect()
     }
 
+    fn collect_lifetimes(&self) -> Vec<MirLifetimeParam> {
+        let fn_id = self.fn_id.local_def_index.as_u32();
+        self.region_params
+            .iter()
+            .map(|param| {
+                let locals = self
+                    .region_param_live
+                    .get(&param.region)
+                    .into_iter()
+                    .flatten()
+                    .map(|(local, must_hold_at)| MirLifetimeLocal {
+                        local: FnLocal::new(local.as_u32(), fn_id),
+                        must_hold_at: must_hold_at.clone(),
+                    })
+                    .collect();
+                MirLifetimeParam {
+                    name: param.name.clone(),
+                    spans: param.spans.clone(),
+                    locals,
+                }
+            })
+            .collect()
+    }
+
+    fn collect_origin_graph(&self) -> MirOriginGraph {
+        let Some(origin_graph) = &self.origin_graph else {
+            return MirOriginGraph::default();
+        };
+        let fn_id = self.fn_id.local_def_index.as_u32();
+        let mut origins: Vec<_> = origin_graph
+            .origins
+            .iter()
+            .map(|(origin, (locals, live_at))| {
+                let mut locals: Vec<_> = locals
+                    .iter()
+                    .map(|local| FnLocal::new(local.as_u32(), fn_id))
+                    .collect();
+                locals.sort_by_key(|local| local.id);
+                MirOrigin {
+                    id: origin.as_u32(),
+                    locals,
+                    live_at: live_at.clone(),
+                }
+            })
+            .collect();
+        origins.sort_by_key(|origin| origin.id);
+        let mut edges: Vec<_> = origin_graph
+            .edges
+            .iter()
+            .map(|((sub, sup), holds_at)| MirOriginEdge {
+                sub: sub.as_u32(),
+                sup: sup.as_u32(),
+                holds_at: holds_at.clone(),
+            })
+            .collect();
+        edges.sort_by_key(|edge| (edge.sub, edge.sup));
+        MirOriginGraph { origins, edges }
+    }
+
     fn is_drop(&self, local: Local) -> bool {
         for (drop_local, _) in &self.input.var_dropped_at {
             if *drop_local == local {
@@ -222,6 +402,8 @@ impl MirAnalyzer {
     #[must_use]
     pub fn analyze(self) -> AnalyzeResult {
         let decls = self.collect_decls();
+        let lifetimes = self.collect_lifetimes();
+        let origin_graph = self.collect_origin_graph();
         let basic_blocks = self.basic_blocks;
 
         AnalyzeResult {
@@ -230,8 +412,18 @@ impl MirAnalyzer {
             mir_hash: self.mir_hash,
             analyzed: Function {
                 fn_id: self.fn_id.local_def_index.as_u32(),
+                name: self.name,
+                span: self.span,
                 basic_blocks,
                 decls,
+                lifetimes,
+                origin_graph,
+                needless_clones: self.needless_clones,
+                statements: self.statements,
+                summary: self.summary,
+                closure: self.closure,
+                macro_calls: self.macro_calls,
+                precision: self.precision,
             },
         }
     }
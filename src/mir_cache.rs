@@ -15,8 +15,18 @@ use serde::{Deserialize, Serialize};
 
 use crate::{models::Function, toolchain::CACHE_DIR_ENV};
 
+/// Version of the format of the cached [`Function`]s; bump it whenever
+/// fields are added, since older caches would still deserialize with them
+/// left empty
+const CACHE_VERSION: u32 = 3;
+
+/// Cache directory of this version of the tool and the cache format
 fn get_cache_path() -> Option<PathBuf> {
-    env::var(CACHE_DIR_ENV).map(PathBuf::from).ok()
+    env::var(CACHE_DIR_ENV)
+        .map(|dir| {
+            PathBuf::from(dir).join(format!("{}-{CACHE_VERSION}", env!("CARGO_PKG_VERSION")))
+        })
+        .ok()
 }
 
 pub static CACHE: LazyLock<Mutex<Option<CacheData>>> = LazyLock::new(|| Mutex::new(None));
@@ -1,16 +1,161 @@
-use std::collections::{HashMap, HashSet};
+use std::{
+    collections::{HashMap, HashSet},
+    env, panic,
+    sync::Arc,
+    time::Duration,
+};
 
+use polonius_engine::{Algorithm, FactTypes};
 use rayon::prelude::*;
-use rustc_borrowck::consumers::{PoloniusLocationTable, PoloniusOutput};
+use rustc_borrowck::consumers::{
+    PoloniusInput, PoloniusLocationTable, PoloniusOutput, PoloniusRegionVid, RustcFacts,
+};
 use rustc_index::Idx;
-use rustc_middle::mir::Local;
+use rustc_middle::mir::{Local, Location};
+use serde::{Deserialize, Serialize};
+use tokio::{sync::oneshot, task, time};
 
 use crate::{
     mir_transform::{BorrowData, BorrowMap, rich_locations_to_ranges},
-    models::{MirBasicBlock, Range},
-    range_ops,
+    models::{AnalysisPrecision, MirBasicBlock, Range},
+    range_ops, toolchain,
 };
 
+/// Variant of the Polonius rules used to check borrows, see [`Algorithm`]
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum PoloniusAlgorithm {
+    Naive,
+    #[default]
+    DatafrogOpt,
+    LocationInsensitive,
+    Hybrid,
+}
+impl From<PoloniusAlgorithm> for Algorithm {
+    fn from(algorithm: PoloniusAlgorithm) -> Self {
+        match algorithm {
+            PoloniusAlgorithm::Naive => Self::Naive,
+            PoloniusAlgorithm::DatafrogOpt => Self::DatafrogOpt,
+            PoloniusAlgorithm::LocationInsensitive => Self::LocationInsensitive,
+            PoloniusAlgorithm::Hybrid => Self::Hybrid,
+        }
+    }
+}
+
+/// Settings and limits of the analysis of each function
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "camelCase", default)]
+pub struct PoloniusOptions {
+    pub algorithm: PoloniusAlgorithm,
+    /// Functions with more input facts are checked location-insensitively
+    pub max_facts: usize,
+    /// Functions with more input facts are not checked at all
+    pub max_insensitive_facts: usize,
+    /// Milliseconds after which the borrow check of a function is given up;
+    /// a check that has started still runs to its end on its blocking thread
+    pub timeout_ms: u64,
+    /// Milliseconds after which the whole analysis of a function is dropped
+    pub deadline_ms: u64,
+}
+impl Default for PoloniusOptions {
+    fn default() -> Self {
+        Self {
+            algorithm: PoloniusAlgorithm::default(),
+            max_facts: 2_000_000,
+            max_insensitive_facts: 20_000_000,
+            timeout_ms: 30_000,
+            deadline_ms: 60_000,
+        }
+    }
+}
+impl PoloniusOptions {
+    /// Read the options the language server passes to the compiler process
+    #[must_use]
+    pub fn from_env() -> Self {
+        let Ok(options) = env::var(toolchain::POLONIUS_OPTIONS_ENV) else {
+            return Self::default();
+        };
+        serde_json::from_str(&options).unwrap_or_else(|e| {
+            log::warn!("invalid polonius options: {e}");
+            Self::default()
+        })
+    }
+}
+
+/// Number of input facts, which the cost of the borrow check grows with
+#[must_use]
+pub const fn fact_count(input: &PoloniusInput) -> usize {
+    input.cfg_edge.len()
+        + input.loan_issued_at.len()
+        + input.loan_killed_at.len()
+        + input.loan_invalidated_at.len()
+        + input.subset_base.len()
+        + input.var_used_at.len()
+        + input.var_defined_at.len()
+        + input.var_dropped_at.len()
+        + input.use_of_var_derefs_origin.len()
+        + input.drop_of_var_derefs_origin.len()
+        + input.path_assigned_at_base.len()
+        + input.path_moved_at_base.len()
+        + input.path_accessed_at_base.len()
+}
+
+/// Output of a function whose borrows are not checked
+fn unchecked(precision: AnalysisPrecision) -> (PoloniusOutput, AnalysisPrecision) {
+    let empty = PoloniusInput::default();
+    (
+        PoloniusOutput::compute(&empty, Algorithm::LocationInsensitive, true),
+        precision,
+    )
+}
+
+/// Check the borrows of `input` within the limits of `options`, falling back
+/// to the location-insensitive rules for functions with too many facts, and
+/// to an empty output for functions with far too many facts or that take too
+/// long
+///
+/// The timeout starts once the check has a blocking thread, so checks waiting
+/// behind slow ones do not time out. Polonius cannot be interrupted: a check
+/// that times out keeps its blocking thread until it finishes, only its
+/// result is dropped. The fact limits are what bounds the work, and the
+/// blocking threads of the runtime are capped.
+pub async fn compute(
+    input: Arc<PoloniusInput>,
+    options: PoloniusOptions,
+) -> (PoloniusOutput, AnalysisPrecision) {
+    let facts = fact_count(&input);
+    if facts > options.max_insensitive_facts {
+        log::warn!("{facts} facts exceed the limit; skip the borrow check");
+        return unchecked(AnalysisPrecision::TooLarge);
+    }
+    let algorithm = if facts > options.max_facts {
+        log::info!("{facts} facts exceed the limit; check borrows location-insensitively");
+        PoloniusAlgorithm::LocationInsensitive
+    } else {
+        options.algorithm
+    };
+    let precision = if algorithm == PoloniusAlgorithm::LocationInsensitive {
+        AnalysisPrecision::LocationInsensitive
+    } else {
+        AnalysisPrecision::Full
+    };
+    let (started, has_started) = oneshot::channel();
+    let check = task::spawn_blocking(move || {
+        let _ = started.send(());
+        PoloniusOutput::compute(&input, algorithm.into(), true)
+    });
+    // only a check that panicked before it started drops the sender
+    let _ = has_started.await;
+    match time::timeout(Duration::from_millis(options.timeout_ms), check).await {
+        Ok(Ok(output)) => (output, precision),
+        Ok(Err(e)) => panic::resume_unwind(e.into_panic()),
+        Err(_) => {
+            log::warn!("borrow check of {facts} facts timed out");
+            unchecked(AnalysisPrecision::TimedOut)
+        }
+    }
+}
+
 #[must_use]
 pub fn get_accurate_live(
     datafrog: &PoloniusOutput,
@@ -27,6 +172,32 @@ pub fn get_accurate_live(
     )
 }
 
+/// Whether `local` is neither used again nor borrowed from `location` on
+#[must_use]
+pub fn is_unused_from(
+    datafrog: &PoloniusOutput,
+    location_table: &PoloniusLocationTable,
+    borrow_map: &BorrowMap,
+    local: Local,
+    location: Location,
+) -> bool {
+    let point = location_table.start_index(location);
+    let live = datafrog
+        .var_live_on_entry
+        .get(&point)
+        .is_some_and(|locals| locals.contains(&local));
+    let borrowed = datafrog.loan_live_at.get(&point).is_some_and(|loans| {
+        loans.iter().any(|loan| {
+            matches!(
+                borrow_map.get_from_borrow_index(*loan),
+                Some((_, BorrowData::Shared { borrowed, .. } | BorrowData::Mutable { borrowed, .. }))
+                    if *borrowed == local
+            )
+        })
+    });
+    !live && !borrowed
+}
+
 /// returns (shared, mutable)
 pub fn get_borrow_live(
     datafrog: &PoloniusOutput,
@@ -91,16 +262,7 @@ pub fn get_must_live(
     borrow_map: &BorrowMap,
     basic_blocks: &[MirBasicBlock],
 ) -> HashMap<Local, Vec<Range>> {
-    // obtain a map that region -> region contained locations
-    let mut region_locations = HashMap::new();
-    for (location_idx, region_idc) in &datafrog.origin_live_on_entry {
-        for region_idx in region_idc {
-            region_locations
-                .entry(*region_idx)
-                .or_insert_with(HashSet::new)
-                .insert(*location_idx);
-        }
-    }
+    let region_locations = region_locations(datafrog);
 
     // obtain maps from borrow index to locals:
     // - borrow_assigned_local: borrow index -> local that holds the reference
@@ -122,13 +284,11 @@ pub fn get_must_live(
 
     // check all regions' subset that must be satisfied
     let mut subsets = HashMap::new();
-    for subset in datafrog.subset.values() {
-        for (sup, subs) in subset {
-            subsets
-                .entry(*sup)
-                .or_insert_with(HashSet::new)
-                .extend(subs.iter().copied());
-        }
+    for (sup, sub) in subset_locations(datafrog).keys() {
+        subsets
+            .entry(*sup)
+            .or_insert_with(HashSet::new)
+            .insert(*sub);
     }
     // obtain a map that region -> locations
     // a region must contains the locations
@@ -188,6 +348,210 @@ pub fn get_must_live(
         .collect()
 }
 
+type PoloniusLocationIndex = <RustcFacts as FactTypes>::Point;
+
+/// obtain a map that region -> locations where the region is live
+fn region_locations(
+    datafrog: &PoloniusOutput,
+) -> HashMap<PoloniusRegionVid, HashSet<PoloniusLocationIndex>> {
+    let mut region_locations = HashMap::new();
+    for (location_idx, region_idc) in &datafrog.origin_live_on_entry {
+        for region_idx in region_idc {
+            region_locations
+                .entry(*region_idx)
+                .or_insert_with(HashSet::new)
+                .insert(*location_idx);
+        }
+    }
+    region_locations
+}
+
+/// obtain a map that (origin1, origin2) -> locations where `origin1` is a
+/// subset of `origin2`
+fn subset_locations(
+    datafrog: &PoloniusOutput,
+) -> HashMap<(PoloniusRegionVid, PoloniusRegionVid), Vec<PoloniusLocationIndex>> {
+    let mut subsets = HashMap::new();
+    for (location_idx, subset) in &datafrog.subset {
+        for (sup, subs) in subset {
+            for sub in subs {
+                subsets
+                    .entry((*sup, *sub))
+                    .or_insert_with(Vec::new)
+                    .push(*location_idx);
+            }
+        }
+    }
+    subsets
+}
+
+fn location_indices_to_ranges(
+    locations: impl IntoIterator<Item = PoloniusLocationIndex>,
+    location_table: &PoloniusLocationTable,
+    basic_blocks: &[MirBasicBlock],
+) -> Vec<Range> {
+    let locations: Vec<_> = locations
+        .into_iter()
+        .map(|v| location_table.to_rich_location(v))
+        .collect();
+    range_ops::eliminated_ranges(rich_locations_to_ranges(basic_blocks, &locations))
+}
+
+/// The origin subset graph of a function
+#[derive(Default)]
+pub struct OriginGraph {
+    /// origin -> (locals whose types contain the origin, live ranges)
+    pub origins: HashMap<PoloniusRegionVid, (HashSet<Local>, Vec<Range>)>,
+    /// (sub, sup) -> ranges where `sub` is a subset of `sup`
+    pub edges: HashMap<(PoloniusRegionVid, PoloniusRegionVid), Vec<Range>>,
+}
+
+/// obtain the origin subset graph
+///
+/// Nodes are the origins that appear in a subset relation or in the type of a
+/// local; edges are the subset relations computed by Polonius.
+#[must_use]
+pub fn get_origin_graph(
+    datafrog: &PoloniusOutput,
+    input: &PoloniusInput,
+    location_table: &PoloniusLocationTable,
+    basic_blocks: &[MirBasicBlock],
+) -> OriginGraph {
+    let edges: HashMap<_, _> = subset_locations(datafrog)
+        .into_iter()
+        .map(|(edge, locations)| {
+            (
+                edge,
+                location_indices_to_ranges(locations, location_table, basic_blocks),
+            )
+        })
+        .collect();
+
+    let mut origin_locals: HashMap<_, HashSet<_>> = HashMap::new();
+    for (local, origin) in input
+        .use_of_var_derefs_origin
+        .iter()
+        .chain(&input.drop_of_var_derefs_origin)
+    {
+        origin_locals.entry(*origin).or_default().insert(*local);
+    }
+    for (sub, sup) in edges.keys() {
+        origin_locals.entry(*sub).or_default();
+        origin_locals.entry(*sup).or_default();
+    }
+
+    let mut region_locations = region_locations(datafrog);
+    let origins = origin_locals
+        .into_iter()
+        .map(|(origin, locals)| {
+            let live = location_indices_to_ranges(
+                region_locations.remove(&origin).unwrap_or_default(),
+                location_table,
+                basic_blocks,
+            );
+            (origin, (locals, live))
+        })
+        .collect();
+
+    OriginGraph { origins, edges }
+}
+
+/// obtain, for each universal region in `regions`, the locals whose types
+/// contain an origin related to it via the subset facts, together with the
+/// points where the region must hold because of that local
+///
+/// Related origins are searched in both directions of `subset_base`, but the
+/// search does not continue through other universal regions, as every
+/// universal region is related to the function body region.
+#[must_use]
+pub fn get_region_param_live(
+    datafrog: &PoloniusOutput,
+    input: &PoloniusInput,
+    location_table: &PoloniusLocationTable,
+    basic_blocks: &[MirBasicBlock],
+    regions: &[PoloniusRegionVid],
+) -> HashMap<PoloniusRegionVid, HashMap<Local, Vec<Range>>> {
+    let universal: HashSet<_> = input.universal_region.iter().copied().collect();
+
+    let mut related = HashMap::new();
+    for (sup, sub, _) in &input.subset_base {
+        related
+            .entry(*sup)
+            .or_insert_with(HashSet::new)
+            .insert(*sub);
+        related
+            .entry(*sub)
+            .or_insert_with(HashSet::new)
+            .insert(*sup);
+    }
+
+    let mut origin_locals = HashMap::new();
+    for (local, origin) in input
+        .use_of_var_derefs_origin
+        .iter()
+        .chain(&input.drop_of_var_derefs_origin)
+    {
+        origin_locals
+            .entry(*origin)
+            .or_insert_with(HashSet::new)
+            .insert(*local);
+    }
+
+    let mut origin_locations = HashMap::new();
+    for (location_idx, origins) in &datafrog.origin_live_on_entry {
+        for origin in origins {
+            origin_locations
+                .entry(*origin)
+                .or_insert_with(Vec::new)
+                .push(*location_idx);
+        }
+    }
+
+    regions
+        .iter()
+        .map(|region| {
+            let mut visited = HashSet::from([*region]);
+            let mut stack = vec![*region];
+            while let Some(origin) = stack.pop() {
+                for next in related.get(&origin).into_iter().flatten() {
+                    if !universal.contains(next) && visited.insert(*next) {
+                        stack.push(*next);
+                    }
+                }
+            }
+
+            let mut local_locations: HashMap<Local, HashSet<_>> = HashMap::new();
+            for origin in &visited {
+                for local in origin_locals.get(origin).into_iter().flatten() {
+                    let locations = local_locations.entry(*local).or_default();
+                    // universal regions are live everywhere
+                    if !universal.contains(origin) {
+                        locations.extend(origin_locations.get(origin).into_iter().flatten());
+                    }
+                }
+            }
+
+            let locals = local_locations
+                .into_iter()
+                .map(|(local, locations)| {
+                    let locations: Vec<_> = locations
+                        .into_iter()
+                        .map(|v| location_table.to_rich_location(v))
+                        .collect();
+                    (
+                        local,
+                        range_ops::eliminated_ranges(rich_locations_to_ranges(
+                            basic_blocks,
+                            &locations,
+                        )),
+                    )
+                })
+                .collect();
+            (*region, locals)
+        })
+        .collect()
+}
+
 /// obtain map from local id to living range
 #[must_use]
 pub fn drop_range(
@@ -230,3 +594,34 @@ pub fn get_range(
         })
         .collect()
 }
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use tokio::runtime::Builder;
+
+    use super::*;
+
+    #[test]
+    fn timeout_starts_when_the_check_runs() {
+        let rt = Builder::new_current_thread()
+            .enable_all()
+            .max_blocking_threads(1)
+            .build()
+            .unwrap();
+        let options = PoloniusOptions {
+            timeout_ms: 200,
+            ..PoloniusOptions::default()
+        };
+        let (_, precision) = rt.block_on(async {
+            // a slow check holds the only blocking thread for longer than
+            // the timeout
+            let slow = task::spawn_blocking(|| thread::sleep(Duration::from_millis(400)));
+            let checked = compute(Arc::new(PoloniusInput::default()), options).await;
+            slow.await.unwrap();
+            checked
+        });
+        assert_eq!(precision, AnalysisPrecision::Full);
+    }
+}
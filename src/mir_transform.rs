@@ -1,20 +1,42 @@
 use std::collections::{HashMap, HashSet};
 
 use rayon::prelude::*;
-use rustc_borrowck::consumers::{BorrowIndex, BorrowSet, RichLocation};
-use rustc_hir::def_id::LocalDefId;
+use rustc_borrowck::consumers::{BorrowIndex, BorrowSet, PoloniusRegionVid, RichLocation};
+use rustc_hir::{
+    BindingMode, ByRef, Expr, ExprKind, HirId, ItemKind, LangItem, Lifetime, LifetimeKind, Node,
+    Pat, PatKind, Stmt,
+    def::DefKind,
+    def_id::{DefId, LocalDefId},
+    intravisit::{self, Visitor},
+};
+use rustc_index::Idx;
 use rustc_middle::{
     mir::{
-        BasicBlocks, Body, BorrowKind, Local, Location, Operand, Rvalue, Statement, StatementKind,
-        Terminator, TerminatorKind, VarDebugInfoContents,
+        BasicBlock, BindingForm, Body, BorrowKind, CastKind, Local, LocalInfo, LocalKind, Location,
+        Mutability, Operand, Rvalue, Statement, StatementKind, Terminator, TerminatorKind,
+        VarDebugInfoContents, visit as mir_visit,
+    },
+    ty::{
+        self, BoundRegionKind, BoundVariableKind, GenericArg, GenericArgs, Region, RegionKind, Ty,
+        TyCtxt, TyKind, TypeFoldable, TypeFolder, TypingEnv, UpvarCapture,
+        place_to_string_for_capture,
     },
-    ty::{TyCtxt, TypeFoldable, TypeFolder},
 };
-use rustc_span::source_map::SourceMap;
+use rustc_mir_dataflow::{
+    Analysis,
+    impls::{MaybeInitializedPlaces, MaybeUninitializedPlaces},
+    move_paths::{LookupResult, MoveData},
+};
+use rustc_span::{DesugaringKind, ExpnKind, MacroKind, Span, source_map::Spanned, sym};
 
 use crate::{
     mir_analysis::{range_from_span, sort_locs},
-    models::{FnLocal, MirBasicBlock, MirRval, MirStatement, MirTerminator, Range},
+    models::{
+        BindingMode as MirBindingMode, CaptureMode, DropState, FnLocal, FnSummary, MacroCall,
+        MirBasicBlock, MirBinding, MirCapture, MirClosure, MirRval, MirStatement, MirTemporary,
+        MirTerminator, ParamSummary, ParamUse, Range, TempScope,
+    },
+    range_ops,
 };
 
 /// `RegionEraser` to erase region variables from MIR body
@@ -61,11 +83,221 @@ pub fn collect_user_vars(
         .collect()
 }
 
+/// Collects the binding modes written in binding patterns
+struct WrittenBindings {
+    modes: HashMap<Span, ByRef>,
+}
+impl<'tcx> Visitor<'tcx> for WrittenBindings {
+    fn visit_pat(&mut self, p: &'tcx Pat<'tcx>) {
+        if let PatKind::Binding(BindingMode(by_ref, _), ..) = p.kind {
+            self.modes.insert(p.span, by_ref);
+        }
+        intravisit::walk_pat(self, p);
+    }
+}
+
+/// How the user variables of `body` are bound by their patterns, and
+/// whether that is implied by matching a reference
+pub fn collect_bindings(
+    tcx: TyCtxt<'_>,
+    fn_id: LocalDefId,
+    body: &Body<'_>,
+) -> HashMap<Local, MirBinding> {
+    let mut written = WrittenBindings {
+        modes: HashMap::new(),
+    };
+    if let Some(hir_body) = tcx.hir_maybe_body_owned_by(fn_id) {
+        written.visit_body(hir_body);
+    }
+    body.local_decls
+        .iter_enumerated()
+        .filter_map(|(local, decl)| {
+            let LocalInfo::User(BindingForm::Var(var)) = decl.local_info() else {
+                return None;
+            };
+            let BindingMode(by_ref, _) = var.binding_mode;
+            let mode = match by_ref {
+                ByRef::No => MirBindingMode::Value,
+                ByRef::Yes(Mutability::Not) => MirBindingMode::Ref,
+                ByRef::Yes(Mutability::Mut) => MirBindingMode::RefMut,
+            };
+            let implicit = written
+                .modes
+                .get(&decl.source_info.span)
+                .is_some_and(|written| *written != by_ref);
+            Some((local, MirBinding { mode, implicit }))
+        })
+        .collect()
+}
+
+/// A named lifetime parameter together with the universal region that
+/// borrowck created for it
+pub struct RegionParam {
+    pub region: PoloniusRegionVid,
+    pub name: String,
+    pub spans: Vec<Range>,
+}
+
+/// Collects the spans of the lifetimes referring to the given parameters
+struct LifetimeUses {
+    spans: HashMap<LocalDefId, Vec<Span>>,
+}
+impl<'tcx> Visitor<'tcx> for LifetimeUses {
+    fn visit_lifetime(&mut self, lifetime: &'tcx Lifetime) {
+        if let LifetimeKind::Param(param) = lifetime.kind
+            && let Some(spans) = self.spans.get_mut(&param)
+        {
+            spans.push(lifetime.ident.span);
+        }
+    }
+}
+
+/// find the uses of the lifetime parameters `params` in the signature of
+/// `fn_id` and in the header of the impl or trait it belongs to
+fn lifetime_uses(
+    tcx: TyCtxt<'_>,
+    fn_id: LocalDefId,
+    params: impl Iterator<Item = LocalDefId>,
+) -> HashMap<LocalDefId, Vec<Span>> {
+    let mut uses = LifetimeUses {
+        spans: params.map(|param| (param, Vec::new())).collect(),
+    };
+    // bodies are nested and not visited
+    match tcx.hir_node_by_def_id(fn_id) {
+        Node::Item(item) => intravisit::walk_item(&mut uses, item),
+        Node::ImplItem(item) => intravisit::walk_impl_item(&mut uses, item),
+        Node::TraitItem(item) => intravisit::walk_trait_item(&mut uses, item),
+        _ => return uses.spans,
+    }
+    let parent = tcx.hir_get_parent_item(tcx.local_def_id_to_hir_id(fn_id));
+    if let Node::Item(item) = tcx.hir_node_by_def_id(parent.def_id)
+        && matches!(item.kind, ItemKind::Impl(_) | ItemKind::Trait(..))
+    {
+        intravisit::walk_item(&mut uses, item);
+    }
+    uses.spans
+}
+
+/// Map universal regions back to the named lifetime parameters they stand for.
+///
+/// Borrowck creates universal regions in a fixed order: `'static`, the
+/// early-bound parameters of the typeck root, the late-bound parameters of
+/// the signature and finally the region of the function body.
+/// For closures only the early-bound parameters of the parent are mapped.
+pub fn collect_region_params(
+    tcx: TyCtxt<'_>,
+    fn_id: LocalDefId,
+    source: &str,
+    offset: u32,
+    universal_count: usize,
+) -> Vec<RegionParam> {
+    let typeck_root = tcx.typeck_root_def_id(fn_id.to_def_id());
+    let generics = tcx.generics_of(typeck_root);
+
+    let mut params = Vec::new();
+    for region in GenericArgs::identity_for_item(tcx, typeck_root).regions() {
+        let def_id = match region.kind() {
+            RegionKind::ReEarlyParam(param) => Some(generics.region_param(param, tcx).def_id),
+            _ => None,
+        };
+        params.push((region.get_name(), def_id));
+    }
+    // closures and constants (like array lengths) have no `fn_sig` of their own
+    if matches!(tcx.def_kind(fn_id), DefKind::Fn | DefKind::AssocFn) {
+        for bound_var in tcx.fn_sig(fn_id).instantiate_identity().bound_vars() {
+            if let BoundVariableKind::Region(kind) = bound_var {
+                let def_id = match kind {
+                    BoundRegionKind::Named(def_id, _) => Some(def_id),
+                    _ => None,
+                };
+                params.push((kind.get_name(), def_id));
+            }
+        }
+        // `'static` and the function body region surround the parameters
+        if params.len() + 2 != universal_count {
+            log::debug!(
+                "unexpected universal region layout of {fn_id:?}: {} params, {universal_count} \
+                 universal regions",
+                params.len()
+            );
+            return Vec::new();
+        }
+    }
+
+    let mut uses = lifetime_uses(
+        tcx,
+        fn_id,
+        params
+            .iter()
+            .filter_map(|(_, def_id)| def_id.and_then(DefId::as_local)),
+    );
+    params
+        .into_iter()
+        .enumerate()
+        .filter_map(|(index, (name, def_id))| {
+            let name = name?.to_string();
+            let decl_span = def_id.map(|def_id| tcx.def_span(def_id));
+            let use_spans = def_id
+                .and_then(DefId::as_local)
+                .and_then(|def_id| uses.remove(&def_id))
+                .unwrap_or_default();
+            let spans = decl_span
+                .into_iter()
+                .chain(use_spans)
+                .filter_map(|span| range_from_span(source, span, offset))
+                .collect();
+            Some(RegionParam {
+                region: PoloniusRegionVid::new(index + 1),
+                name,
+                spans: range_ops::eliminated_ranges(spans),
+            })
+        })
+        .collect()
+}
+
+/// Map the locals holding a reference or raw pointer to the local they were
+/// derived from.
+fn pointer_sources(body: &Body<'_>) -> HashMap<Local, Local> {
+    body.basic_blocks
+        .iter()
+        .flat_map(|bb| &bb.statements)
+        .filter_map(|stmt| match &stmt.kind {
+            StatementKind::Assign(assign) => {
+                let (place, rval) = &**assign;
+                let source = match rval {
+                    Rvalue::Ref(_, _, source) | Rvalue::RawPtr(_, source) => source.local,
+                    Rvalue::Cast(_, Operand::Copy(source) | Operand::Move(source), ty)
+                        if ty.is_raw_ptr() =>
+                    {
+                        source.local
+                    }
+                    _ => return None,
+                };
+                place.as_local().map(|local| (local, source))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// The local a pointer derived from `local` points into, following
+/// reborrows and pointer casts.
+fn pointee(pointers: &HashMap<Local, Local>, mut local: Local) -> Local {
+    let mut visited = HashSet::new();
+    while let Some(source) = pointers.get(&local)
+        && visited.insert(local)
+    {
+        local = *source;
+    }
+    local
+}
+
 fn convert_rvalue(
     fn_id: LocalDefId,
     source: &str,
     offset: u32,
     span: rustc_span::Span,
+    pointers: &HashMap<Local, Local>,
     rval: &Rvalue<'_>,
 ) -> Option<MirRval> {
     match rval {
@@ -76,6 +308,13 @@ fn convert_rvalue(
                 range,
             })
         }
+        Rvalue::Use(Operand::Copy(p)) => {
+            let local = p.local;
+            range_from_span(source, span, offset).map(|range| MirRval::Copy {
+                target_local: FnLocal::new(local.as_u32(), fn_id.local_def_index.as_u32()),
+                range,
+            })
+        }
         Rvalue::Ref(_region, kind, place) => {
             let mutable = matches!(kind, BorrowKind::Mut { .. });
             let local = place.local;
@@ -86,6 +325,30 @@ fn convert_rvalue(
                 outlive: None,
             })
         }
+        Rvalue::RawPtr(kind, place) => {
+            let local = if place.is_indirect_first_projection() {
+                pointee(pointers, place.local)
+            } else {
+                place.local
+            };
+            range_from_span(source, span, offset).map(|range| MirRval::RawPtr {
+                target_local: FnLocal::new(local.as_u32(), fn_id.local_def_index.as_u32()),
+                range,
+                mutable: kind.to_mutbl_lossy().is_mut(),
+            })
+        }
+        Rvalue::Cast(
+            CastKind::PtrToPtr | CastKind::PointerCoercion(..),
+            Operand::Copy(place) | Operand::Move(place),
+            ty,
+        ) if ty.is_raw_ptr() && pointers.contains_key(&place.local) => {
+            let local = pointee(pointers, place.local);
+            range_from_span(source, span, offset).map(|range| MirRval::RawPtr {
+                target_local: FnLocal::new(local.as_u32(), fn_id.local_def_index.as_u32()),
+                range,
+                mutable: ty.is_mutable_ptr(),
+            })
+        }
         _ => None,
     }
 }
@@ -94,29 +357,221 @@ fn convert_statement(
     fn_id: LocalDefId,
     source: &str,
     offset: u32,
+    span: Span,
+    pointers: &HashMap<Local, Local>,
     statement: &Statement<'_>,
 ) -> Option<MirStatement> {
-    let span = statement.source_info.span;
+    let fn_local = |local: Local| FnLocal::new(local.as_u32(), fn_id.local_def_index.as_u32());
     match &statement.kind {
         StatementKind::Assign(v) => {
             let (place, rval) = &**v;
             let target_local_index = place.local.as_u32();
-            let rv = convert_rvalue(fn_id, source, offset, span, rval);
+            let rv = convert_rvalue(fn_id, source, offset, span, pointers, rval);
             range_from_span(source, span, offset).map(|range| MirStatement::Assign {
                 target_local: FnLocal::new(target_local_index, fn_id.local_def_index.as_u32()),
                 range,
                 rval: rv,
             })
         }
+        StatementKind::StorageLive(local) => {
+            range_from_span(source, span, offset).map(|range| MirStatement::StorageLive {
+                target_local: fn_local(*local),
+                range,
+            })
+        }
+        StatementKind::StorageDead(local) => {
+            range_from_span(source, span, offset).map(|range| MirStatement::StorageDead {
+                target_local: fn_local(*local),
+                range,
+            })
+        }
         _ => range_from_span(source, span, offset).map(|range| MirStatement::Other { range }),
     }
 }
 
-fn convert_terminator(
+/// Normalize a def path such as `std::cell::RefCell::<T>::borrow_mut` or
+/// `<std::rc::Rc<T> as std::clone::Clone>::clone` to `Type::method` form
+/// without generic arguments.
+pub fn callee_path(path: &str) -> String {
+    let path = path
+        .strip_prefix('<')
+        .and_then(|qualified| {
+            let mut depth = 1_usize;
+            let end = qualified.char_indices().find_map(|(i, c)| {
+                match c {
+                    '<' => depth += 1,
+                    '>' => depth -= 1,
+                    _ => {}
+                }
+                (depth == 0).then_some(i)
+            })?;
+            let self_ty = qualified[..end].split(" as ").next()?;
+            Some(format!("{self_ty}{}", &qualified[end + 1..]))
+        })
+        .unwrap_or_else(|| path.to_owned());
+    let mut depth = 0_usize;
+    let mut result = String::with_capacity(path.len());
+    for c in path.chars() {
+        match c {
+            '<' => depth += 1,
+            '>' => depth = depth.saturating_sub(1),
+            _ if depth == 0 => result.push(c),
+            _ => {}
+        }
+    }
+    result.replace("::::", "::")
+}
+
+/// Locals a body moves out of, wholly or in part
+#[derive(Default)]
+struct MovedLocals(HashSet<Local>);
+impl<'tcx> mir_visit::Visitor<'tcx> for MovedLocals {
+    fn visit_operand(&mut self, operand: &Operand<'tcx>, location: Location) {
+        if let Operand::Move(place) = operand {
+            self.0.insert(place.local);
+        }
+        self.super_operand(operand, location);
+    }
+}
+
+/// The ownership contract of the function `def_id`, derived from its
+/// `body` if it was analyzed, so that a by-value parameter the body never
+/// moves is dropped by it, and from its signature otherwise; `None` for
+/// closures, constructors and the like.
+pub fn fn_summary<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    def_id: DefId,
+    body: Option<&Body<'tcx>>,
+) -> Option<FnSummary> {
+    if !matches!(tcx.def_kind(def_id), DefKind::Fn | DefKind::AssocFn) {
+        return None;
+    }
+    let poly_sig = tcx.fn_sig(def_id).instantiate_identity();
+    let sig = poly_sig.skip_binder();
+    let erased = tcx.instantiate_bound_regions_with_erased(poly_sig);
+    let typing_env = TypingEnv::non_body_analysis(tcx, def_id);
+    let regions_of = |ty: Ty<'tcx>| -> Vec<Region<'tcx>> {
+        ty.walk()
+            .filter_map(GenericArg::as_region)
+            .filter(|region| !region.is_static())
+            .collect()
+    };
+    let returned = regions_of(sig.output());
+    let names = tcx.fn_arg_idents(def_id);
+    let moved = body.map(|body| {
+        let mut moved = MovedLocals::default();
+        mir_visit::Visitor::visit_body(&mut moved, body);
+        moved.0
+    });
+    let params = sig
+        .inputs()
+        .iter()
+        .zip(erased.inputs())
+        .enumerate()
+        .map(|(index, (ty, erased_ty))| ParamSummary {
+            name: names
+                .get(index)
+                .copied()
+                .flatten()
+                .map_or_else(|| "_".to_owned(), |ident| ident.to_string()),
+            usage: match ty.kind() {
+                TyKind::Ref(_, _, Mutability::Mut) => ParamUse::MutableBorrow,
+                TyKind::Ref(..) => ParamUse::SharedBorrow,
+                _ if tcx.type_is_copy_modulo_regions(typing_env, *erased_ty) => ParamUse::Copy,
+                _ if moved
+                    .as_ref()
+                    .is_some_and(|moved| !moved.contains(&Local::new(index + 1))) =>
+                {
+                    ParamUse::Drop
+                }
+                _ => ParamUse::Move,
+            },
+            returned: regions_of(*ty)
+                .iter()
+                .any(|region| returned.contains(region)),
+        })
+        .collect();
+    Some(FnSummary {
+        params,
+        output: erased.output().to_string(),
+    })
+}
+
+/// The captures and kind of the closure `fn_id`; `None` if it is no
+/// closure or is written by a macro.
+pub fn collect_closure(
+    tcx: TyCtxt<'_>,
+    fn_id: LocalDefId,
+    source: &str,
+    offset: u32,
+) -> Option<MirClosure> {
+    let Node::Expr(Expr {
+        kind: ExprKind::Closure(closure),
+        ..
+    }) = tcx.hir_node_by_def_id(fn_id)
+    else {
+        return None;
+    };
+    let TyKind::Closure(_, args) = tcx.type_of(fn_id).instantiate_identity().kind() else {
+        return None;
+    };
+    let head = range_from_span(source, closure.fn_decl_span, offset)?;
+    let kind_origin = tcx
+        .typeck(fn_id)
+        .closure_kind_origins()
+        .get(tcx.local_def_id_to_hir_id(fn_id))
+        .and_then(|(span, place)| {
+            let range = range_from_span(source, *span, offset)?;
+            Some((place_to_string_for_capture(tcx, place), range))
+        });
+    let captures = tcx
+        .closure_captures(fn_id)
+        .iter()
+        .map(|captured| MirCapture {
+            name: captured.to_string(tcx),
+            mode: match captured.info.capture_kind {
+                UpvarCapture::ByValue => CaptureMode::ByValue,
+                UpvarCapture::ByUse => CaptureMode::ByUse,
+                UpvarCapture::ByRef(ty::BorrowKind::Immutable) => CaptureMode::SharedRef,
+                UpvarCapture::ByRef(_) => CaptureMode::MutableRef,
+            },
+        })
+        .collect();
+    Some(MirClosure {
+        head,
+        kind: args.as_closure().kind().as_str().to_owned(),
+        kind_origin,
+        captures,
+    })
+}
+
+/// The contract of the function called with `func`, keeping only the
+/// parameters whose arguments are places, like the `args` of
+/// [`MirTerminator::Call`]
+fn call_summary<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    func: &Operand<'tcx>,
+    args: &[Spanned<Operand<'tcx>>],
+) -> Option<FnSummary> {
+    let (def_id, _) = func.const_fn_def()?;
+    let mut summary = fn_summary(tcx, def_id, None)?;
+    summary.params = summary
+        .params
+        .into_iter()
+        .zip(args)
+        .filter(|(_, arg)| arg.node.place().is_some())
+        .map(|(param, _)| param)
+        .collect();
+    Some(summary)
+}
+
+fn convert_terminator<'tcx>(
+    tcx: TyCtxt<'tcx>,
     fn_id: LocalDefId,
     source: &str,
     offset: u32,
-    terminator: &Terminator<'_>,
+    terminator: &Terminator<'tcx>,
+    drop_state: Option<DropState>,
 ) -> Option<MirTerminator> {
     match &terminator.kind {
         TerminatorKind::Drop { place, .. } => {
@@ -124,12 +579,15 @@ fn convert_terminator(
                 MirTerminator::Drop {
                     local: FnLocal::new(place.local.as_u32(), fn_id.local_def_index.as_u32()),
                     range,
+                    state: drop_state.unwrap_or_default(),
                 }
             })
         }
         TerminatorKind::Call {
             destination,
             fn_span,
+            args,
+            func,
             ..
         } => range_from_span(source, *fn_span, offset).map(|fn_span| MirTerminator::Call {
             destination_local: FnLocal::new(
@@ -137,40 +595,417 @@ fn convert_terminator(
                 fn_id.local_def_index.as_u32(),
             ),
             fn_span,
+            args: args
+                .iter()
+                .filter_map(|arg| arg.node.place())
+                .map(|place| FnLocal::new(place.local.as_u32(), fn_id.local_def_index.as_u32()))
+                .collect(),
+            callee: func
+                .const_fn_def()
+                .map(|(def_id, args)| callee_path(&tcx.def_path_str_with_args(def_id, args)))
+                .unwrap_or_default(),
+            summary: call_summary(tcx, func, args),
         }),
+        TerminatorKind::Yield { .. } => {
+            range_from_span(source, terminator.source_info.span, offset)
+                .map(|range| MirTerminator::Yield { range })
+        }
+        // the loop of an `.await` is not written by the user
+        TerminatorKind::FalseUnwind { .. }
+            if !terminator
+                .source_info
+                .span
+                .is_desugaring(DesugaringKind::Await) =>
+        {
+            range_from_span(source, terminator.source_info.span, offset)
+                .map(|range| MirTerminator::Loop { range })
+        }
         _ => range_from_span(source, terminator.source_info.span, offset)
             .map(|range| MirTerminator::Other { range }),
     }
 }
 
+/// The size in bytes of every local whose type has a known layout
+pub fn collect_local_sizes<'tcx>(tcx: TyCtxt<'tcx>, body: &Body<'tcx>) -> HashMap<Local, u64> {
+    let typing_env = body.typing_env(tcx);
+    body.local_decls
+        .iter_enumerated()
+        .filter_map(|(local, decl)| {
+            let ty = tcx.erase_regions(decl.ty);
+            let layout = tcx.layout_of(typing_env.as_query_input(ty)).ok()?;
+            Some((local, layout.size.bytes()))
+        })
+        .collect()
+}
+
+/// A call of `clone`, `to_owned` or `to_string` on a whole local variable
+/// that returns a value of the variable's own type
+pub struct CloneCall {
+    pub source: Local,
+    pub name: String,
+    pub method: String,
+    /// The whole `x.clone()` expression
+    pub range: Range,
+    /// Where the result is available, the entry of the block the call
+    /// returns to
+    pub after: Location,
+}
+
+/// The local `arg` borrows as a whole, as in `_a = &x`, assigned in `block`
+fn borrowed_whole_local(body: &Body<'_>, block: BasicBlock, arg: Local) -> Option<Local> {
+    body.basic_blocks[block]
+        .statements
+        .iter()
+        .rev()
+        .find_map(|stmt| match &stmt.kind {
+            StatementKind::Assign(assign) if assign.0.as_local() == Some(arg) => Some(&assign.1),
+            _ => None,
+        })
+        .and_then(|rval| match rval {
+            Rvalue::Ref(_, BorrowKind::Shared, place) => place.as_local(),
+            _ => None,
+        })
+}
+
+/// Find the method-call clones of owned, non-`Copy` user variables, e.g.
+/// `x.clone()` with `x: String`
+pub fn collect_clone_calls<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    source: &str,
+    offset: u32,
+    body: &Body<'tcx>,
+    user_vars: &HashMap<Local, (Range, String)>,
+) -> Vec<CloneCall> {
+    let source_map = tcx.sess.source_map();
+    let typing_env = body.typing_env(tcx);
+    body.basic_blocks
+        .iter_enumerated()
+        .filter_map(|(block, bb)| {
+            let terminator = bb.terminator.as_ref()?;
+            let TerminatorKind::Call {
+                func,
+                args,
+                destination,
+                target: Some(target),
+                fn_span,
+                ..
+            } = &terminator.kind
+            else {
+                return None;
+            };
+            let (def_id, _) = func.const_fn_def()?;
+            if !(tcx.is_lang_item(def_id, LangItem::CloneFn)
+                || tcx.is_diagnostic_item(sym::to_owned_method, def_id)
+                || tcx.is_diagnostic_item(sym::to_string_method, def_id))
+            {
+                return None;
+            }
+            let [arg] = &**args else {
+                return None;
+            };
+            let x = borrowed_whole_local(body, block, arg.node.place()?.as_local()?)?;
+            let (_, name) = user_vars.get(&x)?;
+            let ty = body.local_decls[x].ty;
+            if destination.as_local().map(|dest| body.local_decls[dest].ty) != Some(ty)
+                || ty.is_ref()
+                || tcx.type_is_copy_modulo_regions(typing_env, ty)
+            {
+                return None;
+            }
+            // only method calls on the variable itself, like `x.clone()`
+            let span = terminator.source_info.span;
+            let expr = source_map.span_to_snippet(span).ok()?;
+            let call = source_map.span_to_snippet(*fn_span).ok()?;
+            let receiver = expr
+                .strip_suffix(call.as_str())?
+                .trim_end()
+                .strip_suffix('.')?;
+            if span.from_expansion() || receiver.trim() != name {
+                return None;
+            }
+            Some(CloneCall {
+                source: x,
+                name: name.clone(),
+                method: tcx.item_name(def_id).to_string(),
+                range: range_from_span(source, span, offset)?,
+                after: Location {
+                    block: *target,
+                    statement_index: 0,
+                },
+            })
+        })
+        .collect()
+}
+
+/// Determine for every `Drop` terminator whether the dropped place is still
+/// initialized, the same way drop elaboration decides whether a drop needs a
+/// drop flag or can be removed.
+fn collect_drop_states<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    body: &Body<'tcx>,
+) -> HashMap<BasicBlock, DropState> {
+    let move_data = MoveData::gather_moves(body, tcx, |_| true);
+    let mut maybe_init = MaybeInitializedPlaces::new(tcx, body, &move_data)
+        .iterate_to_fixpoint(tcx, body, None)
+        .into_results_cursor(body);
+    let mut maybe_uninit = MaybeUninitializedPlaces::new(tcx, body, &move_data)
+        .iterate_to_fixpoint(tcx, body, None)
+        .into_results_cursor(body);
+
+    body.basic_blocks
+        .iter_enumerated()
+        .filter_map(|(bb, bb_data)| {
+            let TerminatorKind::Drop { place, .. } = &bb_data.terminator().kind else {
+                return None;
+            };
+            let LookupResult::Exact(path) = move_data.rev_lookup.find(place.as_ref()) else {
+                return None;
+            };
+            let location = body.terminator_loc(bb);
+            maybe_init.seek_before_primary_effect(location);
+            maybe_uninit.seek_before_primary_effect(location);
+            let state = match (
+                maybe_init.get().contains(path),
+                maybe_uninit.get().contains(path),
+            ) {
+                (false, _) => DropState::MovedOut,
+                (true, true) => DropState::MaybeDropped,
+                (true, false) => DropState::Dropped,
+            };
+            Some((bb, state))
+        })
+        .collect()
+}
+
+/// Collects the first HIR expression with each span
+struct ExprSpans {
+    spans: HashMap<Span, HirId>,
+}
+impl<'tcx> Visitor<'tcx> for ExprSpans {
+    fn visit_expr(&mut self, ex: &'tcx Expr<'tcx>) {
+        self.spans.entry(ex.span).or_insert(ex.hir_id);
+        intravisit::walk_expr(self, ex);
+    }
+}
+
+/// Collects the spans of statements written in the source
+struct StmtSpans {
+    spans: Vec<Span>,
+}
+impl<'tcx> Visitor<'tcx> for StmtSpans {
+    fn visit_stmt(&mut self, s: &'tcx Stmt<'tcx>) {
+        if !s.span.from_expansion() {
+            self.spans.push(s.span);
+        }
+        intravisit::walk_stmt(self, s);
+    }
+}
+
+/// Map a span produced by a macro expansion to the outermost macro call,
+/// unless it points at an argument written inside that call.
+fn call_site_span(span: Span) -> Span {
+    if !span.from_expansion() {
+        return span;
+    }
+    let call_site = span.source_callsite();
+    if call_site.contains(span) {
+        span
+    } else {
+        call_site
+    }
+}
+
+/// Collect the outermost bang macro calls whose expansions contain
+/// statements of `body`, with the arguments they format.
+pub fn collect_macro_calls(source: &str, offset: u32, body: &Body<'_>) -> Vec<MacroCall> {
+    let mut calls: Vec<MacroCall> = Vec::new();
+    let spans = body
+        .basic_blocks
+        .iter()
+        .flat_map(|bb| bb.statements.iter().map(|stmt| stmt.source_info.span));
+    for span in spans.filter(|span| span.from_expansion()) {
+        let backtrace: Vec<_> = span.macro_backtrace().collect();
+        let Some((name, call_site)) = backtrace.iter().rev().find_map(|expn| match expn.kind {
+            ExpnKind::Macro(MacroKind::Bang, name) => Some((name, expn.call_site)),
+            _ => None,
+        }) else {
+            continue;
+        };
+        let Some(range) = range_from_span(source, call_site, offset) else {
+            continue;
+        };
+        let name = name.as_str();
+        let name = name.rsplit("::").next().unwrap_or(name);
+        if !calls.iter().any(|call| call.range == range) {
+            calls.push(MacroCall {
+                name: name.to_owned(),
+                range,
+                formatted: Vec::new(),
+            });
+        }
+        let formatting = backtrace.iter().any(|expn| {
+            matches!(expn.kind, ExpnKind::Macro(MacroKind::Bang, name)
+                if matches!(name.as_str().rsplit("::").next(), Some("format_args" | "format_args_nl")))
+        });
+        if formatting
+            && let Some(argument) = range_from_span(source, call_site_span(span), offset)
+            && let Some(call) = calls.iter_mut().find(|call| call.range == range)
+            && !call.formatted.contains(&argument)
+        {
+            call.formatted.push(argument);
+        }
+    }
+    calls
+}
+
+/// Collect the statements of the body of `fn_id`, outside of macro
+/// expansions and nested closures.
+pub fn collect_statements(
+    tcx: TyCtxt<'_>,
+    fn_id: LocalDefId,
+    source: &str,
+    offset: u32,
+) -> Vec<Range> {
+    let Some(hir_body) = tcx.hir_maybe_body_owned_by(fn_id) else {
+        return Vec::new();
+    };
+    let mut stmts = StmtSpans { spans: Vec::new() };
+    stmts.visit_body(hir_body);
+    stmts
+        .spans
+        .into_iter()
+        .filter_map(|span| range_from_span(source, span, offset))
+        .collect()
+}
+
+/// Classify where the temporary created by expression `expr` is dropped
+fn temp_scope(tcx: TyCtxt<'_>, fn_id: LocalDefId, expr: HirId) -> Option<TempScope> {
+    let scope_tree = tcx.region_scope_tree(fn_id);
+    let (scope, _) = tcx
+        .typeck(fn_id)
+        .rvalue_scopes
+        .temporary_scope(scope_tree, expr.local_id);
+    let scope_id = scope?.hir_id(scope_tree)?;
+    // a scrutinee keeps its temporaries alive until the end of the `match`
+    // or `if let` that owns it, unless a terminating scope comes first
+    let mut child = expr;
+    for (parent_id, parent) in tcx.hir_parent_iter(expr) {
+        if child == scope_id {
+            break;
+        }
+        if let Node::Expr(parent_expr) = parent {
+            match parent_expr.kind {
+                ExprKind::Match(scrutinee, ..) if scrutinee.hir_id == child => {
+                    return Some(TempScope::MatchScrutinee);
+                }
+                ExprKind::Let(let_expr) if let_expr.init.hir_id == child => {
+                    return Some(TempScope::LetScrutinee);
+                }
+                _ => {}
+            }
+        }
+        child = parent_id;
+    }
+    let is_tail = matches!(
+        tcx.parent_hir_node(scope_id),
+        Node::Block(block) if block.expr.is_some_and(|tail| tail.hir_id == scope_id)
+    );
+    Some(match tcx.hir_node(scope_id) {
+        Node::Block(_) => TempScope::Extended,
+        Node::Expr(_) if is_tail => TempScope::BlockTail,
+        _ => TempScope::Statement,
+    })
+}
+
+/// Collect the temporaries of `body` together with the expression that
+/// creates them and the scope they are dropped at.
+pub fn collect_temporaries(
+    tcx: TyCtxt<'_>,
+    fn_id: LocalDefId,
+    source: &str,
+    offset: u32,
+    body: &Body<'_>,
+) -> HashMap<Local, MirTemporary> {
+    let Some(hir_body) = tcx.hir_maybe_body_owned_by(fn_id) else {
+        return HashMap::new();
+    };
+    let mut exprs = ExprSpans {
+        spans: HashMap::new(),
+    };
+    exprs.visit_body(hir_body);
+
+    body.local_decls
+        .iter_enumerated()
+        .filter(|(local, decl)| {
+            body.local_kind(*local) == LocalKind::Temp
+                && !decl.is_user_variable()
+                && !decl.source_info.span.from_expansion()
+        })
+        .filter_map(|(local, decl)| {
+            let span = decl.source_info.span;
+            let scope = temp_scope(tcx, fn_id, *exprs.spans.get(&span)?)?;
+            let expr = tcx
+                .sess
+                .source_map()
+                .span_to_snippet(span)
+                .ok()?
+                .split_whitespace()
+                .collect::<Vec<_>>()
+                .join(" ");
+            let span = range_from_span(source, span, offset)?;
+            Some((local, MirTemporary { span, expr, scope }))
+        })
+        .collect()
+}
+
 /// Collect and transform [`BasicBlocks`] into our data structure
 /// [`MirBasicBlock`]s.
-pub fn collect_basic_blocks(
+pub fn collect_basic_blocks<'tcx>(
+    tcx: TyCtxt<'tcx>,
     fn_id: LocalDefId,
     source: &str,
     offset: u32,
-    basic_blocks: &BasicBlocks<'_>,
-    source_map: &SourceMap,
+    body: &Body<'tcx>,
 ) -> Vec<MirBasicBlock> {
-    basic_blocks
+    let source_map = tcx.sess.source_map();
+    let drop_states = collect_drop_states(tcx, body);
+    let pointers = pointer_sources(body);
+    body.basic_blocks
         .iter_enumerated()
-        .map(|(_bb, bb_data)| {
+        .map(|(bb, bb_data)| {
             let statements: Vec<_> = bb_data
                 .statements
                 .iter()
                 .filter(|stmt| stmt.source_info.span.is_visible(source_map))
+                // spans can only be walked on the compiler thread
+                .map(|stmt| (call_site_span(stmt.source_info.span), stmt))
                 .collect();
             let statements = statements
                 .par_iter()
-                .filter_map(|statement| convert_statement(fn_id, source, offset, statement))
+                .filter_map(|(span, statement)| {
+                    convert_statement(fn_id, source, offset, *span, &pointers, statement)
+                })
                 .collect();
-            let terminator = bb_data
+            let terminator = bb_data.terminator.as_ref().and_then(|term| {
+                convert_terminator(
+                    tcx,
+                    fn_id,
+                    source,
+                    offset,
+                    term,
+                    drop_states.get(&bb).copied(),
+                )
+            });
+            let successors = bb_data
                 .terminator
                 .as_ref()
-                .and_then(|term| convert_terminator(fn_id, source, offset, term));
+                .map(|term| term.successors().map(BasicBlock::as_u32).collect())
+                .unwrap_or_default();
             MirBasicBlock {
                 statements,
                 terminator,
+                successors,
+                cleanup: bb_data.is_cleanup,
             }
         })
         .collect()
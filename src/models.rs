@@ -150,6 +150,15 @@ impl Workspace {
     }
 }
 
+/// Printed by the compiler wrapper before it analyzes the functions of a
+/// crate, so that the language server can report their progress
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+#[serde(tag = "reason", rename = "ferrous-owl-crate")]
+pub struct CrateFunctions {
+    pub name: String,
+    pub functions: usize,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 #[serde(transparent)]
 pub struct Crate(pub HashMap<String, File>);
@@ -175,12 +184,23 @@ pub enum MirRval {
         target_local: FnLocal,
         range: Range,
     },
+    /// Copy of a `Copy` value.
+    Copy {
+        target_local: FnLocal,
+        range: Range,
+    },
     Borrow {
         target_local: FnLocal,
         range: Range,
         mutable: bool,
         outlive: Option<Range>,
     },
+    /// Raw pointer into `target_local`, e.g. `&raw const x` or `r as *mut T`.
+    RawPtr {
+        target_local: FnLocal,
+        range: Range,
+        mutable: bool,
+    },
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -215,16 +235,50 @@ impl MirStatement {
     }
 }
 
+/// Whether the value is still initialized when a drop is reached.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum DropState {
+    /// The destructor runs.
+    #[default]
+    Dropped,
+    /// The destructor runs only if the value was not moved on the way here.
+    MaybeDropped,
+    /// The value was moved out; the drop does nothing.
+    MovedOut,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 #[serde(rename_all = "snake_case", tag = "type")]
 pub enum MirTerminator {
     Drop {
         local: FnLocal,
         range: Range,
+        #[serde(default)]
+        state: DropState,
     },
     Call {
         destination_local: FnLocal,
         fn_span: Range,
+        /// Locals passed as arguments, by copy or move.
+        #[serde(default)]
+        args: Vec<FnLocal>,
+        /// Path of the called function as `Type::method`, without generic
+        /// arguments; empty for calls through function pointers or closures.
+        #[serde(default)]
+        callee: String,
+        /// Contract of the called function, with the parameters that
+        /// correspond to `args`.
+        #[serde(default)]
+        summary: Option<FnSummary>,
+    },
+    /// Suspension point of a coroutine, i.e. an `.await`.
+    Yield {
+        range: Range,
+    },
+    /// Entry of a loop body, reached again on every iteration.
+    Loop {
+        range: Range,
     },
     Other {
         range: Range,
@@ -235,15 +289,24 @@ impl MirTerminator {
     pub const fn range(&self) -> Range {
         match self {
             Self::Call { fn_span, .. } => *fn_span,
-            Self::Drop { range, .. } | Self::Other { range } => *range,
+            Self::Drop { range, .. }
+            | Self::Yield { range }
+            | Self::Loop { range }
+            | Self::Other { range } => *range,
         }
     }
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
 pub struct MirBasicBlock {
     pub statements: Vec<MirStatement>,
     pub terminator: Option<MirTerminator>,
+    /// Indices of the blocks the terminator may continue to.
+    #[serde(default)]
+    pub successors: Vec<u32>,
+    /// Whether the block is only reached while unwinding.
+    #[serde(default)]
+    pub cleanup: bool,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -260,6 +323,12 @@ pub enum MirDecl {
         drop: bool,
         drop_range: Vec<Range>,
         must_live_at: Vec<Range>,
+        /// Size of the type in bytes, if its layout is known.
+        #[serde(default)]
+        size: Option<u64>,
+        /// How the variable is bound by its pattern; `None` for `self`.
+        #[serde(default)]
+        binding: Option<MirBinding>,
     },
     Other {
         local: FnLocal,
@@ -270,14 +339,360 @@ pub enum MirDecl {
         drop: bool,
         drop_range: Vec<Range>,
         must_live_at: Vec<Range>,
+        #[serde(default)]
+        temporary: Option<MirTemporary>,
+        /// Size of the type in bytes, if its layout is known.
+        #[serde(default)]
+        size: Option<u64>,
     },
 }
 
+/// How a pattern binds a variable.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum BindingMode {
+    /// The value is moved or copied into the variable.
+    Value,
+    /// The variable is a shared reference to the value, as with `ref x`.
+    Ref,
+    /// The variable is a mutable reference to the value, as with `ref mut x`.
+    RefMut,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct MirBinding {
+    pub mode: BindingMode,
+    /// Whether the mode is not written but follows from matching a
+    /// reference with a non-reference pattern (default binding modes).
+    pub implicit: bool,
+}
+
+/// Where the temporary created by an expression is dropped.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum TempScope {
+    /// Dropped at the end of the enclosing statement.
+    Statement,
+    /// Dropped right after the tail expression of a block.
+    BlockTail,
+    /// Extended to the end of the enclosing block by a `let` binding.
+    Extended,
+    /// Part of a `match` scrutinee; dropped after the whole `match`.
+    MatchScrutinee,
+    /// Part of an `if let` or `while let` scrutinee; dropped after its body.
+    LetScrutinee,
+}
+impl TempScope {
+    #[must_use]
+    pub const fn is_scrutinee(self) -> bool {
+        matches!(self, Self::MatchScrutinee | Self::LetScrutinee)
+    }
+
+    #[must_use]
+    pub const fn explanation(self) -> &'static str {
+        match self {
+            Self::Statement => "temporary dropped at end of statement",
+            Self::BlockTail => "temporary dropped after the block's tail expression",
+            Self::Extended => "lifetime extended to enclosing block",
+            Self::MatchScrutinee => {
+                "temporary in `match` scrutinee lives until the end of the `match`"
+            }
+            Self::LetScrutinee => "temporary in `if let` scrutinee lives until the end of its body",
+        }
+    }
+}
+
+/// The expression a temporary local was created for.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct MirTemporary {
+    pub span: Range,
+    pub expr: String,
+    pub scope: TempScope,
+}
+
+/// A local whose type contains an origin related to a lifetime parameter.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct MirLifetimeLocal {
+    pub local: FnLocal,
+    /// Points where the lifetime parameter must hold because of this local.
+    pub must_hold_at: Vec<Range>,
+}
+
+/// A named lifetime parameter of the function or its enclosing `impl`.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct MirLifetimeParam {
+    /// Name including the leading quote, e.g. `'a`.
+    pub name: String,
+    /// Occurrences of the parameter in the generics and the signature.
+    pub spans: Vec<Range>,
+    pub locals: Vec<MirLifetimeLocal>,
+}
+
+/// An origin (region variable) of the borrow checker.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct MirOrigin {
+    pub id: u32,
+    /// Locals whose types contain this origin.
+    pub locals: Vec<FnLocal>,
+    /// Points where the origin is live.
+    pub live_at: Vec<Range>,
+}
+
+/// A subset relation between two origins: loans in `sub` flow into `sup`,
+/// i.e. `sup: sub`.
 #[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct MirOriginEdge {
+    pub sub: u32,
+    pub sup: u32,
+    /// Points where the relation holds.
+    pub holds_at: Vec<Range>,
+}
+
+/// A clone of a variable that is not used afterwards, so it could be moved
+/// instead, e.g. `x.clone()`.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct NeedlessClone {
+    pub local: FnLocal,
+    pub name: String,
+    /// `clone`, `to_owned` or `to_string`
+    pub method: String,
+    /// The whole call expression.
+    pub range: Range,
+}
+
+/// The origin subset graph computed by Polonius.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct MirOriginGraph {
+    pub origins: Vec<MirOrigin>,
+    pub edges: Vec<MirOriginEdge>,
+}
+impl MirOriginGraph {
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.origins.is_empty() && self.edges.is_empty()
+    }
+}
+
+/// How a closure captures a variable.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum CaptureMode {
+    /// Moved or copied into the closure.
+    ByValue,
+    /// Cloned into the closure, by a `use` closure.
+    ByUse,
+    SharedRef,
+    MutableRef,
+}
+impl CaptureMode {
+    #[must_use]
+    pub const fn explanation(self) -> &'static str {
+        match self {
+            Self::ByValue => "moved or copied into the closure",
+            Self::ByUse => "cloned into the closure",
+            Self::SharedRef => "borrowed by the closure",
+            Self::MutableRef => "mutably borrowed by the closure",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct MirCapture {
+    /// The captured place, e.g. `a` or `a.field`.
+    pub name: String,
+    pub mode: CaptureMode,
+}
+impl MirCapture {
+    /// The capture as written in a capture list, e.g. `&mut a`
+    #[must_use]
+    pub fn label(&self) -> String {
+        let name = &self.name;
+        match self.mode {
+            CaptureMode::ByValue => format!("move {name}"),
+            CaptureMode::ByUse => format!("use {name}"),
+            CaptureMode::SharedRef => format!("&{name}"),
+            CaptureMode::MutableRef => format!("&mut {name}"),
+        }
+    }
+}
+
+/// The captures of a closure and the closure trait it implements.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct MirClosure {
+    /// The `move |...|` head of the closure.
+    pub head: Range,
+    /// `Fn`, `FnMut` or `FnOnce`.
+    pub kind: String,
+    /// The place whose use makes the closure `FnMut` or `FnOnce`, and where
+    /// it is used.
+    pub kind_origin: Option<(String, Range)>,
+    pub captures: Vec<MirCapture>,
+}
+
+/// An outermost macro call in a function body, such as `println!(..)`.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct MacroCall {
+    /// Name of the macro without the `!`.
+    pub name: String,
+    pub range: Range,
+    /// Arguments the macro passes to `format_args!`.
+    #[serde(default)]
+    pub formatted: Vec<Range>,
+}
+
+impl MacroCall {
+    /// Describe a borrow or move of `range` made by this macro, e.g.
+    /// "borrowed by `println!` formatting".
+    #[must_use]
+    pub fn describe(&self, range: Range, verb: &str) -> String {
+        if self.formatted.contains(&range) {
+            format!("{verb} by `{}!` formatting", self.name)
+        } else {
+            format!("{verb} by `{}!`", self.name)
+        }
+    }
+}
+
+/// What a function does with one of its parameters.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum ParamUse {
+    /// Takes ownership of the argument.
+    Move,
+    /// Takes ownership of the argument only to drop it, e.g. a by-value
+    /// parameter the body merely reads.
+    Drop,
+    /// Takes a copy of a `Copy` argument.
+    Copy,
+    /// Borrows the argument through a shared reference.
+    SharedBorrow,
+    /// Borrows the argument through a mutable reference.
+    MutableBorrow,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct ParamSummary {
+    pub name: String,
+    pub usage: ParamUse,
+    /// Whether the return value may borrow from this parameter.
+    pub returned: bool,
+}
+
+/// The ownership contract of a function, derived from its body or, when
+/// that is not analyzed, its signature: what it does with its arguments and
+/// what the return value borrows from.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug, Default)]
+pub struct FnSummary {
+    pub params: Vec<ParamSummary>,
+    /// Type of the return value.
+    pub output: String,
+}
+
+impl FnSummary {
+    /// This summary of a call with what the analyzed body of the callee,
+    /// summarized in `callee`, does with each parameter
+    #[must_use]
+    pub fn with_body_of(&self, callee: &Self) -> Self {
+        let params = self
+            .params
+            .iter()
+            .map(|param| ParamSummary {
+                usage: callee
+                    .params
+                    .iter()
+                    .find(|other| other.name == param.name)
+                    .map_or(param.usage, |other| other.usage),
+                ..param.clone()
+            })
+            .collect();
+        Self {
+            params,
+            output: self.output.clone(),
+        }
+    }
+
+    /// Describe what a call does with its arguments, given the variables
+    /// they are taken from, e.g. "moves `buf`, borrows `cfg` until the
+    /// returned `Iter` is dropped". Copies are left out unless borrowed from.
+    #[must_use]
+    pub fn describe(&self, args: &[Option<&str>]) -> String {
+        let output = if self.output.starts_with('&') {
+            "reference".to_owned()
+        } else {
+            let path = self.output.split('<').next().unwrap_or(&self.output);
+            format!("`{}`", path.rsplit("::").next().unwrap_or(path))
+        };
+        self.params
+            .iter()
+            .enumerate()
+            .filter(|(_, param)| param.usage != ParamUse::Copy || param.returned)
+            .map(|(index, param)| {
+                let verb = match param.usage {
+                    ParamUse::Move => "moves",
+                    ParamUse::Drop => "drops",
+                    ParamUse::Copy => "copies",
+                    ParamUse::SharedBorrow => "borrows",
+                    ParamUse::MutableBorrow => "mutably borrows",
+                };
+                let arg = args.get(index).copied().flatten().map_or_else(
+                    || format!("the value passed as `{}`", param.name),
+                    |name| format!("`{name}`"),
+                );
+                if param.returned {
+                    format!("{verb} {arg} until the returned {output} is dropped")
+                } else {
+                    format!("{verb} {arg}")
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+/// How precisely a function was analyzed, depending on the analysis limits
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum AnalysisPrecision {
+    #[default]
+    Full,
+    /// Borrows were checked without locations, so borrow ranges are missing
+    LocationInsensitive,
+    /// The function had too many facts to check its borrows
+    TooLarge,
+    /// The borrow check did not finish in time
+    TimedOut,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
 pub struct Function {
     pub fn_id: u32,
+    /// Path of the function definition, e.g. `module::Type::method`.
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub span: Option<Range>,
     pub basic_blocks: Vec<MirBasicBlock>,
     pub decls: Vec<MirDecl>,
+    #[serde(default)]
+    pub lifetimes: Vec<MirLifetimeParam>,
+    /// Only computed for graph exports
+    #[serde(default, skip_serializing_if = "MirOriginGraph::is_empty")]
+    pub origin_graph: MirOriginGraph,
+    #[serde(default)]
+    pub needless_clones: Vec<NeedlessClone>,
+    /// Statements of the body, where code such as a `drop` can be inserted.
+    #[serde(default)]
+    pub statements: Vec<Range>,
+    #[serde(default)]
+    pub summary: Option<FnSummary>,
+    /// Captures of the closure, if this function is one.
+    #[serde(default)]
+    pub closure: Option<MirClosure>,
+    /// Macro calls whose expansions borrow or move locals.
+    #[serde(default)]
+    pub macro_calls: Vec<MacroCall>,
+    #[serde(default)]
+    pub precision: AnalysisPrecision,
 }
 
 #[cfg(test)]
@@ -390,6 +805,7 @@ mod tests {
             fn_id: 42,
             basic_blocks: Vec::new(),
             decls: Vec::new(),
+            ..Default::default()
         };
 
         let function_clone = function.clone();
@@ -409,6 +825,7 @@ mod tests {
                 fn_id: i,
                 basic_blocks: Vec::new(),
                 decls: Vec::new(),
+                ..Default::default()
             });
         }
 
@@ -419,6 +836,7 @@ mod tests {
             fn_id: 999,
             basic_blocks: Vec::with_capacity(1000),
             decls: Vec::with_capacity(500),
+            ..Default::default()
         };
 
         assert!(large_function.basic_blocks.capacity() >= 1000);
@@ -506,4 +924,71 @@ mod tests {
         let debug_fn_local = format!("{fn_local:?}");
         assert!(debug_fn_local.contains("FnLocal"));
     }
+
+    #[test]
+    fn test_crate_functions_line_is_not_a_workspace() {
+        let announcement = CrateFunctions {
+            name: "app".to_owned(),
+            functions: 3,
+        };
+        let line = serde_json::to_string(&announcement).unwrap();
+        assert_eq!(
+            line,
+            r#"{"reason":"ferrous-owl-crate","name":"app","functions":3}"#
+        );
+        assert_eq!(
+            serde_json::from_str::<CrateFunctions>(&line).unwrap(),
+            announcement
+        );
+        assert!(serde_json::from_str::<Workspace>(&line).is_err());
+        assert!(serde_json::from_str::<CrateFunctions>(r#"{"app":{}}"#).is_err());
+    }
+
+    #[test]
+    fn test_fn_summary_description() {
+        let param = |name: &str, usage, returned| ParamSummary {
+            name: name.to_owned(),
+            usage,
+            returned,
+        };
+        let summary = FnSummary {
+            params: vec![
+                param("buf", ParamUse::Move, false),
+                param("cfg", ParamUse::SharedBorrow, true),
+                param("n", ParamUse::Copy, false),
+                param("out", ParamUse::MutableBorrow, false),
+            ],
+            output: "std::slice::Iter<'_, u8>".to_owned(),
+        };
+        assert_eq!(
+            summary.describe(&[Some("data"), Some("config"), Some("len"), None]),
+            "moves `data`, borrows `config` until the returned `Iter` is dropped, mutably borrows \
+             the value passed as `out`"
+        );
+
+        let summary = FnSummary {
+            params: vec![param("s", ParamUse::SharedBorrow, true)],
+            output: "&str".to_owned(),
+        };
+        assert_eq!(
+            summary.describe(&[Some("name")]),
+            "borrows `name` until the returned reference is dropped"
+        );
+
+        let call = FnSummary {
+            params: vec![param("buf", ParamUse::Move, false)],
+            output: "usize".to_owned(),
+        };
+        let body = FnSummary {
+            params: vec![
+                param("n", ParamUse::Copy, false),
+                param("buf", ParamUse::Drop, false),
+            ],
+            output: "usize".to_owned(),
+        };
+        assert_eq!(
+            call.with_body_of(&body).describe(&[Some("data")]),
+            "drops `data`"
+        );
+    }
 }
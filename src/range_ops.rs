@@ -113,7 +113,7 @@ pub fn mir_visit(func: &Function, visitor: &mut impl MirVisitor) {
 mod tests {
     use super::*;
     use crate::models::{
-        FnLocal, Loc, MirBasicBlock, MirDecl, MirRval, MirStatement, MirTerminator,
+        DropState, FnLocal, Loc, MirBasicBlock, MirDecl, MirRval, MirStatement, MirTerminator,
     };
 
     fn r(from: u32, until: u32) -> Range {
@@ -315,6 +315,8 @@ mod tests {
                     drop: false,
                     drop_range: vec![],
                     must_live_at: vec![],
+                    size: None,
+                    binding: None,
                 },
                 MirDecl::Other {
                     local: FnLocal::new(2, 0),
@@ -325,6 +327,8 @@ mod tests {
                     drop: false,
                     drop_range: vec![],
                     must_live_at: vec![],
+                    size: None,
+                    temporary: None,
                 },
             ],
             basic_blocks: vec![
@@ -348,7 +352,11 @@ mod tests {
                     terminator: Some(MirTerminator::Call {
                         destination_local: local,
                         fn_span: r(10, 20),
+                        args: vec![],
+                        callee: String::new(),
+                        summary: None,
                     }),
+                    ..Default::default()
                 },
                 MirBasicBlock {
                     statements: vec![MirStatement::Assign {
@@ -362,9 +370,12 @@ mod tests {
                     terminator: Some(MirTerminator::Drop {
                         local,
                         range: r(30, 35),
+                        state: DropState::Dropped,
                     }),
+                    ..Default::default()
                 },
             ],
+            ..Default::default()
         }
     }
 
@@ -385,6 +396,7 @@ mod tests {
             fn_id: 0,
             decls: vec![],
             basic_blocks: vec![],
+            ..Default::default()
         };
         let mut counter = VisitCounter::new();
         mir_visit(&func, &mut counter);
@@ -402,7 +414,9 @@ mod tests {
             basic_blocks: vec![MirBasicBlock {
                 statements: vec![MirStatement::Other { range: r(0, 5) }],
                 terminator: None,
+                ..Default::default()
             }],
+            ..Default::default()
         };
         let mut counter = VisitCounter::new();
         mir_visit(&func, &mut counter);
@@ -0,0 +1,492 @@
+use std::collections::{HashMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    lints,
+    models::{FnLocal, Function, MirDecl, MirRval, MirStatement, MirTerminator, Range},
+    range_ops,
+};
+
+/// Whether a runtime borrow allows other borrows of the same cell meanwhile
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum RuntimeBorrowKind {
+    Shared,
+    Mutable,
+}
+
+/// A method whose call borrows its receiver at runtime, e.g.
+/// `std::cell::RefCell::borrow_mut`
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct RuntimeBorrowApi {
+    /// Path of the method as `Type::method`, without generic arguments
+    pub path: String,
+    pub kind: RuntimeBorrowKind,
+}
+
+const DEFAULT_APIS: [(&str, RuntimeBorrowKind); 22] = [
+    ("std::cell::RefCell::borrow", RuntimeBorrowKind::Shared),
+    ("std::cell::RefCell::try_borrow", RuntimeBorrowKind::Shared),
+    ("std::cell::RefCell::borrow_mut", RuntimeBorrowKind::Mutable),
+    (
+        "std::cell::RefCell::try_borrow_mut",
+        RuntimeBorrowKind::Mutable,
+    ),
+    ("std::sync::Mutex::lock", RuntimeBorrowKind::Mutable),
+    ("std::sync::Mutex::try_lock", RuntimeBorrowKind::Mutable),
+    ("std::sync::RwLock::read", RuntimeBorrowKind::Shared),
+    ("std::sync::RwLock::try_read", RuntimeBorrowKind::Shared),
+    ("std::sync::RwLock::write", RuntimeBorrowKind::Mutable),
+    ("std::sync::RwLock::try_write", RuntimeBorrowKind::Mutable),
+    ("std::rc::Rc::clone", RuntimeBorrowKind::Shared),
+    ("std::sync::Arc::clone", RuntimeBorrowKind::Shared),
+    ("tokio::sync::Mutex::lock", RuntimeBorrowKind::Mutable),
+    ("tokio::sync::Mutex::try_lock", RuntimeBorrowKind::Mutable),
+    ("tokio::sync::RwLock::read", RuntimeBorrowKind::Shared),
+    ("tokio::sync::RwLock::write", RuntimeBorrowKind::Mutable),
+    ("lock_api::Mutex::lock", RuntimeBorrowKind::Mutable),
+    ("lock_api::Mutex::try_lock", RuntimeBorrowKind::Mutable),
+    ("lock_api::RwLock::read", RuntimeBorrowKind::Shared),
+    ("lock_api::RwLock::write", RuntimeBorrowKind::Mutable),
+    ("lock_api::ReentrantMutex::lock", RuntimeBorrowKind::Shared),
+    (
+        "lock_api::ReentrantMutex::try_lock",
+        RuntimeBorrowKind::Shared,
+    ),
+];
+
+/// Methods of `Cell` that copy, replace or take its value without lending
+/// out a reference, so there is nothing to check at runtime
+const CELL_ACCESSES: [&str; 5] = [
+    "std::cell::Cell::get",
+    "std::cell::Cell::set",
+    "std::cell::Cell::replace",
+    "std::cell::Cell::take",
+    "std::cell::Cell::swap",
+];
+
+/// Calls that only unwrap the guard returned by a runtime borrow
+const UNWRAPS: [&str; 4] = [
+    "std::result::Result::unwrap",
+    "std::result::Result::expect",
+    "std::option::Option::unwrap",
+    "std::option::Option::expect",
+];
+
+/// The runtime borrow APIs to look for
+#[derive(Clone, Debug)]
+pub struct RuntimeBorrowTable {
+    apis: Vec<RuntimeBorrowApi>,
+}
+impl Default for RuntimeBorrowTable {
+    fn default() -> Self {
+        Self {
+            apis: DEFAULT_APIS
+                .iter()
+                .map(|(path, kind)| RuntimeBorrowApi {
+                    path: (*path).to_owned(),
+                    kind: *kind,
+                })
+                .collect(),
+        }
+    }
+}
+impl RuntimeBorrowTable {
+    /// Add APIs, replacing the kind of already known paths
+    pub fn extend(&mut self, apis: impl IntoIterator<Item = RuntimeBorrowApi>) {
+        for api in apis {
+            self.apis.retain(|known| known.path != api.path);
+            self.apis.push(api);
+        }
+    }
+
+    #[must_use]
+    pub fn kind_of(&self, callee: &str) -> Option<RuntimeBorrowKind> {
+        self.apis
+            .iter()
+            .find(|api| api.path == callee)
+            .map(|api| api.kind)
+    }
+}
+
+/// A call borrowing `cell` at runtime and the ranges in which the returned
+/// `guard` keeps it borrowed
+#[derive(Clone, Debug)]
+pub struct RuntimeBorrow {
+    pub cell: FnLocal,
+    pub guard: FnLocal,
+    pub kind: RuntimeBorrowKind,
+    pub api: String,
+    pub call: Range,
+    pub holds: Vec<Range>,
+}
+
+/// The local a borrow or move chain assigned to `local` starts from
+fn origin_local(func: &Function, local: FnLocal) -> FnLocal {
+    let sources: HashMap<_, _> = func
+        .basic_blocks
+        .iter()
+        .flat_map(|bb| &bb.statements)
+        .filter_map(|stmt| match stmt {
+            MirStatement::Assign {
+                target_local,
+                rval:
+                    Some(
+                        MirRval::Borrow {
+                            target_local: from, ..
+                        }
+                        | MirRval::Move {
+                            target_local: from, ..
+                        },
+                    ),
+                ..
+            } => Some((*target_local, *from)),
+            _ => None,
+        })
+        .collect();
+    let mut visited = HashSet::new();
+    let mut current = local;
+    while visited.insert(current)
+        && let Some(from) = sources.get(&current)
+    {
+        current = *from;
+    }
+    current
+}
+
+/// Follow the guard through calls that only unwrap it
+fn unwrapped_guard(func: &Function, guard: FnLocal) -> FnLocal {
+    func.basic_blocks
+        .iter()
+        .find_map(|bb| match &bb.terminator {
+            Some(MirTerminator::Call {
+                destination_local,
+                args,
+                callee,
+                ..
+            }) if args.as_slice() == [guard] && UNWRAPS.contains(&callee.as_str()) => {
+                Some(unwrapped_guard(func, *destination_local))
+            }
+            _ => None,
+        })
+        .unwrap_or(guard)
+}
+
+fn has_drop(func: &Function, local: FnLocal) -> bool {
+    func.decls.iter().any(|decl| match decl {
+        MirDecl::User {
+            local: decl_local,
+            drop,
+            ..
+        }
+        | MirDecl::Other {
+            local: decl_local,
+            drop,
+            ..
+        } => *decl_local == local && *drop,
+    })
+}
+
+/// All calls of `func` to an API of `table`
+#[must_use]
+pub fn runtime_borrows(func: &Function, table: &RuntimeBorrowTable) -> Vec<RuntimeBorrow> {
+    let mut borrows = Vec::new();
+    for bb in &func.basic_blocks {
+        let Some(MirTerminator::Call {
+            destination_local,
+            fn_span,
+            args,
+            callee,
+            ..
+        }) = &bb.terminator
+        else {
+            continue;
+        };
+        let (Some(kind), Some(receiver)) = (table.kind_of(callee), args.first()) else {
+            continue;
+        };
+        let guard = unwrapped_guard(func, *destination_local);
+        let holds = if has_drop(func, guard) {
+            let mut holds: Vec<_> = lints::guard_holds(func, guard)
+                .into_iter()
+                .map(|hold| hold.range)
+                .collect();
+            holds.push(*fn_span);
+            range_ops::eliminated_ranges(holds)
+        } else {
+            vec![*fn_span]
+        };
+        borrows.push(RuntimeBorrow {
+            cell: origin_local(func, *receiver),
+            guard,
+            kind,
+            api: callee.clone(),
+            call: *fn_span,
+            holds,
+        });
+    }
+    borrows.sort_by_key(|borrow| borrow.call.from());
+    borrows
+}
+
+/// A call accessing `cell` without a runtime borrow
+#[derive(Clone, Debug)]
+pub struct CellAccess {
+    pub cell: FnLocal,
+    pub api: String,
+    pub call: Range,
+}
+
+/// All calls of `func` to a method of `Cell` that needs no runtime check
+#[must_use]
+pub fn cell_accesses(func: &Function) -> Vec<CellAccess> {
+    func.basic_blocks
+        .iter()
+        .filter_map(|bb| match &bb.terminator {
+            Some(MirTerminator::Call {
+                fn_span,
+                args,
+                callee,
+                ..
+            }) if CELL_ACCESSES.contains(&callee.as_str()) => Some(CellAccess {
+                cell: origin_local(func, *args.first()?),
+                api: callee.clone(),
+                call: *fn_span,
+            }),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Whether calls of `api` return an error instead of failing when the cell
+/// is already borrowed, like `RefCell::try_borrow` or `Mutex::try_lock`
+fn is_fallible(api: &str) -> bool {
+    api.rsplit("::")
+        .next()
+        .is_some_and(|method| method.starts_with("try_"))
+}
+
+/// A runtime borrow made while another one of the same cell is held, and at
+/// least one of them is mutable
+#[derive(Clone, Debug)]
+pub struct RuntimeBorrowConflict {
+    pub cell: FnLocal,
+    /// The call that fails at runtime
+    pub call: Range,
+    pub api: String,
+    pub kind: RuntimeBorrowKind,
+    /// The call of the borrow still held
+    pub held: Range,
+}
+
+/// Conflicting runtime borrows among `borrows`; calls that return an error
+/// instead of failing are never the conflicting ones
+#[must_use]
+pub fn conflicts(borrows: &[RuntimeBorrow]) -> Vec<RuntimeBorrowConflict> {
+    let mut conflicts = Vec::new();
+    for held in borrows {
+        for borrow in borrows {
+            if borrow.cell == held.cell
+                && !is_fallible(&borrow.api)
+                && borrow.call != held.call
+                && (borrow.kind == RuntimeBorrowKind::Mutable
+                    || held.kind == RuntimeBorrowKind::Mutable)
+                && held.holds.iter().any(|hold| {
+                    hold.from() < borrow.call.from() && borrow.call.until() <= hold.until()
+                })
+            {
+                conflicts.push(RuntimeBorrowConflict {
+                    cell: borrow.cell,
+                    call: borrow.call,
+                    api: borrow.api.clone(),
+                    kind: borrow.kind,
+                    held: held.call,
+                });
+            }
+        }
+    }
+    conflicts
+}
+
+/// How a conflicting call of `api` fails
+#[must_use]
+pub fn failure(api: &str, kind: RuntimeBorrowKind) -> &'static str {
+    match kind {
+        _ if !api.contains("RefCell::") => "may deadlock or panic",
+        RuntimeBorrowKind::Mutable => "panics with `BorrowMutError`",
+        RuntimeBorrowKind::Shared => "panics with `BorrowError`",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{DropState, Loc, MirBasicBlock};
+
+    fn r(from: u32, until: u32) -> Range {
+        Range::new(Loc::from(from), Loc::from(until)).unwrap()
+    }
+
+    fn local(id: u32) -> FnLocal {
+        FnLocal::new(id, 0)
+    }
+
+    fn borrow_call(dest: u32, receiver: u32, callee: &str, fn_span: Range) -> MirBasicBlock {
+        MirBasicBlock {
+            statements: vec![MirStatement::Assign {
+                target_local: local(receiver),
+                range: fn_span,
+                rval: Some(MirRval::Borrow {
+                    target_local: local(1),
+                    range: fn_span,
+                    mutable: false,
+                    outlive: None,
+                }),
+            }],
+            terminator: Some(MirTerminator::Call {
+                destination_local: local(dest),
+                fn_span,
+                args: vec![local(receiver)],
+                callee: callee.to_owned(),
+                summary: None,
+            }),
+            ..Default::default()
+        }
+    }
+
+    fn guard_decl(id: u32, drop_at: Range) -> MirDecl {
+        MirDecl::Other {
+            local: local(id),
+            ty: "std::cell::RefMut<'_, i32>".into(),
+            lives: vec![],
+            shared_borrow: vec![],
+            mutable_borrow: vec![],
+            drop: true,
+            drop_range: vec![drop_at],
+            must_live_at: vec![],
+            size: None,
+            temporary: None,
+        }
+    }
+
+    fn drop_block(id: u32, range: Range) -> MirBasicBlock {
+        MirBasicBlock {
+            terminator: Some(MirTerminator::Drop {
+                local: local(id),
+                range,
+                state: DropState::Dropped,
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn table_extend_replaces_kind() {
+        let mut table = RuntimeBorrowTable::default();
+        assert_eq!(
+            table.kind_of("std::cell::RefCell::borrow_mut"),
+            Some(RuntimeBorrowKind::Mutable)
+        );
+        assert_eq!(table.kind_of("my::Cell::get"), None);
+        table.extend([
+            RuntimeBorrowApi {
+                path: "my::Cell::get".into(),
+                kind: RuntimeBorrowKind::Shared,
+            },
+            RuntimeBorrowApi {
+                path: "std::sync::Arc::clone".into(),
+                kind: RuntimeBorrowKind::Mutable,
+            },
+        ]);
+        assert_eq!(
+            table.kind_of("my::Cell::get"),
+            Some(RuntimeBorrowKind::Shared)
+        );
+        assert_eq!(
+            table.kind_of("std::sync::Arc::clone"),
+            Some(RuntimeBorrowKind::Mutable)
+        );
+    }
+
+    #[test]
+    fn overlapping_mutable_borrows_conflict() {
+        // let a = c.borrow_mut(); let b = c.borrow(); drop(a); drop(b);
+        let mut blocks = vec![
+            borrow_call(2, 3, "std::cell::RefCell::borrow_mut", r(10, 24)),
+            borrow_call(4, 5, "std::cell::RefCell::borrow", r(30, 40)),
+            drop_block(2, r(50, 51)),
+            drop_block(4, r(60, 61)),
+        ];
+        for (index, bb) in blocks.iter_mut().enumerate().take(3) {
+            bb.successors = vec![u32::try_from(index).unwrap() + 1];
+        }
+        let func = Function {
+            decls: vec![guard_decl(2, r(50, 51)), guard_decl(4, r(60, 61))],
+            basic_blocks: blocks,
+            ..Default::default()
+        };
+        let borrows = runtime_borrows(&func, &RuntimeBorrowTable::default());
+        assert_eq!(borrows.len(), 2);
+        assert!(borrows.iter().all(|borrow| borrow.cell == local(1)));
+        assert_eq!(borrows[0].holds, vec![r(10, 51)]);
+
+        let found = conflicts(&borrows);
+        assert_eq!(found.len(), 1, "{found:?}");
+        assert_eq!(found[0].call, r(30, 40));
+        assert_eq!(found[0].held, r(10, 24));
+        assert_eq!(
+            failure(&found[0].api, found[0].kind),
+            "panics with `BorrowError`"
+        );
+    }
+
+    #[test]
+    fn try_borrows_and_cell_accesses_do_not_conflict() {
+        // let a = c.try_borrow_mut()?; let b = c.try_borrow(); c2.set(1);
+        let mut blocks = vec![
+            borrow_call(2, 3, "std::cell::RefCell::try_borrow_mut", r(10, 24)),
+            borrow_call(4, 5, "std::cell::RefCell::try_borrow", r(30, 40)),
+            borrow_call(6, 7, "std::cell::Cell::set", r(42, 48)),
+            drop_block(2, r(50, 51)),
+            drop_block(4, r(60, 61)),
+        ];
+        for (index, bb) in blocks.iter_mut().enumerate().take(4) {
+            bb.successors = vec![u32::try_from(index).unwrap() + 1];
+        }
+        let func = Function {
+            decls: vec![guard_decl(2, r(50, 51)), guard_decl(4, r(60, 61))],
+            basic_blocks: blocks,
+            ..Default::default()
+        };
+        let borrows = runtime_borrows(&func, &RuntimeBorrowTable::default());
+        assert_eq!(borrows.len(), 2, "`Cell::set` is no runtime borrow");
+        assert!(conflicts(&borrows).is_empty());
+
+        let accesses = cell_accesses(&func);
+        assert_eq!(accesses.len(), 1);
+        assert_eq!(accesses[0].cell, local(1));
+        assert_eq!(accesses[0].call, r(42, 48));
+    }
+
+    #[test]
+    fn sequential_borrows_do_not_conflict() {
+        let mut blocks = vec![
+            borrow_call(2, 3, "std::cell::RefCell::borrow_mut", r(10, 24)),
+            drop_block(2, r(25, 26)),
+            borrow_call(4, 5, "std::cell::RefCell::borrow_mut", r(30, 44)),
+            drop_block(4, r(45, 46)),
+        ];
+        for (index, bb) in blocks.iter_mut().enumerate().take(3) {
+            bb.successors = vec![u32::try_from(index).unwrap() + 1];
+        }
+        let func = Function {
+            decls: vec![guard_decl(2, r(25, 26)), guard_decl(4, r(45, 46))],
+            basic_blocks: blocks,
+            ..Default::default()
+        };
+        let borrows = runtime_borrows(&func, &RuntimeBorrowTable::default());
+        assert_eq!(borrows.len(), 2);
+        assert!(conflicts(&borrows).is_empty());
+    }
+}
@@ -0,0 +1,410 @@
+use std::{
+    collections::{BTreeMap, HashSet, VecDeque},
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use serde::Deserialize;
+
+/// File describing the crates of a workspace that is not built with cargo,
+/// as read by rust-analyzer
+pub const FILE_NAME: &str = "rust-project.json";
+
+/// Contents of a `rust-project.json` file
+#[derive(Deserialize, Clone, Debug)]
+pub struct RustProject {
+    pub crates: Vec<ProjectCrate>,
+    /// Directory of the file, which relative paths are resolved against
+    #[serde(skip)]
+    pub root: PathBuf,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct ProjectCrate {
+    pub display_name: Option<String>,
+    pub root_module: PathBuf,
+    pub edition: String,
+    #[serde(default)]
+    pub deps: Vec<ProjectDep>,
+    #[serde(default = "default_member")]
+    pub is_workspace_member: bool,
+    /// Configuration options in the syntax of `--cfg`, e.g. `feature="std"`
+    #[serde(default)]
+    pub cfg: Vec<String>,
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    #[serde(default)]
+    pub is_proc_macro: bool,
+    /// Compiled procedural macro, which crates depending on it load
+    pub proc_macro_dylib_path: Option<PathBuf>,
+    pub target: Option<String>,
+    /// Target of the build system the crate comes from
+    pub build: Option<ProjectBuild>,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct ProjectBuild {
+    /// `bin`, `lib` or `test`
+    pub target_kind: String,
+}
+
+const fn default_member() -> bool {
+    true
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct ProjectDep {
+    /// Index of the crate in [`RustProject::crates`]
+    #[serde(rename = "crate")]
+    pub krate: usize,
+    /// Name of the crate in the depending crate
+    pub name: String,
+}
+
+impl RustProject {
+    /// Read the file in `dir` or in the closest of its ancestors having one
+    #[must_use]
+    pub fn discover(dir: &Path) -> Option<Self> {
+        let path = dir
+            .ancestors()
+            .map(|dir| dir.join(FILE_NAME))
+            .find(|path| path.is_file())?;
+        let text = fs::read_to_string(&path).ok()?;
+        match serde_json::from_str::<Self>(&text) {
+            Ok(mut project) => {
+                project.root = path.parent()?.to_path_buf();
+                Some(project)
+            }
+            Err(e) => {
+                log::warn!("invalid {}: {e}", path.display());
+                None
+            }
+        }
+    }
+
+    fn path(&self, path: &Path) -> PathBuf {
+        self.root.join(path)
+    }
+
+    /// Target directory: `CARGO_TARGET_DIR` of the configured environment
+    /// `env` or of this process, or `target` next to the file
+    #[must_use]
+    pub fn target_dir(&self, env: &BTreeMap<String, String>) -> PathBuf {
+        env.get("CARGO_TARGET_DIR")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("CARGO_TARGET_DIR").map(PathBuf::from))
+            .map_or_else(|| self.root.join("target"), |dir| self.path(&dir))
+    }
+
+    /// Whether crate `index` is a binary: its build target says so or, with
+    /// no build target, no crate depends on it and its root module is a
+    /// `main.rs`
+    fn is_bin(&self, index: usize) -> bool {
+        let krate = &self.crates[index];
+        if let Some(build) = &krate.build {
+            return build.target_kind == "bin";
+        }
+        !krate.is_proc_macro
+            && !self
+                .crates
+                .iter()
+                .any(|other| other.deps.iter().any(|dep| dep.krate == index))
+            && krate
+                .root_module
+                .file_name()
+                .is_some_and(|name| name == "main.rs")
+    }
+
+    /// Indices of the crates, each after its dependencies
+    #[must_use]
+    pub fn build_order(&self) -> Vec<usize> {
+        let mut missing: Vec<usize> = self.crates.iter().map(|krate| krate.deps.len()).collect();
+        let mut dependents = vec![Vec::new(); self.crates.len()];
+        for (index, krate) in self.crates.iter().enumerate() {
+            for dep in &krate.deps {
+                match dependents.get_mut(dep.krate) {
+                    Some(dependents) => dependents.push(index),
+                    None => missing[index] -= 1,
+                }
+            }
+        }
+
+        let mut ready: VecDeque<usize> = (0..self.crates.len())
+            .filter(|&index| missing[index] == 0)
+            .collect();
+        let mut order = Vec::with_capacity(self.crates.len());
+        while let Some(index) = ready.pop_front() {
+            order.push(index);
+            for &dependent in &dependents[index] {
+                missing[dependent] -= 1;
+                if missing[dependent] == 0 {
+                    ready.push_back(dependent);
+                }
+            }
+        }
+        if order.len() < self.crates.len() {
+            log::warn!("dependency cycle in {FILE_NAME}");
+            order.extend((0..self.crates.len()).filter(|&index| 0 < missing[index]));
+        }
+        order
+    }
+
+    /// Crate with the innermost root module directory containing `file`
+    #[must_use]
+    pub fn owning_crate(&self, file: &Path) -> Option<usize> {
+        (0..self.crates.len())
+            .filter_map(|index| {
+                let root = self.path(&self.crates[index].root_module);
+                let dir = root.parent()?.to_path_buf();
+                file.starts_with(&dir).then_some((index, dir))
+            })
+            .max_by_key(|(_, dir)| dir.as_os_str().len())
+            .map(|(index, _)| index)
+    }
+
+    /// Crate `index` and the crates it depends on, directly or indirectly
+    #[must_use]
+    pub fn with_dependencies(&self, index: usize) -> HashSet<usize> {
+        let mut crates = HashSet::from([index]);
+        let mut stack = vec![index];
+        while let Some(index) = stack.pop() {
+            for dep in self
+                .crates
+                .get(index)
+                .into_iter()
+                .flat_map(|krate| &krate.deps)
+            {
+                if crates.insert(dep.krate) {
+                    stack.push(dep.krate);
+                }
+            }
+        }
+        crates
+    }
+
+    /// Name of crate `index` in the compiler
+    #[must_use]
+    pub fn crate_name(&self, index: usize) -> String {
+        let krate = &self.crates[index];
+        krate
+            .display_name
+            .clone()
+            .or_else(|| {
+                self.crates
+                    .iter()
+                    .flat_map(|krate| &krate.deps)
+                    .find(|dep| dep.krate == index)
+                    .map(|dep| dep.name.clone())
+            })
+            .unwrap_or_else(|| format!("crate{index}"))
+            .replace('-', "_")
+    }
+
+    /// Whether crate `index` is compiled: procedural macros only need to be
+    /// if they are analyzed, since other crates load their compiled library
+    #[must_use]
+    pub fn is_compiled(&self, index: usize) -> bool {
+        let krate = &self.crates[index];
+        krate.is_workspace_member || !krate.is_proc_macro
+    }
+
+    /// Whether crate `index` is a procedural macro that is neither compiled
+    /// nor has a compiled library, so the crates depending on it cannot be
+    #[must_use]
+    pub fn lacks_dylib(&self, index: usize) -> bool {
+        let krate = &self.crates[index];
+        !self.is_compiled(index) && krate.proc_macro_dylib_path.is_none()
+    }
+
+    /// Metadata of crate `index` emitted into `out_dir`
+    #[must_use]
+    pub fn metadata_path(&self, index: usize, out_dir: &Path) -> PathBuf {
+        out_dir.join(format!("lib{}-{index}.rmeta", self.crate_name(index)))
+    }
+
+    /// Compiler arguments that emit the metadata of crate `index` into
+    /// `out_dir`, next to that of its dependencies
+    #[must_use]
+    pub fn compiler_args(&self, index: usize, sysroot: &Path, out_dir: &Path) -> Vec<String> {
+        let krate = &self.crates[index];
+        let crate_type = if krate
+            .build
+            .as_ref()
+            .is_some_and(|build| build.target_kind == "test")
+        {
+            "--test".to_owned()
+        } else if krate.is_proc_macro {
+            "--crate-type=proc-macro".to_owned()
+        } else if self.is_bin(index) {
+            "--crate-type=bin".to_owned()
+        } else {
+            "--crate-type=lib".to_owned()
+        };
+        let mut args = vec![
+            self.path(&krate.root_module).display().to_string(),
+            format!("--crate-name={}", self.crate_name(index)),
+            format!("--edition={}", krate.edition),
+            crate_type,
+            "--emit=metadata".to_owned(),
+            format!("-o{}", self.metadata_path(index, out_dir).display()),
+            format!("-Ldependency={}", out_dir.display()),
+            "--error-format=json".to_owned(),
+            format!("--sysroot={}", sysroot.display()),
+        ];
+        for cfg in &krate.cfg {
+            args.extend(["--cfg".to_owned(), cfg.clone()]);
+        }
+        for dep in &krate.deps {
+            let Some(dep_crate) = self.crates.get(dep.krate) else {
+                continue;
+            };
+            let library = match &dep_crate.proc_macro_dylib_path {
+                Some(dylib) if dep_crate.is_proc_macro => self.path(dylib),
+                _ => self.metadata_path(dep.krate, out_dir),
+            };
+            args.extend([
+                "--extern".to_owned(),
+                format!("{}={}", dep.name, library.display()),
+            ]);
+        }
+        if krate.is_proc_macro {
+            args.extend(["--extern".to_owned(), "proc_macro".to_owned()]);
+        }
+        if let Some(target) = &krate.target {
+            args.push(format!("--target={target}"));
+        }
+        args
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+
+    fn project() -> RustProject {
+        let mut project: RustProject = serde_json::from_value(serde_json::json!({
+            "crates": [
+                {
+                    "display_name": "app",
+                    "root_module": "app/main.rs",
+                    "edition": "2021",
+                    "deps": [{ "crate": 1, "name": "util" }, { "crate": 2, "name": "derive" }],
+                    "cfg": ["feature=\"std\""],
+                    "env": { "APP_NAME": "app" }
+                },
+                {
+                    "root_module": "util/lib.rs",
+                    "edition": "2018",
+                    "is_workspace_member": false
+                },
+                {
+                    "display_name": "my-derive",
+                    "root_module": "derive/lib.rs",
+                    "edition": "2021",
+                    "is_workspace_member": false,
+                    "is_proc_macro": true,
+                    "proc_macro_dylib_path": "out/libmy_derive.so"
+                }
+            ]
+        }))
+        .unwrap();
+        project.root = PathBuf::from("/repo");
+        project
+    }
+
+    #[test]
+    fn crates_are_built_after_their_dependencies() {
+        let project = project();
+        assert_eq!(project.build_order(), [1, 2, 0]);
+        assert_eq!(project.crate_name(1), "util");
+        assert_eq!(project.crate_name(2), "my_derive");
+        assert!(project.is_compiled(1));
+        assert!(!project.is_compiled(2));
+        assert!(!project.lacks_dylib(2));
+
+        let mut project = self::project();
+        project.crates[2].proc_macro_dylib_path = None;
+        assert!(project.lacks_dylib(2));
+        project.crates[2].is_workspace_member = true;
+        assert!(!project.lacks_dylib(2));
+    }
+
+    #[test]
+    fn compiler_args_of_crate() {
+        let project = project();
+        let out = Path::new("/repo/target/owl/rust-project");
+        let args = project.compiler_args(0, Path::new("/sysroot"), out);
+        assert_eq!(
+            args,
+            [
+                "/repo/app/main.rs",
+                "--crate-name=app",
+                "--edition=2021",
+                "--crate-type=bin",
+                "--emit=metadata",
+                "-o/repo/target/owl/rust-project/libapp-0.rmeta",
+                "-Ldependency=/repo/target/owl/rust-project",
+                "--error-format=json",
+                "--sysroot=/sysroot",
+                "--cfg",
+                "feature=\"std\"",
+                "--extern",
+                "util=/repo/target/owl/rust-project/libutil-1.rmeta",
+                "--extern",
+                "derive=/repo/out/libmy_derive.so",
+            ]
+        );
+        let args = project.compiler_args(1, Path::new("/sysroot"), out);
+        assert!(args.contains(&"--crate-type=lib".to_owned()));
+    }
+
+    #[test]
+    fn crate_type_of_build_target() {
+        let mut project = project();
+        project.crates[0].build = Some(ProjectBuild {
+            target_kind: "test".to_owned(),
+        });
+        project.crates[1].build = Some(ProjectBuild {
+            target_kind: "bin".to_owned(),
+        });
+        let out = Path::new("/out");
+        let args = project.compiler_args(0, Path::new("/sysroot"), out);
+        assert_eq!(args[3], "--test");
+        let args = project.compiler_args(1, Path::new("/sysroot"), out);
+        assert_eq!(args[3], "--crate-type=bin");
+
+        // without a build target, only a `main.rs` root is a binary
+        let mut project = self::project();
+        project.crates[0].deps.clear();
+        project.crates[1].root_module = PathBuf::from("tool/src/bin.rs");
+        let args = project.compiler_args(0, Path::new("/sysroot"), out);
+        assert_eq!(args[3], "--crate-type=bin");
+        let args = project.compiler_args(1, Path::new("/sysroot"), out);
+        assert_eq!(args[3], "--crate-type=lib");
+    }
+
+    #[test]
+    fn crate_owning_file_and_its_dependencies() {
+        let project = project();
+        assert_eq!(
+            project.owning_crate(Path::new("/repo/util/lib.rs")),
+            Some(1)
+        );
+        assert_eq!(
+            project.owning_crate(Path::new("/repo/app/cli/args.rs")),
+            Some(0)
+        );
+        assert_eq!(project.owning_crate(Path::new("/elsewhere/lib.rs")), None);
+        assert_eq!(project.with_dependencies(0), HashSet::from([0, 1, 2]));
+        assert_eq!(project.with_dependencies(1), HashSet::from([1]));
+    }
+
+    #[test]
+    fn target_dir_of_environment() {
+        let project = project();
+        let env = BTreeMap::from([("CARGO_TARGET_DIR".to_owned(), "build".to_owned())]);
+        assert_eq!(project.target_dir(&env), Path::new("/repo/build"));
+    }
+}
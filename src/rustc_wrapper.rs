@@ -1,10 +1,11 @@
 use std::{
     collections::HashMap,
-    env, error, fmt,
+    env, error, fmt, fs,
     panic::{AssertUnwindSafe, catch_unwind},
-    path::Path,
+    path::{Path, PathBuf},
     sync::{LazyLock, Mutex, atomic::AtomicBool},
     thread,
+    time::Duration,
 };
 
 use rustc_hir::def_id::{LOCAL_CRATE, LocalDefId};
@@ -16,12 +17,16 @@ use tokio::{
     runtime::{Builder, Runtime},
     sync::mpsc,
     task::JoinSet,
+    time,
 };
+use tokio_util::sync::CancellationToken;
 
 use crate::{
     mir_analysis::{AnalyzeResult, MirAnalyzer, MirAnalyzerInitResult},
     mir_cache,
-    models::{Crate, File, Workspace},
+    mir_polonius::PoloniusOptions,
+    models::{AnalysisPrecision, Crate, CrateFunctions, File, Function, MirOriginGraph, Workspace},
+    toolchain,
 };
 
 #[derive(Debug)]
@@ -44,6 +49,9 @@ impl error::Error for AnalysisError {}
 pub struct AnalysisHandle {
     pub results: mpsc::UnboundedReceiver<Workspace>,
     pub thread: thread::JoinHandle<Result<i32, AnalysisError>>,
+    /// Stops the analyses that have not finished yet; the compilation itself
+    /// still runs to the end
+    pub cancel: CancellationToken,
 }
 
 #[must_use]
@@ -51,9 +59,27 @@ pub fn run_as_rustc_wrapper() -> i32 {
     run_compiler(&env::args().collect::<Vec<_>>())
 }
 
+/// Analyze `file` in a compiler thread of this process, checking borrows
+/// with `polonius`
+#[cfg(test)]
+#[must_use]
+pub fn spawn_analysis(file: &Path, sysroot: &Path, polonius: PoloniusOptions) -> AnalysisHandle {
+    spawn_analysis_with_origin_graph(file, sysroot, polonius, None)
+}
+
+/// Analyze `file` in a compiler thread of this process, checking borrows
+/// with `polonius` and computing the origin subset graph of the function
+/// called `origin_graph`
 #[must_use]
-pub fn spawn_analysis(file: &Path, sysroot: &Path) -> AnalysisHandle {
+pub fn spawn_analysis_with_origin_graph(
+    file: &Path,
+    sysroot: &Path,
+    polonius: PoloniusOptions,
+    origin_graph: Option<String>,
+) -> AnalysisHandle {
     let (sender, receiver) = mpsc::unbounded_channel();
+    let cancel = CancellationToken::new();
+    let cancel_c = cancel.clone();
 
     let output_file = NamedTempFile::new().expect("Failed to create temp file for compiler output");
     let output_path = output_file.path().to_string_lossy().to_string();
@@ -73,6 +99,9 @@ pub fn spawn_analysis(file: &Path, sysroot: &Path) -> AnalysisHandle {
         .spawn(move || {
             let _output_guard = output_file;
             *RESULT_SENDER.lock().unwrap() = Some(sender);
+            *POLONIUS_OPTIONS.lock().unwrap() = polonius;
+            *ORIGIN_GRAPH.lock().unwrap() = origin_graph;
+            *CANCEL.lock().unwrap() = cancel_c;
             let result = catch_unwind(AssertUnwindSafe(|| run_compiler(&args)));
             *RESULT_SENDER.lock().unwrap() = None;
 
@@ -89,23 +118,49 @@ pub fn spawn_analysis(file: &Path, sysroot: &Path) -> AnalysisHandle {
     AnalysisHandle {
         results: receiver,
         thread,
+        cancel,
     }
 }
 
 static ATOMIC_TRUE: AtomicBool = AtomicBool::new(true);
-static TASKS: LazyLock<Mutex<JoinSet<AnalyzeResult>>> =
+static TASKS: LazyLock<Mutex<JoinSet<Option<AnalyzeResult>>>> =
     LazyLock::new(|| Mutex::new(JoinSet::new()));
 static RESULT_SENDER: LazyLock<Mutex<Option<mpsc::UnboundedSender<Workspace>>>> =
     LazyLock::new(|| Mutex::new(None));
+static POLONIUS_OPTIONS: LazyLock<Mutex<PoloniusOptions>> =
+    LazyLock::new(|| Mutex::new(PoloniusOptions::from_env()));
+static ORIGIN_GRAPH: LazyLock<Mutex<Option<String>>> =
+    LazyLock::new(|| Mutex::new(env::var(toolchain::ORIGIN_GRAPH_ENV).ok()));
+static CANCEL: LazyLock<Mutex<CancellationToken>> =
+    LazyLock::new(|| Mutex::new(CancellationToken::new()));
+static EXCLUDED: LazyLock<Vec<PathBuf>> = LazyLock::new(|| {
+    let Ok(paths) = env::var(toolchain::EXCLUDE_ENV) else {
+        return Vec::new();
+    };
+    serde_json::from_str::<Vec<PathBuf>>(&paths)
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|path| path.canonicalize().ok())
+        .collect()
+});
 
 static RUNTIME: LazyLock<Runtime> = LazyLock::new(|| {
-    let worker_threads = thread::available_parallelism()
-        .map(|n| (n.get() / 2).clamp(2, 8))
-        .unwrap_or(4);
+    let worker_threads = env::var(toolchain::THREADS_ENV)
+        .ok()
+        .and_then(|threads| threads.parse().ok())
+        .filter(|&threads| 0 < threads)
+        .unwrap_or_else(|| {
+            thread::available_parallelism()
+                .map(|n| (n.get() / 2).clamp(2, 8))
+                .unwrap_or(4)
+        });
 
+    // borrow checks that time out keep their blocking thread until they
+    // finish, so at most as many of them run as there are workers
     Builder::new_multi_thread()
         .enable_all()
         .worker_threads(worker_threads)
+        .max_blocking_threads(worker_threads)
         .thread_stack_size(128 * 1024 * 1024)
         .build()
         .unwrap()
@@ -131,11 +186,34 @@ fn run_compiler(args: &[String]) -> i32 {
         });
     }
 
+    watch_run_file();
     rustc_driver::catch_with_exit_code(|| {
         rustc_driver::run_compiler(&args, &mut AnalyzerCallback);
     })
 }
 
+/// Cancel the analyses of this process once the run file set by the language
+/// server no longer holds the id of the run this compiler belongs to, i.e.
+/// once the run was cancelled or superseded by a new one
+fn watch_run_file() {
+    let (Ok(path), Ok(run_id)) = (
+        env::var(toolchain::RUN_FILE_ENV),
+        env::var(toolchain::RUN_ID_ENV),
+    ) else {
+        return;
+    };
+    let cancel = CANCEL.lock().unwrap().clone();
+    RUNTIME.spawn(async move {
+        while !cancel.is_cancelled() {
+            if fs::read_to_string(&path).ok().as_deref() != Some(run_id.as_str()) {
+                log::info!("analysis run {run_id} is stale; cancel remaining analyses");
+                cancel.cancel();
+            }
+            time::sleep(Duration::from_millis(100)).await;
+        }
+    });
+}
+
 struct PassthroughCallback;
 
 impl rustc_driver::Callbacks for PassthroughCallback {}
@@ -157,13 +235,19 @@ impl rustc_driver::Callbacks for AnalyzerCallback {
         _compiler: &interface::Compiler,
         tcx: TyCtxt<'_>,
     ) -> rustc_driver::Compilation {
-        let result = rustc_driver::catch_fatal_errors(|| tcx.analysis(()));
+        announce_functions(tcx);
+        let result = rustc_driver::catch_fatal_errors(|| borrowck_active_file(tcx))
+            .and_then(|()| rustc_driver::catch_fatal_errors(|| tcx.analysis(())));
 
         #[allow(clippy::await_holding_lock, reason = "lock duration is minimal")]
         RUNTIME.block_on(async move {
+            // cancelled and timed out analyses finish without a result, the
+            // finished ones are still sent and cached
             while let Some(Ok(result)) = { TASKS.lock().unwrap().join_next().await } {
                 log::info!("one task joined");
-                send_result(tcx, result);
+                if let Some(result) = result {
+                    send_result(tcx, result);
+                }
             }
             if let Some(cache) = mir_cache::CACHE.lock().unwrap().as_ref() {
                 mir_cache::write_cache(&tcx.crate_name(LOCAL_CRATE).to_string(), cache);
@@ -178,6 +262,54 @@ impl rustc_driver::Callbacks for AnalyzerCallback {
     }
 }
 
+/// Tell the language server how many function results of this crate follow
+fn announce_functions(tcx: TyCtxt<'_>) {
+    // results of a compiler thread are counted by its receiver
+    if RESULT_SENDER.lock().unwrap().is_some() {
+        return;
+    }
+    let announcement = CrateFunctions {
+        name: tcx.crate_name(LOCAL_CRATE).to_string(),
+        functions: tcx.hir_body_owners().count(),
+    };
+    println!("{}", serde_json::to_string(&announcement).unwrap());
+}
+
+/// Borrow check the bodies in the file open in the editor before the rest of
+/// the crate, so that their analyses are started first
+fn borrowck_active_file(tcx: TyCtxt<'_>) {
+    let Some(active) = env::var_os(toolchain::ACTIVE_FILE_ENV)
+        .and_then(|path| Path::new(&path).canonicalize().ok())
+    else {
+        return;
+    };
+    let source_map = tcx.sess.source_map();
+    for def_id in tcx.hir_body_owners() {
+        if tcx.is_typeck_child(def_id.to_def_id()) {
+            continue;
+        }
+        let file = source_map.span_to_filename(tcx.def_span(def_id));
+        if file
+            .into_local_path()
+            .and_then(|path| path.canonicalize().ok())
+            .is_some_and(|path| path == active)
+        {
+            let _ = tcx.mir_borrowck(def_id);
+        }
+    }
+}
+
+/// Whether the body of `def_id` is in a path excluded from the analysis
+fn is_excluded(tcx: TyCtxt<'_>, def_id: LocalDefId) -> bool {
+    if EXCLUDED.is_empty() {
+        return false;
+    }
+    let file = tcx.sess.source_map().span_to_filename(tcx.def_span(def_id));
+    file.into_local_path()
+        .and_then(|path| path.canonicalize().ok())
+        .is_some_and(|path| EXCLUDED.iter().any(|excluded| path.starts_with(excluded)))
+}
+
 fn override_queries(_session: &rustc_session::Session, local: &mut Providers) {
     local.mir_borrowck = mir_borrowck;
 }
@@ -186,21 +318,44 @@ fn override_queries(_session: &rustc_session::Session, local: &mut Providers) {
 fn mir_borrowck(tcx: TyCtxt<'_>, def_id: LocalDefId) -> queries::mir_borrowck::ProvidedValue<'_> {
     log::debug!("start borrowck of {def_id:?}");
 
-    let analyzer = MirAnalyzer::init(tcx, def_id);
+    let polonius = *POLONIUS_OPTIONS.lock().unwrap();
+    let origin_graph = ORIGIN_GRAPH.lock().unwrap().clone();
+    let cancel = CANCEL.lock().unwrap().clone();
+    let analyzer = (!cancel.is_cancelled() && !is_excluded(tcx, def_id))
+        .then(|| MirAnalyzer::init(tcx, def_id, polonius, origin_graph.as_deref()));
 
     {
         let mut tasks = TASKS.lock().unwrap();
         match analyzer {
-            MirAnalyzerInitResult::Cached(cached) => send_result(tcx, cached),
-            MirAnalyzerInitResult::Analyzer(analyzer) => {
-                tasks.spawn_on(async move { analyzer.await.analyze() }, RUNTIME.handle());
+            Some(MirAnalyzerInitResult::Cached(cached)) => send_result(tcx, *cached),
+            Some(MirAnalyzerInitResult::Analyzer(analyzer)) => {
+                // past the deadline the analysis is dropped, while a borrow
+                // check it has started still runs to its end
+                let deadline = Duration::from_millis(polonius.deadline_ms);
+                tasks.spawn_on(
+                    async move {
+                        tokio::select! {
+                            () = cancel.cancelled() => None,
+                            done = time::timeout(deadline, analyzer) => {
+                                if done.is_err() {
+                                    log::warn!("analysis of {def_id:?} exceeded the deadline");
+                                }
+                                done.ok().map(MirAnalyzer::analyze)
+                            }
+                        }
+                    },
+                    RUNTIME.handle(),
+                );
             }
+            None => log::debug!("analysis cancelled or excluded; skip {def_id:?}"),
         }
 
         log::debug!("there are {} tasks", tasks.len());
         while let Some(Ok(result)) = tasks.try_join_next() {
             log::debug!("one task joined");
-            send_result(tcx, result);
+            if let Some(result) = result {
+                send_result(tcx, result);
+            }
         }
     }
 
@@ -214,11 +369,19 @@ fn mir_borrowck(tcx: TyCtxt<'_>, def_id: LocalDefId) -> queries::mir_borrowck::P
 }
 
 fn send_result(tcx: TyCtxt<'_>, analyzed: AnalyzeResult) {
-    if let Some(cache) = mir_cache::CACHE.lock().unwrap().as_mut() {
+    // functions analyzed within limits are analyzed again with other limits
+    if analyzed.analyzed.precision == AnalysisPrecision::Full
+        && let Some(cache) = mir_cache::CACHE.lock().unwrap().as_mut()
+    {
+        // the origin graph is computed again for each export
+        let function = Function {
+            origin_graph: MirOriginGraph::default(),
+            ..analyzed.analyzed.clone()
+        };
         cache.insert_cache(
             analyzed.file_hash.clone(),
             analyzed.mir_hash.clone(),
-            analyzed.analyzed.clone(),
+            function,
         );
     }
 
@@ -240,14 +403,28 @@ fn send_result(tcx: TyCtxt<'_>, analyzed: AnalyzeResult) {
 
 #[cfg(test)]
 mod tests {
-    use std::{collections::HashMap, io::Write, sync::Mutex};
+    use std::{
+        collections::HashMap,
+        fs::File,
+        io::Write,
+        path::PathBuf,
+        sync::{Mutex, MutexGuard, PoisonError},
+    };
 
     use tempfile::TempDir;
+    use tokio::runtime::Builder;
 
-    use super::{AnalysisHandle, spawn_analysis};
+    use super::{AnalysisHandle, spawn_analysis, spawn_analysis_with_origin_graph};
     use crate::{
-        lsp_decoration::{CalcDecos, Deco, SelectLocal},
-        models::{FnLocal, Function, Loc, Workspace},
+        graph_export::{self, GraphFormat},
+        lints::{self, LintOptions},
+        lsp_decoration::{self as decoration, CalcDecos, Deco, SelectLifetime, SelectLocal},
+        lsp_inlay_hint::{CalcInlayHints, drop_order},
+        mir_polonius::{PoloniusAlgorithm, PoloniusOptions},
+        models::{
+            AnalysisPrecision, BindingMode, DropState, FnLocal, Function, Loc, MirDecl, MirRval,
+            MirStatement, ParamUse, Range, TempScope, Workspace,
+        },
         range_ops::mir_visit,
         toolchain,
     };
@@ -256,24 +433,21 @@ mod tests {
     // RESULT_SENDER.
     static TEST_LOCK: Mutex<()> = Mutex::new(());
 
-    fn acquire_lock() -> std::sync::MutexGuard<'static, ()> {
-        TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner())
+    fn acquire_lock() -> MutexGuard<'static, ()> {
+        TEST_LOCK.lock().unwrap_or_else(PoisonError::into_inner)
     }
 
-    fn write_temp_rs(code: &str) -> (TempDir, std::path::PathBuf) {
+    fn write_temp_rs(code: &str) -> (TempDir, PathBuf) {
         let dir = TempDir::new().expect("create temp dir");
         let path = dir.path().join("test_input.rs");
-        let mut f = std::fs::File::create(&path).expect("create temp .rs file");
+        let mut f = File::create(&path).expect("create temp .rs file");
         f.write_all(code.as_bytes()).expect("write temp source");
         f.flush().expect("flush temp source");
         (dir, path)
     }
 
     fn collect_workspace(handle: AnalysisHandle) -> Workspace {
-        let rt = tokio::runtime::Builder::new_current_thread()
-            .enable_all()
-            .build()
-            .unwrap();
+        let rt = Builder::new_current_thread().enable_all().build().unwrap();
         let mut merged = Workspace(HashMap::new());
         rt.block_on(async {
             let mut rx = handle.results;
@@ -294,6 +468,14 @@ mod tests {
             .expect("workspace should contain at least one function")
     }
 
+    fn find_function<'a>(ws: &'a Workspace, name: &str) -> &'a Function {
+        ws.0.values()
+            .flat_map(|krate| krate.0.values())
+            .flat_map(|file| &file.items)
+            .find(|func| func.name == name)
+            .unwrap_or_else(|| panic!("workspace should contain function `{name}`"))
+    }
+
     fn run_decos_for_cursor(func: &Function, cursor: u32) -> Vec<Deco> {
         let mut sel = SelectLocal::new(Loc::from(cursor));
         mir_visit(func, &mut sel);
@@ -319,7 +501,7 @@ pub fn example() {
 "#,
         );
         let sysroot = toolchain::get_sysroot();
-        let handle = spawn_analysis(&path, &sysroot);
+        let handle = spawn_analysis(&path, &sysroot, PoloniusOptions::default());
         let ws = collect_workspace(handle);
 
         let func = find_first_function(&ws);
@@ -327,7 +509,7 @@ pub fn example() {
 
         // Find the user decl for `s`
         let s_local: Option<FnLocal> = func.decls.iter().find_map(|d| match d {
-            crate::models::MirDecl::User { local, name, .. } if name == "s" => Some(*local),
+            MirDecl::User { local, name, .. } if name == "s" => Some(*local),
             _ => None,
         });
         assert!(s_local.is_some(), "Should find a user decl named 's'");
@@ -364,12 +546,12 @@ pub fn example() {
 "#,
         );
         let sysroot = toolchain::get_sysroot();
-        let handle = spawn_analysis(&path, &sysroot);
+        let handle = spawn_analysis(&path, &sysroot, PoloniusOptions::default());
         let ws = collect_workspace(handle);
 
         let func = find_first_function(&ws);
         let x_local = func.decls.iter().find_map(|d| match d {
-            crate::models::MirDecl::User { local, name, .. } if name == "x" => Some(*local),
+            MirDecl::User { local, name, .. } if name == "x" => Some(*local),
             _ => None,
         });
         assert!(x_local.is_some(), "Should find a user decl named 'x'");
@@ -402,12 +584,12 @@ pub fn example() {
 "#,
         );
         let sysroot = toolchain::get_sysroot();
-        let handle = spawn_analysis(&path, &sysroot);
+        let handle = spawn_analysis(&path, &sysroot, PoloniusOptions::default());
         let ws = collect_workspace(handle);
 
         let func = find_first_function(&ws);
         let x_local = func.decls.iter().find_map(|d| match d {
-            crate::models::MirDecl::User { local, name, .. } if name == "x" => Some(*local),
+            MirDecl::User { local, name, .. } if name == "x" => Some(*local),
             _ => None,
         });
         assert!(x_local.is_some(), "Should find user decl 'x'");
@@ -435,12 +617,12 @@ pub fn example() {
 "#,
         );
         let sysroot = toolchain::get_sysroot();
-        let handle = spawn_analysis(&path, &sysroot);
+        let handle = spawn_analysis(&path, &sysroot, PoloniusOptions::default());
         let ws = collect_workspace(handle);
 
         let func = find_first_function(&ws);
         let s_local = func.decls.iter().find_map(|d| match d {
-            crate::models::MirDecl::User { local, name, .. } if name == "s" => Some(*local),
+            MirDecl::User { local, name, .. } if name == "s" => Some(*local),
             _ => None,
         });
         assert!(s_local.is_some(), "Should find user decl 's'");
@@ -462,14 +644,14 @@ pub fn example() {
         let code = "pub fn example() {\n    let x = 42;\n}\n";
         let (_dir, path) = write_temp_rs(code);
         let sysroot = toolchain::get_sysroot();
-        let handle = spawn_analysis(&path, &sysroot);
+        let handle = spawn_analysis(&path, &sysroot, PoloniusOptions::default());
         let ws = collect_workspace(handle);
 
         let func = find_first_function(&ws);
 
         // Find the span of `x` from its decl
         let x_span = func.decls.iter().find_map(|d| match d {
-            crate::models::MirDecl::User { name, span, .. } if name == "x" => Some(*span),
+            MirDecl::User { name, span, .. } if name == "x" => Some(*span),
             _ => None,
         });
         assert!(x_span.is_some(), "Should find user decl 'x' with a span");
@@ -485,4 +667,1224 @@ pub fn example() {
             "SelectLocal + CalcDecos should produce decorations for cursor at 'x'"
         );
     }
+
+    #[test]
+    fn integration_named_lifetime_param() {
+        let _guard = acquire_lock();
+        let code = r#"
+pub fn longest<'a>(x: &'a str, y: &'a str) -> &'a str {
+    let r = if x.len() > y.len() { x } else { y };
+    r
+}
+"#;
+        let (_dir, path) = write_temp_rs(code);
+        let sysroot = toolchain::get_sysroot();
+        let handle = spawn_analysis(&path, &sysroot, PoloniusOptions::default());
+        let ws = collect_workspace(handle);
+
+        let func = find_first_function(&ws);
+        let param = func
+            .lifetimes
+            .iter()
+            .find(|param| param.name == "'a")
+            .expect("Should find lifetime parameter 'a");
+        // `<'a>`, and three occurrences in the signature
+        assert_eq!(param.spans.len(), 4, "Unexpected spans: {:?}", param.spans);
+
+        let local_of = |var: &str| {
+            func.decls.iter().find_map(|d| match d {
+                MirDecl::User { local, name, .. } if name == var => Some(*local),
+                _ => None,
+            })
+        };
+        for var in ["x", "y", "r"] {
+            let local = local_of(var).expect("user decl should exist");
+            assert!(
+                param.locals.iter().any(|related| related.local == local),
+                "`{var}` should be related to 'a, got: {:?}",
+                param.locals
+            );
+        }
+
+        let cursor = u32::try_from(code.find("'a>").unwrap()).unwrap();
+        let mut sel = SelectLifetime::new(Loc::from(cursor));
+        mir_visit(func, &mut sel);
+        let (fn_ids, name) = sel.selected().expect("cursor should select 'a");
+        let mut calc = CalcDecos::new([]).with_lifetime(fn_ids, name);
+        mir_visit(func, &mut calc);
+        let decos = calc.decorations();
+        assert!(
+            decos
+                .iter()
+                .any(|d| matches!(d, Deco::LifetimeParam { .. })),
+            "Expected LifetimeParam decos, got: {decos:?}"
+        );
+    }
+
+    #[test]
+    fn integration_impl_lifetime_param() {
+        let _guard = acquire_lock();
+        let code = r#"
+pub struct Parser<'a> {
+    input: &'a str,
+}
+
+impl<'a> Parser<'a> {
+    pub fn rest(&self) -> &'a str {
+        let input = self.input;
+        input
+    }
+}
+"#;
+        let (_dir, path) = write_temp_rs(code);
+        let sysroot = toolchain::get_sysroot();
+        let handle = spawn_analysis(&path, &sysroot, PoloniusOptions::default());
+        let ws = collect_workspace(handle);
+
+        let func = find_first_function(&ws);
+        let param = func
+            .lifetimes
+            .iter()
+            .find(|param| param.name == "'a")
+            .expect("Should find impl lifetime parameter 'a");
+        let input = func
+            .decls
+            .iter()
+            .find_map(|d| match d {
+                MirDecl::User { local, name, .. } if name == "input" => Some(*local),
+                _ => None,
+            })
+            .expect("user decl `input` should exist");
+        assert!(
+            param.locals.iter().any(|related| related.local == input),
+            "`input` should be related to 'a, got: {:?}",
+            param.locals
+        );
+    }
+
+    #[test]
+    fn integration_impl_lifetime_shared_by_methods() {
+        let _guard = acquire_lock();
+        let code = r#"
+pub struct Parser<'a> {
+    input: &'a str,
+}
+
+impl<'a> Parser<'a> {
+    pub fn rest(&self) -> &'a str {
+        let input = self.input;
+        input
+    }
+
+    pub fn skip(&self, n: [u8; 'a' as usize]) -> &'a str {
+        let tail = &self.input[n.len()..];
+        tail
+    }
+}
+"#;
+        let (_dir, path) = write_temp_rs(code);
+        let sysroot = toolchain::get_sysroot();
+        let handle = spawn_analysis(&path, &sysroot, PoloniusOptions::default());
+        let ws = collect_workspace(handle);
+        let functions: Vec<&Function> =
+            ws.0.values()
+                .flat_map(|krate| krate.0.values())
+                .flat_map(|file| &file.items)
+                .collect();
+        let method = |name: &str| {
+            *functions
+                .iter()
+                .find(|func| graph_export::is_named(&func.name, name))
+                .unwrap_or_else(|| panic!("workspace should contain method `{name}`"))
+        };
+
+        for name in ["rest", "skip"] {
+            let param = method(name)
+                .lifetimes
+                .iter()
+                .find(|param| param.name == "'a")
+                .expect("Should find impl lifetime parameter 'a");
+            // `impl<'a>`, `Parser<'a>` and the return type, but not the
+            // char literal `'a'`
+            assert_eq!(
+                param.spans.len(),
+                3,
+                "Unexpected spans of `{name}`: {:?}",
+                param.spans
+            );
+        }
+
+        let cursor = u32::try_from(code.find("'a> Parser").unwrap()).unwrap();
+        let mut sel = SelectLifetime::new(Loc::from(cursor));
+        for func in &functions {
+            mir_visit(func, &mut sel);
+        }
+        let (fn_ids, name) = sel.selected().expect("cursor should select 'a");
+        assert!(
+            fn_ids.contains(&method("rest").fn_id) && fn_ids.contains(&method("skip").fn_id),
+            "Both methods should share 'a, got: {fn_ids:?}"
+        );
+        let mut calc = CalcDecos::new([]).with_lifetime(fn_ids, name);
+        for func in &functions {
+            mir_visit(func, &mut calc);
+        }
+        let decos = calc.decorations();
+        for var in ["input", "tail"] {
+            assert!(
+                decos.iter().any(|d| matches!(
+                    d,
+                    Deco::LifetimeParam { hover_text, .. } if hover_text.starts_with(&format!("`{var}`"))
+                )),
+                "`{var}` should be decorated, got: {decos:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn integration_origin_graph() {
+        let _guard = acquire_lock();
+        let code = r#"
+pub fn first<'a>(x: &'a Vec<i32>) -> &'a i32 {
+    let r = &x[0];
+    r
+}
+"#;
+        let (_dir, path) = write_temp_rs(code);
+        let sysroot = toolchain::get_sysroot();
+        let handle = spawn_analysis(&path, &sysroot, PoloniusOptions::default());
+        let ws = collect_workspace(handle);
+        let func = find_first_function(&ws);
+        assert!(
+            func.origin_graph.is_empty(),
+            "Graphs are only computed for exports"
+        );
+
+        let handle = spawn_analysis_with_origin_graph(
+            &path,
+            &sysroot,
+            PoloniusOptions::default(),
+            Some("first".to_owned()),
+        );
+        let ws = collect_workspace(handle);
+
+        let func = find_first_function(&ws);
+        assert_eq!(func.name, "first", "Unexpected function path");
+        assert!(func.span.is_some(), "Function span should be recorded");
+        assert!(
+            !func.origin_graph.edges.is_empty(),
+            "Expected subset edges, got: {:?}",
+            func.origin_graph
+        );
+
+        let dot = graph_export::origin_graph(func, code, GraphFormat::Dot).unwrap();
+        assert!(dot.contains(" -> "), "Expected DOT edges: {dot}");
+        assert!(dot.contains("\\nr"), "Expected origin of `r` in DOT: {dot}");
+        let mermaid = graph_export::origin_graph(func, code, GraphFormat::Mermaid).unwrap();
+        assert!(
+            mermaid.contains(" -->"),
+            "Expected Mermaid edges: {mermaid}"
+        );
+    }
+
+    #[test]
+    fn integration_cfg_successors() {
+        let _guard = acquire_lock();
+        let code = r#"
+pub fn pick(flag: bool) -> String {
+    let s = String::from("a");
+    if flag {
+        return s;
+    }
+    let t = s;
+    t
+}
+"#;
+        let (_dir, path) = write_temp_rs(code);
+        let sysroot = toolchain::get_sysroot();
+        let handle = spawn_analysis(&path, &sysroot, PoloniusOptions::default());
+        let ws = collect_workspace(handle);
+
+        let func = find_first_function(&ws);
+        let branches = func
+            .basic_blocks
+            .iter()
+            .filter(|bb| bb.successors.len() > 1)
+            .count();
+        assert!(branches > 0, "Expected a branching block: {func:?}");
+        assert!(
+            func.basic_blocks.iter().any(|bb| bb.cleanup),
+            "Expected cleanup blocks for the unwinding path"
+        );
+        for bb in &func.basic_blocks {
+            for succ in &bb.successors {
+                assert!(
+                    (*succ as usize) < func.basic_blocks.len(),
+                    "Successor {succ} out of bounds"
+                );
+            }
+        }
+
+        let dot = graph_export::cfg_graph(func, code, GraphFormat::Dot).unwrap();
+        assert!(dot.contains("bb0 -> "), "Expected edges from entry: {dot}");
+        assert!(dot.contains("moved: s"), "Expected move of `s`: {dot}");
+    }
+
+    #[test]
+    fn integration_drop_order() {
+        let _guard = acquire_lock();
+        let code = r#"
+pub struct Noisy(pub u8);
+
+impl Drop for Noisy {
+    fn drop(&mut self) {}
+}
+
+pub fn order(flag: bool) {
+    let a = Noisy(1);
+    let b = Noisy(2);
+    if flag {
+        drop(a);
+    }
+}
+"#;
+        let (_dir, path) = write_temp_rs(code);
+        let sysroot = toolchain::get_sysroot();
+        let handle = spawn_analysis(&path, &sysroot, PoloniusOptions::default());
+        let ws = collect_workspace(handle);
+
+        let func = find_function(&ws, "order");
+        let local_of = |var: &str| {
+            func.decls
+                .iter()
+                .find_map(|d| match d {
+                    MirDecl::User { local, name, .. } if name == var => Some(*local),
+                    _ => None,
+                })
+                .expect("user decl should exist")
+        };
+        let (a, b) = (local_of("a"), local_of("b"));
+        let groups = drop_order(func);
+        let scope_end = groups
+            .iter()
+            .find(|group| group.drops.iter().any(|(local, _)| *local == b))
+            .expect("`b` should be dropped at the end of the function");
+        assert_eq!(
+            scope_end.drops,
+            vec![(b, DropState::Dropped), (a, DropState::MaybeDropped)],
+            "`b` is dropped before `a`, which may have been moved"
+        );
+    }
+
+    #[test]
+    fn integration_temporary_scopes() {
+        let _guard = acquire_lock();
+        let code = r#"
+use std::sync::Mutex;
+
+pub fn guards(m: &Mutex<Vec<u8>>) -> usize {
+    let n = match m.lock().unwrap().len() {
+        0 => 0,
+        n => n,
+    };
+    let first = if let Some(x) = m.lock().unwrap().first() {
+        usize::from(*x)
+    } else {
+        0
+    };
+    let len = m.lock().unwrap().len();
+    let name = &String::from("guard");
+    n + first + len + name.len()
+}
+"#;
+        let (_dir, path) = write_temp_rs(code);
+        let sysroot = toolchain::get_sysroot();
+        let handle = spawn_analysis(&path, &sysroot, PoloniusOptions::default());
+        let ws = collect_workspace(handle);
+
+        let func = find_function(&ws, "guards");
+        // the guards themselves, not the references taken to call methods on them
+        let scopes_of = |expr: &str| {
+            let mut scopes: Vec<_> = func
+                .decls
+                .iter()
+                .filter_map(|d| match d {
+                    MirDecl::Other {
+                        drop: true,
+                        temporary: Some(temporary),
+                        ..
+                    } if temporary.expr == expr => Some((temporary.span.from(), temporary.scope)),
+                    _ => None,
+                })
+                .collect();
+            scopes.sort_by_key(|(from, _)| *from);
+            scopes
+                .into_iter()
+                .map(|(_, scope)| scope)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            scopes_of("m.lock().unwrap()"),
+            vec![
+                TempScope::MatchScrutinee,
+                TempScope::LetScrutinee,
+                TempScope::Statement
+            ],
+        );
+        assert_eq!(
+            scopes_of("String::from(\"guard\")"),
+            vec![TempScope::Extended]
+        );
+
+        let guard_start = u32::try_from(code.find("m.lock().unwrap().len() {").unwrap()).unwrap();
+        // cursor on `unwrap`
+        let decos = run_decos_for_cursor(func, guard_start + 12);
+        assert!(
+            decos.iter().any(|d| matches!(d,
+                Deco::Lifetime { hover_text, .. }
+                    if hover_text.starts_with("lifetime of temporary `m.lock().unwrap()`")
+                        && hover_text.contains("until the end of the `match`"))),
+            "the guard should be selectable and explained: {decos:?}"
+        );
+
+        let mut calc = CalcInlayHints::new();
+        mir_visit(func, &mut calc);
+        let labels: Vec<_> = calc.hints().into_iter().map(|hint| hint.label).collect();
+        assert!(
+            labels.contains(&"held until end of `match`".to_owned())
+                && labels.contains(&"held until end of `if let`".to_owned()),
+            "scrutinee guards should be flagged: {labels:?}"
+        );
+    }
+
+    #[test]
+    fn integration_guard_held_across_call_and_loop() {
+        let _guard = acquire_lock();
+        let code = r#"
+use std::cell::RefCell;
+use std::sync::Mutex;
+
+fn work() {}
+
+pub fn held(m: &Mutex<Vec<u8>>, c: &RefCell<u8>) -> usize {
+    let g = m.lock().unwrap();
+    let mut n = g.len();
+    for x in 0..3 {
+        n += x;
+    }
+    drop(g);
+    let b = c.borrow_mut();
+    work();
+    n + usize::from(*b)
+}
+
+fn total(m: &Mutex<Vec<u8>>) -> usize {
+    m.lock().unwrap().len()
+}
+
+pub fn relocks(m: &Mutex<Vec<u8>>) -> usize {
+    let g = m.lock().unwrap();
+    let n = total(m);
+    n + g.len()
+}
+
+pub fn released(m: &Mutex<Vec<u8>>) -> usize {
+    let n = m.lock().unwrap().len();
+    work();
+    n
+}
+"#;
+        let (_dir, path) = write_temp_rs(code);
+        let sysroot = toolchain::get_sysroot();
+        let handle = spawn_analysis(&path, &sysroot, PoloniusOptions::default());
+        let ws = collect_workspace(handle);
+
+        let text_of = |range: Range| {
+            &code[u32::from(range.from()) as usize..u32::from(range.until()) as usize]
+        };
+        let options = LintOptions::default();
+        let found = lints::guard_lints(find_function(&ws, "held"), &options);
+        assert_eq!(found.len(), 2, "unexpected lints: {found:?}");
+        assert!(found[0].message.starts_with("lock guard `g`"));
+        assert!(
+            found[0].message.ends_with("held across a loop and a call"),
+            "{}",
+            found[0].message
+        );
+        assert!(text_of(found[0].range).starts_with("g = m.lock()"));
+        assert!(text_of(found[0].range).contains("for x in 0..3"));
+        assert!(!text_of(found[0].range).contains("borrow_mut"));
+        assert!(found[1].message.starts_with("`RefCell` borrow `b`"));
+        assert!(
+            found[1]
+                .related
+                .iter()
+                .any(|(range, _)| text_of(*range) == "work()"),
+            "the call to `work` should be related: {:?}",
+            found[1].related
+        );
+
+        // the helper locks the mutex again and deadlocks
+        let found = lints::guard_lints(find_function(&ws, "relocks"), &options);
+        assert_eq!(found.len(), 1, "unexpected lints: {found:?}");
+        assert!(
+            found[0]
+                .related
+                .iter()
+                .any(|(range, _)| text_of(*range) == "total(m)"),
+            "the call to `total` should be related: {:?}",
+            found[0].related
+        );
+
+        let found = lints::guard_lints(find_function(&ws, "released"), &options);
+        assert!(found.is_empty(), "the guard is dropped: {found:?}");
+    }
+
+    #[test]
+    fn integration_runtime_borrow_conflict() {
+        let _guard = acquire_lock();
+        let code = r#"
+use std::cell::RefCell;
+
+pub fn conflict(c: &RefCell<Vec<u8>>) -> usize {
+    let first = c.borrow();
+    c.borrow_mut().push(1);
+    first.len()
+}
+
+pub fn sequential(c: &RefCell<Vec<u8>>) -> usize {
+    c.borrow_mut().push(1);
+    c.borrow().len()
+}
+"#;
+        let (_dir, path) = write_temp_rs(code);
+        let sysroot = toolchain::get_sysroot();
+        let handle = spawn_analysis(&path, &sysroot, PoloniusOptions::default());
+        let ws = collect_workspace(handle);
+
+        let text_of = |range: Range| {
+            &code[u32::from(range.from()) as usize..u32::from(range.until()) as usize]
+        };
+        let cursor = |func: &str| {
+            let start = code.find(&format!("fn {func}(c")).unwrap() + func.len() + 4;
+            u32::try_from(start).unwrap()
+        };
+
+        let decos = run_decos_for_cursor(find_function(&ws, "conflict"), cursor("conflict"));
+        assert!(
+            decos.iter().any(|d| matches!(
+                d,
+                Deco::DynSharedBorrow { hover_text, .. }
+                    if hover_text.contains("std::cell::RefCell::borrow`")
+            )),
+            "expected a dynamic shared borrow: {decos:?}"
+        );
+        assert!(
+            decos.iter().any(|d| matches!(d, Deco::DynMutBorrow { .. })),
+            "expected a dynamic mutable borrow: {decos:?}"
+        );
+        assert!(
+            !decos.iter().any(|d| matches!(d, Deco::Call { .. })),
+            "runtime borrows are not plain calls: {decos:?}"
+        );
+        let conflicts: Vec<_> = decos
+            .iter()
+            .filter_map(|d| match d {
+                Deco::DynBorrowConflict {
+                    range, hover_text, ..
+                } => Some((text_of(*range), hover_text)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(conflicts.len(), 1, "{decos:?}");
+        assert_eq!(conflicts[0].0, "borrow_mut()");
+        assert!(
+            conflicts[0].1.contains("BorrowMutError"),
+            "{}",
+            conflicts[0].1
+        );
+
+        let decos = run_decos_for_cursor(find_function(&ws, "sequential"), cursor("sequential"));
+        assert!(
+            !decos
+                .iter()
+                .any(|d| matches!(d, Deco::DynBorrowConflict { .. })),
+            "the first borrow ends with its statement: {decos:?}"
+        );
+    }
+
+    #[test]
+    fn integration_needless_clone() {
+        let _guard = acquire_lock();
+        let code = r#"
+fn consume(s: String) -> usize {
+    s.len()
+}
+
+pub fn needless(s: String) -> usize {
+    let t = s.clone();
+    consume(t)
+}
+
+pub fn needed(s: String) -> usize {
+    let t = s.clone();
+    consume(t) + s.len()
+}
+
+pub fn in_loop(s: String) -> usize {
+    let mut n = 0;
+    for _ in 0..2 {
+        n += consume(s.clone());
+    }
+    n
+}
+
+pub fn borrowed(s: String) -> usize {
+    let r = &s;
+    let t = s.clone();
+    consume(t) + r.len()
+}
+
+pub fn to_owned_last(s: String, v: &mut Vec<String>) {
+    v.push(s.to_owned());
+}
+
+pub fn copied(n: u32) -> u32 {
+    n.clone()
+}
+"#;
+        let (_dir, path) = write_temp_rs(code);
+        let sysroot = toolchain::get_sysroot();
+        let handle = spawn_analysis(&path, &sysroot, PoloniusOptions::default());
+        let ws = collect_workspace(handle);
+
+        let text_of = |range: Range| {
+            &code[u32::from(range.from()) as usize..u32::from(range.until()) as usize]
+        };
+        let found = lints::needless_clone_lints(find_function(&ws, "needless"));
+        assert_eq!(found.len(), 1, "{found:?}");
+        assert_eq!(text_of(found[0].range), "s.clone()");
+        assert_eq!(found[0].code, "needless-clone");
+        assert!(
+            found[0]
+                .message
+                .starts_with("`s` is not used after this `clone`")
+        );
+        assert_eq!(
+            found[0].fix,
+            Some(lints::Fix::Replace(found[0].range, "s".to_owned()))
+        );
+
+        let found = lints::needless_clone_lints(find_function(&ws, "to_owned_last"));
+        assert_eq!(found.len(), 1, "{found:?}");
+        assert_eq!(text_of(found[0].range), "s.to_owned()");
+
+        for name in ["needed", "in_loop", "borrowed", "copied"] {
+            let found = lints::needless_clone_lints(find_function(&ws, name));
+            assert!(found.is_empty(), "`{name}` needs its clone: {found:?}");
+        }
+    }
+
+    #[test]
+    fn integration_large_copies() {
+        let _guard = acquire_lock();
+        let code = r#"
+fn sum(data: [u8; 1024]) -> u32 {
+    data.iter().map(|b| u32::from(*b)).sum()
+}
+
+pub fn large(data: [u8; 1024], small: [u8; 16]) -> u32 {
+    let mut total = 0;
+    for _ in 0..4 {
+        let copy = small;
+        total += u32::from(copy[0]);
+    }
+    total + sum(data)
+}
+"#;
+        let (_dir, path) = write_temp_rs(code);
+        let sysroot = toolchain::get_sysroot();
+        let handle = spawn_analysis(&path, &sysroot, PoloniusOptions::default());
+        let ws = collect_workspace(handle);
+        let func = find_function(&ws, "large");
+
+        let size_of = |name: &str| {
+            func.decls.iter().find_map(|decl| match decl {
+                MirDecl::User {
+                    name: decl_name,
+                    size,
+                    ..
+                } if decl_name == name => *size,
+                _ => None,
+            })
+        };
+        assert_eq!(size_of("data"), Some(1024));
+        assert_eq!(size_of("small"), Some(16));
+
+        let found = lints::large_copy_lints(func, &LintOptions::default());
+        assert_eq!(found.len(), 1, "{found:?}");
+        assert!(
+            found[0].message.contains("`data`") && found[0].message.contains("1024 bytes"),
+            "{}",
+            found[0].message
+        );
+
+        let options = LintOptions {
+            large_copy_threshold: 512,
+            large_copy_in_loop_threshold: 16,
+            ..LintOptions::default()
+        };
+        let found = lints::large_copy_lints(func, &options);
+        assert!(
+            found
+                .iter()
+                .any(|lint| lint.message == "copy of `small` copies 16 bytes in a loop"),
+            "{found:?}"
+        );
+    }
+
+    #[test]
+    fn integration_held_too_long() {
+        let _guard = acquire_lock();
+        let code = r#"
+use std::sync::Mutex;
+
+fn work() -> u32 {
+    1
+}
+
+pub fn held(m: &Mutex<Vec<u32>>) -> usize {
+    let g = m.lock().unwrap();
+    let n = g.len();
+    work();
+    n
+}
+
+pub fn released(m: &Mutex<Vec<u32>>) -> usize {
+    let g = m.lock().unwrap();
+    let n = g.len();
+    drop(g);
+    work();
+    n
+}
+
+pub fn on_purpose(m: &Mutex<Vec<u32>>) -> u32 {
+    let _g = m.lock().unwrap();
+    work()
+}
+
+pub fn buffer_in_loop() -> u32 {
+    let buf = vec![1u32; 64];
+    let first = buf[0];
+    let mut total = 0;
+    for i in 0..4 {
+        total += work() * i;
+    }
+    total + first
+}
+"#;
+        let (_dir, path) = write_temp_rs(code);
+        let sysroot = toolchain::get_sysroot();
+        let handle = spawn_analysis(&path, &sysroot, PoloniusOptions::default());
+        let ws = collect_workspace(handle);
+
+        let options = LintOptions::default();
+        let found = lints::held_too_long_lints(find_function(&ws, "held"), &options);
+        assert_eq!(found.len(), 1, "{found:?}");
+        assert_eq!(found[0].code, "held-too-long");
+        assert!(
+            found[0]
+                .message
+                .starts_with("lock guard `g` is held across a call after its last use"),
+            "{}",
+            found[0].message
+        );
+        let Some(lints::Fix::InsertLine(at, line)) = &found[0].fix else {
+            panic!("no fix: {found:?}");
+        };
+        assert_eq!(line, "drop(g);");
+        assert!(code[..u32::from(*at) as usize].ends_with("let n = g.len();"));
+        // reported once, as a guard held across the call, with the fix
+        let found = lints::lints(find_function(&ws, "held"), &options);
+        assert_eq!(found.len(), 1, "{found:?}");
+        assert_eq!(found[0].code, "guard-held");
+        assert!(found[0].fix.is_some(), "{found:?}");
+
+        let found = lints::held_too_long_lints(find_function(&ws, "buffer_in_loop"), &options);
+        assert_eq!(found.len(), 1, "{found:?}");
+        assert!(
+            found[0]
+                .message
+                .starts_with("value `buf` is held across a loop"),
+            "{}",
+            found[0].message
+        );
+
+        for name in ["released", "on_purpose"] {
+            let found = lints::held_too_long_lints(find_function(&ws, name), &options);
+            assert!(found.is_empty(), "`{name}` releases in time: {found:?}");
+        }
+    }
+
+    #[test]
+    fn integration_call_summary() {
+        let _guard = acquire_lock();
+        let code = r#"
+pub struct Config {
+    names: Vec<String>,
+}
+
+fn select<'a>(
+    buf: Vec<u8>,
+    cfg: &'a Config,
+    out: &mut Vec<u8>,
+    n: usize,
+) -> std::slice::Iter<'a, String> {
+    out.extend(buf.into_iter().take(n));
+    cfg.names.iter()
+}
+
+pub fn caller(cfg: &Config) -> usize {
+    let buf = vec![1, 2, 3];
+    let mut out = Vec::new();
+    let names = select(buf, cfg, &mut out, 2);
+    names.count() + out.len()
+}
+
+fn size(buf: Vec<u8>) -> usize {
+    buf.len()
+}
+
+pub fn measure() -> usize {
+    let buf = vec![1, 2, 3];
+    size(buf)
+}
+"#;
+        let (_dir, path) = write_temp_rs(code);
+        let sysroot = toolchain::get_sysroot();
+        let handle = spawn_analysis(&path, &sysroot, PoloniusOptions::default());
+        let ws = collect_workspace(handle);
+
+        let summary = find_function(&ws, "select").summary.clone().unwrap();
+        let params: Vec<_> = summary
+            .params
+            .iter()
+            .map(|param| (param.name.as_str(), param.usage, param.returned))
+            .collect();
+        assert_eq!(
+            params,
+            vec![
+                ("buf", ParamUse::Move, false),
+                ("cfg", ParamUse::SharedBorrow, true),
+                ("out", ParamUse::MutableBorrow, false),
+                ("n", ParamUse::Copy, false),
+            ]
+        );
+
+        let callees = decoration::callee_summaries(
+            ws.0.values()
+                .flat_map(|krate| krate.0.values())
+                .flat_map(|file| &file.items),
+        );
+        let caller = find_function(&ws, "caller");
+        let pos = Loc::from(u32::try_from(code.find("select(buf").unwrap()).unwrap());
+        let (range, hover_text) = decoration::call_hover(caller, pos, &callees).unwrap();
+        assert_eq!(
+            &code[u32::from(range.from()) as usize..u32::from(range.until()) as usize],
+            "select(buf, cfg, &mut out, 2)"
+        );
+        assert_eq!(
+            hover_text,
+            "function call: moves `buf`, borrows `cfg` until the returned `Iter` is dropped, \
+             mutably borrows `out`"
+        );
+
+        // a by-value parameter the body only reads is dropped by the callee
+        let summary = find_function(&ws, "size").summary.clone().unwrap();
+        assert_eq!(summary.params[0].usage, ParamUse::Drop);
+        let measure = find_function(&ws, "measure");
+        let pos = Loc::from(u32::try_from(code.find("size(buf)").unwrap()).unwrap());
+        let (_, hover_text) = decoration::call_hover(measure, pos, &callees).unwrap();
+        assert_eq!(hover_text, "function call: drops `buf`");
+        let (_, hover_text) = decoration::call_hover(measure, pos, &HashMap::new()).unwrap();
+        assert_eq!(hover_text, "function call: moves `buf`");
+    }
+
+    #[test]
+    fn integration_closure_captures() {
+        let _guard = acquire_lock();
+        let code = r#"
+pub fn closures(c: String) -> usize {
+    let a = 1;
+    let mut b = Vec::new();
+    let mut push = |x: u32| b.push(x + a);
+    push(1);
+    let consume = move || c;
+    let n = consume().len();
+    let read = || a + 1;
+    n + read() as usize + b.len()
+}
+"#;
+        let (_dir, path) = write_temp_rs(code);
+        let sysroot = toolchain::get_sysroot();
+        let handle = spawn_analysis(&path, &sysroot, PoloniusOptions::default());
+        let ws = collect_workspace(handle);
+
+        let mut calc = CalcInlayHints::new();
+        for func in
+            ws.0.values()
+                .flat_map(|krate| krate.0.values())
+                .flat_map(|file| &file.items)
+        {
+            mir_visit(func, &mut calc);
+        }
+        let mut hints: Vec<_> = calc
+            .hints()
+            .into_iter()
+            .filter(|hint| hint.label.starts_with("Fn"))
+            .map(|hint| (u32::from(hint.pos) as usize, hint.label, hint.tooltip))
+            .collect();
+        hints.sort_by_key(|(pos, ..)| *pos);
+        let labels: Vec<_> = hints
+            .iter()
+            .map(|(pos, label, _)| (&code[..*pos], label.as_str()))
+            .map(|(before, label)| (before.lines().last().unwrap_or_default(), label))
+            .collect();
+        assert_eq!(
+            labels,
+            vec![
+                ("    let mut push = |x: u32|", "FnMut [&mut b, &a]"),
+                ("    let consume = move ||", "FnOnce [move c]"),
+                ("    let read = ||", "Fn [&a]"),
+            ]
+        );
+        assert!(
+            hints[0]
+                .2
+                .starts_with("Closure implements `FnMut` because it mutates `b`"),
+            "{}",
+            hints[0].2
+        );
+        assert!(
+            hints[1]
+                .2
+                .starts_with("Closure implements `FnOnce` because it moves `c` out"),
+            "{}",
+            hints[1].2
+        );
+    }
+
+    #[test]
+    fn integration_binding_modes() {
+        let _guard = acquire_lock();
+        let code = r#"
+pub fn bindings(x: Option<String>, mut y: Option<String>, pair: (u8, String)) -> usize {
+    let mut n = 0;
+    match &x {
+        Some(v) => n += v.len(),
+        None => {}
+    }
+    if let Some(w) = &mut y {
+        w.push('!');
+    }
+    let (a, ref b) = pair;
+    n + usize::from(a) + b.len()
+}
+"#;
+        let (_dir, path) = write_temp_rs(code);
+        let sysroot = toolchain::get_sysroot();
+        let handle = spawn_analysis(&path, &sysroot, PoloniusOptions::default());
+        let ws = collect_workspace(handle);
+        let func = find_function(&ws, "bindings");
+
+        let binding_of = |var: &str| {
+            func.decls
+                .iter()
+                .find_map(|decl| match decl {
+                    MirDecl::User { name, binding, .. } if name == var => *binding,
+                    _ => None,
+                })
+                .map(|binding| (binding.mode, binding.implicit))
+        };
+        assert_eq!(binding_of("x"), Some((BindingMode::Value, false)));
+        assert_eq!(binding_of("v"), Some((BindingMode::Ref, true)));
+        assert_eq!(binding_of("w"), Some((BindingMode::RefMut, true)));
+        assert_eq!(binding_of("a"), Some((BindingMode::Value, false)));
+        assert_eq!(binding_of("b"), Some((BindingMode::Ref, false)));
+
+        let mut calc = CalcInlayHints::new();
+        mir_visit(func, &mut calc);
+        let mut hints = calc.hints();
+        hints.sort_by_key(|hint| hint.pos);
+        let hints: Vec<_> = hints
+            .into_iter()
+            .filter(|hint| hint.label.starts_with('&'))
+            .map(|hint| (&code[u32::from(hint.pos) as usize..][..1], hint.label))
+            .collect();
+        assert_eq!(
+            hints,
+            vec![("v", "&".to_owned()), ("w", "&mut ".to_owned())]
+        );
+    }
+
+    #[test]
+    fn integration_macro_attribution() {
+        let _guard = acquire_lock();
+        let code = r#"
+macro_rules! measure {
+    ($e:expr) => {{
+        let r = &$e;
+        r.len()
+    }};
+}
+
+pub fn macros(s: String) -> usize {
+    println!("{}", s);
+    let n = measure!(s);
+    let v = vec![s];
+    v.len() + n
+}
+"#;
+        let (_dir, path) = write_temp_rs(code);
+        let sysroot = toolchain::get_sysroot();
+        let handle = spawn_analysis(&path, &sysroot, PoloniusOptions::default());
+        let ws = collect_workspace(handle);
+        let func = find_function(&ws, "macros");
+
+        let names: Vec<_> = func
+            .macro_calls
+            .iter()
+            .map(|call| call.name.as_str())
+            .collect();
+        assert!(names.contains(&"println"), "{names:?}");
+        assert!(names.contains(&"measure"), "{names:?}");
+
+        let s = func
+            .decls
+            .iter()
+            .find_map(|decl| match decl {
+                MirDecl::User { local, name, .. } if name == "s" => Some(*local),
+                _ => None,
+            })
+            .unwrap();
+        let mut calc = CalcDecos::new([s]);
+        mir_visit(func, &mut calc);
+        let decos = calc.decorations();
+        let hovers: Vec<_> = decos
+            .iter()
+            .filter_map(|deco| match deco {
+                Deco::ImmBorrow {
+                    range, hover_text, ..
+                }
+                | Deco::Move {
+                    range, hover_text, ..
+                } => Some((
+                    &code[u32::from(range.from()) as usize..u32::from(range.until()) as usize],
+                    hover_text.as_str(),
+                )),
+                _ => None,
+            })
+            .collect();
+        assert!(
+            hovers.contains(&("s", "borrowed by `println!` formatting")),
+            "{hovers:?}"
+        );
+        assert!(
+            hovers.contains(&("measure!(s)", "borrowed by `measure!`")),
+            "{hovers:?}"
+        );
+        assert!(
+            hovers
+                .iter()
+                .any(|(text, hover)| *text == "s" && hover.starts_with("variable moved")),
+            "{hovers:?}"
+        );
+    }
+
+    #[test]
+    fn integration_raw_pointers() {
+        let _guard = acquire_lock();
+        let code = r#"
+pub fn dangling() -> i32 {
+    let p;
+    {
+        let x = 1;
+        p = &raw const x;
+    }
+    unsafe { *p }
+}
+
+pub fn valid(v: &mut Vec<u8>) -> usize {
+    let mut y = 5u8;
+    let q = &mut y as *mut u8;
+    unsafe {
+        *q += 1;
+    }
+    let r = &raw const *v;
+    unsafe { (*r).len() + y as usize }
+}
+"#;
+        let (_dir, path) = write_temp_rs(code);
+        let sysroot = toolchain::get_sysroot();
+        let handle = spawn_analysis(&path, &sysroot, PoloniusOptions::default());
+        let ws = collect_workspace(handle);
+
+        let dangling = find_function(&ws, "dangling");
+        let found = lints::dangling_pointer_lints(dangling);
+        assert_eq!(found.len(), 1, "{found:?}");
+        let text = |range: Range| {
+            &code[u32::from(range.from()) as usize..u32::from(range.until()) as usize]
+        };
+        assert_eq!(text(found[0].range), "&raw const x");
+        assert_eq!(
+            found[0].message,
+            "raw pointer to `x` is still used after `x` goes out of scope"
+        );
+        assert_eq!(text(found[0].related[0].0), "}");
+        assert_eq!(text(found[0].related[1].0), "*p");
+
+        let valid = find_function(&ws, "valid");
+        assert!(lints::dangling_pointer_lints(valid).is_empty());
+        let pointees: Vec<_> = valid
+            .basic_blocks
+            .iter()
+            .flat_map(|bb| &bb.statements)
+            .filter_map(|stmt| match stmt {
+                MirStatement::Assign {
+                    rval:
+                        Some(MirRval::RawPtr {
+                            target_local,
+                            range,
+                            mutable,
+                        }),
+                    ..
+                } => Some((*target_local, text(*range), *mutable)),
+                _ => None,
+            })
+            .collect();
+        let local_of = |var: &str| {
+            valid
+                .decls
+                .iter()
+                .find_map(|decl| match decl {
+                    MirDecl::User { local, name, .. } if name == var => Some(*local),
+                    _ => None,
+                })
+                .unwrap()
+        };
+        assert_eq!(
+            pointees,
+            [
+                (local_of("y"), "&mut y", true),
+                (local_of("v"), "&raw const *v", false)
+            ]
+        );
+
+        let mut calc = CalcDecos::new([local_of("y")]);
+        mir_visit(valid, &mut calc);
+        let decos = calc.decorations();
+        assert!(
+            decos
+                .iter()
+                .any(|deco| matches!(deco, Deco::RawPtr { range, .. } if text(*range) == "&mut y")),
+            "{decos:?}"
+        );
+    }
+
+    #[test]
+    fn integration_polonius_limits() {
+        let _guard = acquire_lock();
+        let code = r#"
+pub fn limited(v: &mut Vec<u8>) -> usize {
+    let first = &v[0];
+    let n = usize::from(*first);
+    v.push(1);
+    n + v.len()
+}
+"#;
+        let (_dir, path) = write_temp_rs(code);
+        let sysroot = toolchain::get_sysroot();
+        let analyze = |polonius: PoloniusOptions| {
+            let ws = collect_workspace(spawn_analysis(&path, &sysroot, polonius));
+            let func = find_function(&ws, "limited").clone();
+            let borrows = func
+                .decls
+                .iter()
+                .find_map(|decl| match decl {
+                    MirDecl::User {
+                        name,
+                        shared_borrow,
+                        ..
+                    } if name == "v" => Some(shared_borrow.len()),
+                    _ => None,
+                })
+                .unwrap();
+            (func.precision, borrows)
+        };
+
+        let (precision, borrows) = analyze(PoloniusOptions::default());
+        assert_eq!(precision, AnalysisPrecision::Full);
+        assert!(borrows > 0);
+        let naive = PoloniusOptions {
+            algorithm: PoloniusAlgorithm::Naive,
+            ..PoloniusOptions::default()
+        };
+        assert_eq!(analyze(naive), (AnalysisPrecision::Full, borrows));
+
+        let few_facts = PoloniusOptions {
+            max_facts: 0,
+            ..PoloniusOptions::default()
+        };
+        assert_eq!(analyze(few_facts).0, AnalysisPrecision::LocationInsensitive);
+
+        let no_time = PoloniusOptions {
+            timeout_ms: 0,
+            ..PoloniusOptions::default()
+        };
+        assert_eq!(analyze(no_time), (AnalysisPrecision::TimedOut, 0));
+
+        let far_too_many_facts = PoloniusOptions {
+            max_insensitive_facts: 0,
+            ..PoloniusOptions::default()
+        };
+        assert_eq!(
+            analyze(far_too_many_facts),
+            (AnalysisPrecision::TooLarge, 0)
+        );
+    }
+
+    #[test]
+    fn integration_deadline_and_cancellation() {
+        let _guard = acquire_lock();
+        let code = r#"
+pub fn stale(v: &mut Vec<u8>) -> usize {
+    let first = &v[0];
+    usize::from(*first)
+}
+"#;
+        let (_dir, path) = write_temp_rs(code);
+        let sysroot = toolchain::get_sysroot();
+        let analyzed = |ws: &Workspace| {
+            ws.0.values()
+                .flat_map(|krate| krate.0.values())
+                .flat_map(|file| &file.items)
+                .count()
+        };
+
+        // timed out analyses are dropped, while the compilation still succeeds;
+        // a tiny function may finish within the timer resolution
+        let no_time = PoloniusOptions {
+            deadline_ms: 0,
+            ..PoloniusOptions::default()
+        };
+        let ws = collect_workspace(spawn_analysis(&path, &sysroot, no_time));
+        assert!(analyzed(&ws) <= 1);
+
+        let handle = spawn_analysis(&path, &sysroot, PoloniusOptions::default());
+        handle.cancel.cancel();
+        let ws = collect_workspace(handle);
+        assert_eq!(analyzed(&ws), 0);
+
+        let ws = collect_workspace(spawn_analysis(&path, &sysroot, PoloniusOptions::default()));
+        assert!(!find_function(&ws, "stale").decls.is_empty());
+    }
 }
@@ -16,7 +16,7 @@ pub fn index_to_line_char(s: &str, idx: Loc) -> (u32, u32) {
             line += 1;
             col = 0;
         } else if c != '\r' {
-            col += 1;
+            col += utf16_len(c);
         }
     }
     (0, 0)
@@ -37,8 +37,19 @@ pub fn line_char_to_index(s: &str, mut line: u32, char: u32) -> u32 {
             line -= 1;
             col = 0;
         } else if c != '\r' {
-            col += 1;
+            col += utf16_len(c);
         }
     }
     0
 }
+
+/// Length of `c` in UTF-16 code units, in which LSP clients count columns
+fn utf16_len(c: char) -> u32 {
+    u32::try_from(c.len_utf16()).unwrap_or(1)
+}
+
+/// Column in UTF-16 code units of the character `column` of `line`
+#[must_use]
+pub fn utf16_column(line: &str, column: usize) -> u32 {
+    line.chars().take(column).map(utf16_len).sum()
+}
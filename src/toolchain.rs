@@ -17,6 +17,32 @@ const COMPILE_TIME_SYSROOT: &str = env!("COMPILE_TIME_SYSROOT");
 /// Environment variable for cache directory path
 pub const CACHE_DIR_ENV: &str = "FERROUS_OWL_CACHE_DIR";
 
+/// Environment variable for the borrow check options, as JSON
+pub const POLONIUS_OPTIONS_ENV: &str = "FERROUS_OWL_POLONIUS_OPTIONS";
+
+/// Environment variable for the file holding the id of the current analysis
+/// run; compiler processes stop analyzing once it holds another id
+pub const RUN_FILE_ENV: &str = "FERROUS_OWL_RUN_FILE";
+
+/// Environment variable for the id of the analysis run a compiler belongs to
+pub const RUN_ID_ENV: &str = "FERROUS_OWL_RUN_ID";
+
+/// Environment variable for the file open in the editor, whose functions are
+/// analyzed first
+pub const ACTIVE_FILE_ENV: &str = "FERROUS_OWL_ACTIVE_FILE";
+
+/// Environment variable for the number of threads analyzing functions in a
+/// compiler process
+pub const THREADS_ENV: &str = "FERROUS_OWL_THREADS";
+
+/// Environment variable for the paths whose functions are not analyzed, as a
+/// JSON array
+pub const EXCLUDE_ENV: &str = "FERROUS_OWL_EXCLUDE";
+
+/// Environment variable for the function whose origin subset graph is
+/// computed, by its path or name
+pub const ORIGIN_GRAPH_ENV: &str = "FERROUS_OWL_ORIGIN_GRAPH";
+
 /// Returns the Rust sysroot path for the compiler.
 ///
 /// Resolution order:
@@ -88,6 +114,25 @@ pub fn setup_cargo_command() -> TokioCommand {
     command
 }
 
+/// Creates a command running the current binary as the compiler of a single
+/// crate, outside of cargo; the crate is analyzed if `analyze` is set and only
+/// compiled otherwise.
+#[must_use]
+pub fn setup_rustc_command(analyze: bool) -> TokioCommand {
+    let exe_path = current_exe_path();
+    let mut command = TokioCommand::new(&exe_path);
+    if analyze {
+        // the wrapper mode of cargo, where the compiler is the first argument
+        command.arg(&exe_path);
+    }
+    command
+        .env("FERROUS_OWL_AS_RUSTC", "1")
+        .env("RUSTC_BOOTSTRAP", "1");
+
+    prepend_library_path(&mut command, &get_sysroot());
+    command
+}
+
 fn prepend_library_path(command: &mut TokioCommand, sysroot: &Path) {
     let lib_dir = sysroot.join("lib");
 